@@ -36,7 +36,7 @@ CREATE TABLE IF NOT EXISTS TrainingDays (
 -- Table for individual exercises
 CREATE TABLE IF NOT EXISTS Exercises (
     exercise_id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
-    exercise_name VARCHAR(255) UNIQUE NOT NULL,
+    exercise_name VARCHAR(255) NOT NULL,
     exercise_description VARCHAR(1000),
     created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
     updated_at TIMESTAMP WITH TIME ZONE
@@ -80,7 +80,7 @@ CREATE TABLE IF NOT EXISTS SessionExercisePerformance (
     performance_id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
     session_id UUID REFERENCES Sessions(session_id),
     exercise_id UUID REFERENCES Exercises(exercise_id),
-    weight FLOAT4,
+    weight NUMERIC(8, 3),
     reps SMALLINT,
     set_number SMALLINT,
     rir SMALLINT,
@@ -97,8 +97,724 @@ BEFORE DELETE ON SessionExercisePerformance
 FOR EACH ROW
 EXECUTE FUNCTION update_set_numbers();
 
+-- Keep updated_at current on every write so it can double as the row version (ETag)
+CREATE OR REPLACE FUNCTION set_updated_at()
+RETURNS TRIGGER AS $$
+BEGIN
+    NEW.updated_at = clock_timestamp();
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS set_updated_at_trigger ON Users;
+CREATE TRIGGER set_updated_at_trigger BEFORE UPDATE ON Users
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+DROP TRIGGER IF EXISTS set_updated_at_trigger ON Routines;
+CREATE TRIGGER set_updated_at_trigger BEFORE UPDATE ON Routines
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+DROP TRIGGER IF EXISTS set_updated_at_trigger ON TrainingDays;
+CREATE TRIGGER set_updated_at_trigger BEFORE UPDATE ON TrainingDays
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+DROP TRIGGER IF EXISTS set_updated_at_trigger ON Exercises;
+CREATE TRIGGER set_updated_at_trigger BEFORE UPDATE ON Exercises
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+DROP TRIGGER IF EXISTS set_updated_at_trigger ON ExerciseTrainingDayLink;
+CREATE TRIGGER set_updated_at_trigger BEFORE UPDATE ON ExerciseTrainingDayLink
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+DROP TRIGGER IF EXISTS set_updated_at_trigger ON Sessions;
+CREATE TRIGGER set_updated_at_trigger BEFORE UPDATE ON Sessions
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+-- The effective load is derived, recomputing it doesn't make the set a new version
+DROP TRIGGER IF EXISTS set_updated_at_trigger ON SessionExercisePerformance;
+CREATE TRIGGER set_updated_at_trigger BEFORE UPDATE ON SessionExercisePerformance
+FOR EACH ROW WHEN (
+    (to_jsonb(OLD) - 'effective_load' - 'updated_at') IS DISTINCT FROM
+    (to_jsonb(NEW) - 'effective_load' - 'updated_at')
+)
+EXECUTE FUNCTION set_updated_at();
+
+-- Responses to POST requests that carried an Idempotency-Key, replayed when the key comes back.
+-- Only signed-in requests are kept, keyed by their user.
+CREATE TABLE IF NOT EXISTS IdempotencyKeys (
+    user_id UUID NOT NULL,
+    idempotency_key VARCHAR(255) NOT NULL,
+    request_method VARCHAR(10) NOT NULL,
+    request_path VARCHAR(1000) NOT NULL,
+    request_hash VARCHAR(64) NOT NULL,
+    -- NULL while the first request with this key is still being handled
+    status_code SMALLINT,
+    content_type VARCHAR(255),
+    response_body BYTEA,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, idempotency_key)
+);
+
+-- Offline sync: the winning write per field (last writer wins, ties broken by mutation_id)
+CREATE TABLE IF NOT EXISTS SyncFieldVersions (
+    entity_type VARCHAR(50) NOT NULL,
+    entity_id UUID NOT NULL,
+    field VARCHAR(100) NOT NULL,
+    changed_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    mutation_id UUID NOT NULL,
+    PRIMARY KEY (entity_type, entity_id, field)
+);
+
+-- Per-user change feed, pulled in the order of position (see below)
+CREATE TABLE IF NOT EXISTS ChangeLog (
+    change_id BIGSERIAL PRIMARY KEY,
+    user_id UUID NOT NULL,
+    entity_type VARCHAR(50) NOT NULL,
+    entity_id UUID NOT NULL,
+    operation VARCHAR(10) NOT NULL,
+    data JSONB,
+    changed_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    received_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS changelog_user_cursor_idx ON ChangeLog (user_id, change_id);
+
+-- Sets that beat the best estimated 1RM (Epley) logged for their exercise
+CREATE TABLE IF NOT EXISTS PersonalRecords (
+    record_id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    exercise_id UUID REFERENCES Exercises(exercise_id) ON DELETE CASCADE,
+    session_id UUID REFERENCES Sessions(session_id) ON DELETE CASCADE,
+    performance_id UUID UNIQUE REFERENCES SessionExercisePerformance(performance_id) ON DELETE CASCADE,
+    weight NUMERIC(8, 3) NOT NULL,
+    reps SMALLINT NOT NULL,
+    estimated_one_rep_max NUMERIC(8, 3) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS personalrecords_exercise_idx ON PersonalRecords (exercise_id, estimated_one_rep_max DESC);
+
+-- Outgoing webhooks, `events` holds activity event types and an empty array means all of them
+CREATE TABLE IF NOT EXISTS Webhooks (
+    webhook_id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    user_id UUID NOT NULL,
+    url VARCHAR(2000) NOT NULL,
+    secret VARCHAR(255) NOT NULL,
+    events TEXT[] NOT NULL DEFAULT '{}',
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS webhooks_user_idx ON Webhooks (user_id);
+
+DROP TRIGGER IF EXISTS set_updated_at_trigger ON Webhooks;
+CREATE TRIGGER set_updated_at_trigger BEFORE UPDATE ON Webhooks
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+-- One row per event sent to a webhook, updated after every attempt
+CREATE TABLE IF NOT EXISTS WebhookDeliveries (
+    delivery_id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    webhook_id UUID NOT NULL REFERENCES Webhooks(webhook_id) ON DELETE CASCADE,
+    event_type VARCHAR(50) NOT NULL,
+    payload JSONB NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    attempt_count INTEGER NOT NULL DEFAULT 0,
+    response_status SMALLINT,
+    last_error VARCHAR(1000),
+    next_attempt_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    delivered_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS webhookdeliveries_webhook_idx ON WebhookDeliveries (webhook_id, created_at DESC);
+
+-- Owners. Training days, links and sets belong to the owner of their routine or session;
+-- rows created before owners existed have none.
+ALTER TABLE Routines ADD COLUMN IF NOT EXISTS user_id UUID REFERENCES Users(user_id);
+ALTER TABLE Sessions ADD COLUMN IF NOT EXISTS user_id UUID REFERENCES Users(user_id);
+
+CREATE INDEX IF NOT EXISTS routines_user_idx ON Routines (user_id);
+CREATE INDEX IF NOT EXISTS sessions_user_idx ON Sessions (user_id);
+
+-- admin, coach or athlete. There is no endpoint to create the first admin, promote one by hand:
+-- UPDATE Users SET role = 'admin' WHERE username = '...';
+ALTER TABLE Users ADD COLUMN IF NOT EXISTS role VARCHAR(20) NOT NULL DEFAULT 'athlete'
+    CHECK (role IN ('admin', 'coach', 'athlete'));
+
+-- Coach-athlete links. A coach invites an athlete, the link is pending until the athlete accepts,
+-- and the flags say what the coach may do with the athlete's data. Declining or revoking deletes it.
+CREATE TABLE IF NOT EXISTS CoachAthletes (
+    link_id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    coach_id UUID NOT NULL REFERENCES Users(user_id) ON DELETE CASCADE,
+    athlete_id UUID NOT NULL REFERENCES Users(user_id) ON DELETE CASCADE,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'accepted')),
+    can_read_history BOOLEAN NOT NULL DEFAULT FALSE,
+    can_edit_routines BOOLEAN NOT NULL DEFAULT FALSE,
+    can_comment BOOLEAN NOT NULL DEFAULT FALSE,
+    accepted_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE,
+    CONSTRAINT unique_coach_athlete UNIQUE (coach_id, athlete_id),
+    CONSTRAINT coach_is_not_athlete CHECK (coach_id <> athlete_id)
+);
+
+CREATE INDEX IF NOT EXISTS coachathletes_athlete_idx ON CoachAthletes (athlete_id);
+
+DROP TRIGGER IF EXISTS set_updated_at_trigger ON CoachAthletes;
+CREATE TRIGGER set_updated_at_trigger BEFORE UPDATE ON CoachAthletes
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+-- Whether `actor` may use `permission` on data owned by `owner`, checked by every repository query.
+-- The owner always may; an accepted coach may when granted:
+--   read_routines  routines, days and their exercises (can_read_history or can_edit_routines)
+--   edit_routines  creating and changing them (can_edit_routines)
+--   read_history   sessions, sets and PRs (can_read_history)
+--   comment        commenting (can_comment)
+-- Anything else, e.g. log_sessions, is the owner's alone. Rows without an owner predate
+-- ownership, only admins may touch them.
+CREATE OR REPLACE FUNCTION has_access(actor UUID, owner UUID, permission TEXT)
+RETURNS BOOLEAN AS $$
+    SELECT CASE WHEN owner IS NULL THEN EXISTS (
+        SELECT 1 FROM Users WHERE user_id = actor AND role = 'admin'
+    ) ELSE owner = actor OR EXISTS (
+        SELECT 1
+        FROM CoachAthletes
+        WHERE coach_id = actor
+        AND athlete_id = owner
+        AND status = 'accepted'
+        AND CASE permission
+            WHEN 'read_routines' THEN can_read_history OR can_edit_routines
+            WHEN 'edit_routines' THEN can_edit_routines
+            WHEN 'read_history' THEN can_read_history
+            WHEN 'comment' THEN can_comment
+            ELSE FALSE
+        END
+    ) END
+$$ LANGUAGE sql STABLE;
+
+-- Owners of the rows that don't carry one themselves
+CREATE OR REPLACE FUNCTION routine_owner(routine UUID)
+RETURNS UUID AS $$
+    SELECT user_id FROM Routines WHERE routine_id = routine
+$$ LANGUAGE sql STABLE;
+
+CREATE OR REPLACE FUNCTION day_owner(day UUID)
+RETURNS UUID AS $$
+    SELECT r.user_id
+    FROM TrainingDays td
+    JOIN Routines r ON r.routine_id = td.routine_id
+    WHERE td.day_id = day
+$$ LANGUAGE sql STABLE;
+
+CREATE OR REPLACE FUNCTION session_owner(session UUID)
+RETURNS UUID AS $$
+    SELECT user_id FROM Sessions WHERE session_id = session
+$$ LANGUAGE sql STABLE;
+
+-- Comment threads on a session, optionally narrowed to one exercise in it or one set.
+-- Replies point at the comment they answer; deleting a comment takes its replies with it.
+CREATE TABLE IF NOT EXISTS Comments (
+    comment_id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    session_id UUID NOT NULL REFERENCES Sessions(session_id) ON DELETE CASCADE,
+    exercise_id UUID REFERENCES Exercises(exercise_id) ON DELETE CASCADE,
+    performance_id UUID REFERENCES SessionExercisePerformance(performance_id) ON DELETE CASCADE,
+    parent_id UUID REFERENCES Comments(comment_id) ON DELETE CASCADE,
+    author_id UUID NOT NULL REFERENCES Users(user_id) ON DELETE CASCADE,
+    body TEXT NOT NULL CHECK (length(body) BETWEEN 1 AND 5000),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX IF NOT EXISTS comments_session_idx ON Comments (session_id, created_at);
+CREATE INDEX IF NOT EXISTS comments_author_idx ON Comments (author_id);
+
+DROP TRIGGER IF EXISTS set_updated_at_trigger ON Comments;
+CREATE TRIGGER set_updated_at_trigger BEFORE UPDATE ON Comments
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+-- The lifter's own notes on sessions, sets and exercises on a training day, searchable as
+-- full text. Unlike comments they belong to the row and go wherever it goes.
+ALTER TABLE Sessions ADD COLUMN IF NOT EXISTS notes TEXT;
+ALTER TABLE SessionExercisePerformance ADD COLUMN IF NOT EXISTS notes TEXT;
+ALTER TABLE ExerciseTrainingDayLink ADD COLUMN IF NOT EXISTS notes TEXT;
+
+ALTER TABLE Sessions ADD COLUMN IF NOT EXISTS notes_search tsvector
+    GENERATED ALWAYS AS (to_tsvector('english', COALESCE(notes, ''))) STORED;
+ALTER TABLE SessionExercisePerformance ADD COLUMN IF NOT EXISTS notes_search tsvector
+    GENERATED ALWAYS AS (to_tsvector('english', COALESCE(notes, ''))) STORED;
+ALTER TABLE ExerciseTrainingDayLink ADD COLUMN IF NOT EXISTS notes_search tsvector
+    GENERATED ALWAYS AS (to_tsvector('english', COALESCE(notes, ''))) STORED;
+
+CREATE INDEX IF NOT EXISTS sessions_notes_search_idx ON Sessions USING GIN (notes_search);
+CREATE INDEX IF NOT EXISTS sessionexerciseperformance_notes_search_idx
+    ON SessionExercisePerformance USING GIN (notes_search);
+CREATE INDEX IF NOT EXISTS exercisetrainingdaylink_notes_search_idx
+    ON ExerciseTrainingDayLink USING GIN (notes_search);
+
+-- Finds the last set note on an exercise when it comes up again
+CREATE INDEX IF NOT EXISTS sessionexerciseperformance_exercise_notes_idx
+    ON SessionExercisePerformance (exercise_id, created_at DESC) WHERE notes IS NOT NULL;
+
+-- Weights are stored in kilograms. This is the unit a user types and reads them in, the API
+-- converts at the edge and rounds to the nearest loadable increment.
+ALTER TABLE Users ADD COLUMN IF NOT EXISTS weight_unit VARCHAR(2) NOT NULL DEFAULT 'kg'
+    CHECK (weight_unit IN ('kg', 'lb'));
+
+-- Weights used to be FLOAT4, which stored 102.5 as 102.49999. Databases from before are converted
+-- once; a float4 carries about 7 significant digits so rounding to grams recovers what was typed.
+DO $$
+BEGIN
+    IF (SELECT data_type FROM information_schema.columns
+        WHERE table_name = 'sessionexerciseperformance' AND column_name = 'weight') = 'real' THEN
+        ALTER TABLE SessionExercisePerformance
+            ALTER COLUMN weight TYPE NUMERIC(8, 3) USING ROUND(weight::numeric, 3);
+    END IF;
+    IF (SELECT data_type FROM information_schema.columns
+        WHERE table_name = 'personalrecords' AND column_name = 'weight') = 'real' THEN
+        ALTER TABLE PersonalRecords
+            ALTER COLUMN weight TYPE NUMERIC(8, 3) USING ROUND(weight::numeric, 3),
+            ALTER COLUMN estimated_one_rep_max TYPE NUMERIC(8, 3)
+                USING ROUND(estimated_one_rep_max::numeric, 3);
+    END IF;
+END;
+$$;
+
+-- Bodyweight, one entry per user and day. Bodyweight exercises take their load from it.
+CREATE TABLE IF NOT EXISTS BodyMeasurements (
+    measurement_id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES Users(user_id) ON DELETE CASCADE,
+    measured_on DATE NOT NULL DEFAULT CURRENT_DATE,
+    bodyweight NUMERIC(8, 3) CHECK (bodyweight > 0),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE,
+    CONSTRAINT unique_measurement_day UNIQUE (user_id, measured_on)
+);
+
+DROP TRIGGER IF EXISTS set_updated_at_trigger ON BodyMeasurements;
+CREATE TRIGGER set_updated_at_trigger BEFORE UPDATE ON BodyMeasurements
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+-- The latest bodyweight logged on or before a day
+CREATE OR REPLACE FUNCTION bodyweight_as_of(person UUID, day DATE)
+RETURNS NUMERIC AS $$
+    SELECT bodyweight
+    FROM BodyMeasurements
+    WHERE user_id = person AND measured_on <= day AND bodyweight IS NOT NULL
+    ORDER BY measured_on DESC
+    LIMIT 1
+$$ LANGUAGE sql STABLE;
+
+-- How a set's weight turns into the load lifted: the bar itself (external), bodyweight alone,
+-- bodyweight plus the weight (a dip belt), or bodyweight minus the weight (an assisted machine).
+ALTER TABLE Exercises ADD COLUMN IF NOT EXISTS load_type VARCHAR(30) NOT NULL DEFAULT 'external'
+    CHECK (load_type IN ('external', 'bodyweight', 'bodyweight_plus_load', 'assisted'));
+
+-- The load e1RM, PRs and volume are computed from. Fixed when the set is written, with the
+-- bodyweight logged as of the session's day, so later weigh-ins don't rewrite history.
+ALTER TABLE SessionExercisePerformance ADD COLUMN IF NOT EXISTS effective_load NUMERIC(8, 3);
+UPDATE SessionExercisePerformance SET effective_load = weight
+WHERE effective_load IS NULL AND weight IS NOT NULL;
 
+-- What a set of `weight` on `exercise` in `session` puts on the lifter
+CREATE OR REPLACE FUNCTION effective_load_of(exercise UUID, session UUID, weight NUMERIC)
+RETURNS NUMERIC AS $$
+DECLARE
+    exercise_load_type VARCHAR(30);
+    lifter_bodyweight NUMERIC;
+BEGIN
+    SELECT load_type INTO exercise_load_type FROM Exercises WHERE exercise_id = exercise;
+    IF exercise_load_type IS NULL OR exercise_load_type = 'external' THEN
+        RETURN weight;
+    END IF;
+
+    -- without a logged bodyweight only the added load counts
+    SELECT bodyweight_as_of(user_id, created_at::date) INTO lifter_bodyweight
+    FROM Sessions
+    WHERE session_id = session;
+    lifter_bodyweight = COALESCE(lifter_bodyweight, 0);
+
+    RETURN CASE exercise_load_type
+        WHEN 'bodyweight' THEN lifter_bodyweight
+        WHEN 'bodyweight_plus_load' THEN lifter_bodyweight + COALESCE(weight, 0)
+        WHEN 'assisted' THEN GREATEST(lifter_bodyweight - COALESCE(weight, 0), 0)
+    END;
+END;
+$$ LANGUAGE plpgsql STABLE;
+
+CREATE OR REPLACE FUNCTION set_effective_load()
+RETURNS TRIGGER AS $$
+BEGIN
+    NEW.effective_load = effective_load_of(NEW.exercise_id, NEW.session_id, NEW.weight);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS set_effective_load_trigger ON SessionExercisePerformance;
+CREATE TRIGGER set_effective_load_trigger
+BEFORE INSERT OR UPDATE OF weight, exercise_id ON SessionExercisePerformance
+FOR EACH ROW EXECUTE FUNCTION set_effective_load();
+
+-- The sets of an exercise that were PRs when logged: a higher estimated 1RM (Epley, as in
+-- `estimated_one_rep_max`) than every earlier set of the exercise by the same lifter
+CREATE OR REPLACE FUNCTION personal_record_sets(exercise UUID)
+RETURNS TABLE (
+    session_id UUID,
+    performance_id UUID,
+    weight NUMERIC,
+    reps SMALLINT,
+    estimated_one_rep_max NUMERIC,
+    created_at TIMESTAMP WITH TIME ZONE
+) AS $$
+    SELECT session_id, performance_id, effective_load, reps, estimated_one_rep_max, created_at
+    FROM (
+        SELECT
+            sets.*,
+            MAX(sets.estimated_one_rep_max) OVER (
+                PARTITION BY sets.lifter
+                ORDER BY sets.logged_at, sets.set_number
+                ROWS BETWEEN UNBOUNDED PRECEDING AND 1 PRECEDING
+            ) AS best_before
+        FROM (
+            SELECT
+                s.user_id AS lifter,
+                s.created_at AS logged_at,
+                sep.session_id,
+                sep.performance_id,
+                sep.set_number,
+                sep.effective_load,
+                sep.reps,
+                sep.created_at,
+                ROUND(CASE WHEN sep.reps <= 1 THEN sep.effective_load
+                    ELSE sep.effective_load * (30 + sep.reps) / 30 END, 3) AS estimated_one_rep_max
+            FROM SessionExercisePerformance sep
+            JOIN Sessions s ON s.session_id = sep.session_id
+            WHERE sep.exercise_id = exercise AND sep.effective_load > 0 AND sep.reps > 0
+        ) sets
+    ) ranked
+    WHERE best_before IS NULL OR estimated_one_rep_max > best_before
+$$ LANGUAGE sql STABLE;
+
+-- Brings an exercise's PRs in line with its sets, keeping the records that still stand
+CREATE OR REPLACE FUNCTION recompute_personal_records(exercise UUID)
+RETURNS VOID AS $$
+BEGIN
+    DELETE FROM PersonalRecords pr
+    WHERE pr.exercise_id = exercise
+        AND pr.performance_id NOT IN (SELECT prs.performance_id FROM personal_record_sets(exercise) prs);
+
+    INSERT INTO PersonalRecords (exercise_id, session_id, performance_id, weight, reps, estimated_one_rep_max, created_at)
+    SELECT exercise, prs.session_id, prs.performance_id, prs.weight, prs.reps, prs.estimated_one_rep_max, prs.created_at
+    FROM personal_record_sets(exercise) prs
+    ON CONFLICT (performance_id) DO UPDATE SET
+        exercise_id = EXCLUDED.exercise_id,
+        weight = EXCLUDED.weight,
+        reps = EXCLUDED.reps,
+        estimated_one_rep_max = EXCLUDED.estimated_one_rep_max;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Changing how an exercise is loaded recomputes the sets logged with it, and the PRs they make.
+-- Only the effective load is written, the sets themselves stay the version they were.
+CREATE OR REPLACE FUNCTION recompute_effective_loads()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE SessionExercisePerformance
+    SET effective_load = effective_load_of(exercise_id, session_id, weight)
+    WHERE exercise_id = NEW.exercise_id
+        AND effective_load IS DISTINCT FROM effective_load_of(exercise_id, session_id, weight);
+    PERFORM recompute_personal_records(NEW.exercise_id);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS recompute_effective_loads_trigger ON Exercises;
+CREATE TRIGGER recompute_effective_loads_trigger
+AFTER UPDATE OF load_type ON Exercises
+FOR EACH ROW WHEN (OLD.load_type IS DISTINCT FROM NEW.load_type)
+EXECUTE FUNCTION recompute_effective_loads();
+
+-- The metrics an exercise's sets record, lifts track reps and weight. Sets fill in the tracked
+-- ones and leave the others empty, reps and weight at zero.
+ALTER TABLE Exercises ADD COLUMN IF NOT EXISTS tracked_metrics TEXT[] NOT NULL
+    DEFAULT ARRAY['reps', 'weight']
+    CHECK (
+        cardinality(tracked_metrics) > 0
+        AND tracked_metrics <@ ARRAY['reps', 'weight', 'duration', 'distance', 'calories', 'heart_rate']
+    );
+
+ALTER TABLE SessionExercisePerformance
+    ADD COLUMN IF NOT EXISTS duration_seconds INTEGER CHECK (duration_seconds > 0),
+    ADD COLUMN IF NOT EXISTS distance_meters NUMERIC(10, 2) CHECK (distance_meters > 0),
+    ADD COLUMN IF NOT EXISTS calories INTEGER CHECK (calories >= 0),
+    -- average beats per minute over the set
+    ADD COLUMN IF NOT EXISTS heart_rate SMALLINT CHECK (heart_rate BETWEEN 20 AND 250);
+
+-- Body fat and tape measurements next to bodyweight, tape in centimeters
+ALTER TABLE BodyMeasurements
+    ADD COLUMN IF NOT EXISTS body_fat_percent NUMERIC(4, 1)
+        CHECK (body_fat_percent > 0 AND body_fat_percent < 100),
+    ADD COLUMN IF NOT EXISTS waist_cm NUMERIC(5, 1) CHECK (waist_cm > 0),
+    ADD COLUMN IF NOT EXISTS chest_cm NUMERIC(5, 1) CHECK (chest_cm > 0),
+    ADD COLUMN IF NOT EXISTS arms_cm NUMERIC(5, 1) CHECK (arms_cm > 0),
+    ADD COLUMN IF NOT EXISTS thighs_cm NUMERIC(5, 1) CHECK (thighs_cm > 0);
+
+CREATE INDEX IF NOT EXISTS body_measurements_user_day_idx
+    ON BodyMeasurements (user_id, measured_on DESC);
+
+-- Catalogue attributes, for filtering and finding substitutes
+ALTER TABLE Exercises
+    ADD COLUMN IF NOT EXISTS equipment VARCHAR(20) NOT NULL DEFAULT 'other'
+        CHECK (equipment IN ('barbell', 'dumbbell', 'kettlebell', 'cable', 'machine', 'band',
+            'bodyweight', 'other')),
+    ADD COLUMN IF NOT EXISTS movement_pattern VARCHAR(20) NOT NULL DEFAULT 'other'
+        CHECK (movement_pattern IN ('squat', 'hinge', 'lunge', 'horizontal_push', 'vertical_push',
+            'horizontal_pull', 'vertical_pull', 'carry', 'core', 'isolation', 'conditioning',
+            'other')),
+    ADD COLUMN IF NOT EXISTS unilateral BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS primary_muscles TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN IF NOT EXISTS secondary_muscles TEXT[] NOT NULL DEFAULT '{}',
+    -- other names the exercise goes by, searched along with the name
+    ADD COLUMN IF NOT EXISTS aliases TEXT[] NOT NULL DEFAULT '{}';
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_constraint WHERE conname = 'exercise_muscles_known') THEN
+        ALTER TABLE Exercises ADD CONSTRAINT exercise_muscles_known CHECK (
+            primary_muscles || secondary_muscles <@ ARRAY['chest', 'front_delts', 'side_delts',
+                'rear_delts', 'biceps', 'triceps', 'forearms', 'lats', 'upper_back', 'traps',
+                'lower_back', 'abs', 'obliques', 'glutes', 'quads', 'hamstrings', 'adductors',
+                'abductors', 'calves', 'neck']
+        );
+    END IF;
+END;
+$$;
+
+CREATE INDEX IF NOT EXISTS exercises_primary_muscles_idx ON Exercises USING GIN (primary_muscles);
+CREATE INDEX IF NOT EXISTS exercises_secondary_muscles_idx ON Exercises USING GIN (secondary_muscles);
+
+-- Fuzzy exercise search, `shared::search` mirrors these in Rust
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
 
+-- Lowercase words separated by single spaces, punctuation dropped
+CREATE OR REPLACE FUNCTION search_normalize(t TEXT)
+RETURNS TEXT AS $$
+    SELECT btrim(regexp_replace(lower(t), '[^[:alnum:]]+', ' ', 'g'))
+$$ LANGUAGE sql IMMUTABLE;
 
+-- How well a name or alias matches a normalized query: trigram similarity, also with the spaces
+-- squeezed out, plus a boost for a prefix and one for an exact match
+CREATE OR REPLACE FUNCTION search_text_score(t TEXT, q TEXT)
+RETURNS REAL AS $$
+    SELECT CASE WHEN q = '' THEN 0 ELSE
+        GREATEST(similarity(n, q), similarity(replace(n, ' ', ''), replace(q, ' ', '')))
+        + CASE WHEN left(n, length(q)) = q THEN 0.25 ELSE 0 END
+        + CASE WHEN n = q THEN 0.25 ELSE 0 END
+    END::real
+    FROM (SELECT search_normalize(t) AS n) normalized
+$$ LANGUAGE sql IMMUTABLE;
+
+-- The built-in library, seeded at startup from db/exercise_library.json, and the exercises
+-- users add for themselves. Exercises from before owners existed become library exercises.
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_name = 'exercises' AND column_name = 'builtin'
+    ) THEN
+        ALTER TABLE Exercises ADD COLUMN builtin BOOLEAN NOT NULL DEFAULT FALSE;
+        UPDATE Exercises SET builtin = TRUE;
+    END IF;
+END;
+$$;
+
+-- A deleted owner's exercises that others still use are kept, in nobody's list
+ALTER TABLE Exercises
+    ADD COLUMN IF NOT EXISTS owner_id UUID REFERENCES Users(user_id) ON DELETE SET NULL;
+
+-- Names are unique within the library and within each user's own exercises, a user's exercise
+-- with a library exercise's name shadows it for them
+ALTER TABLE Exercises DROP CONSTRAINT IF EXISTS exercises_exercise_name_key;
+CREATE UNIQUE INDEX IF NOT EXISTS exercises_library_name_idx
+    ON Exercises (exercise_name) WHERE builtin;
+CREATE UNIQUE INDEX IF NOT EXISTS exercises_owner_name_idx
+    ON Exercises (owner_id, exercise_name) WHERE owner_id IS NOT NULL;
+
+-- Keys are only kept for signed-in users, anonymous ones couldn't be told apart and stored
+-- sign-up responses
+DELETE FROM IdempotencyKeys WHERE user_id = '00000000-0000-0000-0000-000000000000';
+ALTER TABLE IdempotencyKeys ALTER COLUMN user_id DROP DEFAULT;
+
+-- Every write to a synced row lands in its owner's change feed and bumps the versions of the
+-- fields it changed, whichever endpoint made it. Sync push sets sync.changed_at and
+-- sync.mutation_id for the rows it writes, anything else is versioned at the time it happens.
+CREATE OR REPLACE FUNCTION sync_changed_at()
+RETURNS TIMESTAMP WITH TIME ZONE AS $$
+    SELECT COALESCE(NULLIF(current_setting('sync.changed_at', true), '')::timestamptz, clock_timestamp())
+$$ LANGUAGE sql VOLATILE;
+
+CREATE OR REPLACE FUNCTION sync_log_change(owner UUID, entity TEXT, id UUID, op TEXT, data JSONB, fields TEXT[])
+RETURNS VOID AS $$
+BEGIN
+    INSERT INTO SyncFieldVersions (entity_type, entity_id, field, changed_at, mutation_id)
+    SELECT entity, id, field, sync_changed_at(),
+        COALESCE(NULLIF(current_setting('sync.mutation_id', true), '')::uuid, uuid_generate_v4())
+    FROM unnest(fields) AS field
+    ON CONFLICT (entity_type, entity_id, field) DO UPDATE SET
+        changed_at = EXCLUDED.changed_at,
+        mutation_id = EXCLUDED.mutation_id;
+
+    -- Rows nobody owns have no feed to go to
+    IF owner IS NOT NULL THEN
+        INSERT INTO ChangeLog (user_id, entity_type, entity_id, operation, data, changed_at)
+        VALUES (owner, entity, id, op, data, sync_changed_at());
+    END IF;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION sync_routine_change()
+RETURNS TRIGGER AS $$
+DECLARE
+    changed TEXT[];
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM sync_log_change(OLD.user_id, 'routine', OLD.routine_id, 'delete', NULL, ARRAY['_deleted']);
+        RETURN OLD;
+    END IF;
+    changed := ARRAY(
+        SELECT field FROM (VALUES
+            ('name', TG_OP = 'INSERT' OR OLD.name IS DISTINCT FROM NEW.name),
+            ('description', TG_OP = 'INSERT' OR OLD.description IS DISTINCT FROM NEW.description),
+            ('is_active', TG_OP = 'INSERT' OR OLD.is_active IS DISTINCT FROM NEW.is_active)
+        ) AS fields (field, differs)
+        WHERE differs
+    );
+    IF cardinality(changed) = 0 THEN
+        RETURN NEW;
+    END IF;
+    PERFORM sync_log_change(NEW.user_id, 'routine', NEW.routine_id, 'upsert', jsonb_build_object(
+        'routine_id', NEW.routine_id,
+        'name', NEW.name,
+        'description', COALESCE(NEW.description, ''),
+        'is_active', COALESCE(NEW.is_active, false)
+    ), changed);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS sync_change_trigger ON Routines;
+CREATE TRIGGER sync_change_trigger AFTER INSERT OR UPDATE OR DELETE ON Routines
+FOR EACH ROW EXECUTE FUNCTION sync_routine_change();
+
+CREATE OR REPLACE FUNCTION sync_session_change()
+RETURNS TRIGGER AS $$
+DECLARE
+    changed TEXT[];
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM sync_log_change(OLD.user_id, 'session', OLD.session_id, 'delete', NULL, ARRAY['_deleted']);
+        RETURN OLD;
+    END IF;
+    changed := ARRAY(
+        SELECT field FROM (VALUES
+            ('day_id', TG_OP = 'INSERT' OR OLD.day_id IS DISTINCT FROM NEW.day_id),
+            ('day_name', TG_OP = 'INSERT' OR OLD.day_name IS DISTINCT FROM NEW.day_name),
+            ('in_progress', TG_OP = 'INSERT' OR OLD.in_progress IS DISTINCT FROM NEW.in_progress),
+            ('created_at', TG_OP = 'INSERT' OR OLD.created_at IS DISTINCT FROM NEW.created_at)
+        ) AS fields (field, differs)
+        WHERE differs
+    );
+    IF cardinality(changed) = 0 THEN
+        RETURN NEW;
+    END IF;
+    PERFORM sync_log_change(NEW.user_id, 'session', NEW.session_id, 'upsert', jsonb_build_object(
+        'session_id', NEW.session_id,
+        'day_id', NEW.day_id,
+        'day_name', NEW.day_name,
+        'in_progress', COALESCE(NEW.in_progress, false),
+        'created_at', NEW.created_at
+    ), changed);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS sync_change_trigger ON Sessions;
+CREATE TRIGGER sync_change_trigger AFTER INSERT OR UPDATE OR DELETE ON Sessions
+FOR EACH ROW EXECUTE FUNCTION sync_session_change();
+
+CREATE OR REPLACE FUNCTION sync_set_change()
+RETURNS TRIGGER AS $$
+DECLARE
+    changed TEXT[];
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM sync_log_change(session_owner(OLD.session_id), 'set_performance', OLD.performance_id,
+            'delete', NULL, ARRAY['_deleted']);
+        RETURN OLD;
+    END IF;
+    changed := ARRAY(
+        SELECT field FROM (VALUES
+            ('session_id', TG_OP = 'INSERT' OR OLD.session_id IS DISTINCT FROM NEW.session_id),
+            ('exercise_id', TG_OP = 'INSERT' OR OLD.exercise_id IS DISTINCT FROM NEW.exercise_id),
+            ('set_number', TG_OP = 'INSERT' OR OLD.set_number IS DISTINCT FROM NEW.set_number),
+            ('weight', TG_OP = 'INSERT' OR OLD.weight IS DISTINCT FROM NEW.weight),
+            ('reps', TG_OP = 'INSERT' OR OLD.reps IS DISTINCT FROM NEW.reps),
+            ('rir', TG_OP = 'INSERT' OR OLD.rir IS DISTINCT FROM NEW.rir),
+            ('duration_seconds', TG_OP = 'INSERT' OR OLD.duration_seconds IS DISTINCT FROM NEW.duration_seconds),
+            ('distance_meters', TG_OP = 'INSERT' OR OLD.distance_meters IS DISTINCT FROM NEW.distance_meters),
+            ('calories', TG_OP = 'INSERT' OR OLD.calories IS DISTINCT FROM NEW.calories),
+            ('heart_rate', TG_OP = 'INSERT' OR OLD.heart_rate IS DISTINCT FROM NEW.heart_rate)
+        ) AS fields (field, differs)
+        WHERE differs
+    );
+    IF cardinality(changed) = 0 THEN
+        RETURN NEW;
+    END IF;
+    PERFORM sync_log_change(session_owner(NEW.session_id), 'set_performance', NEW.performance_id,
+        'upsert', jsonb_build_object(
+            'performance_id', NEW.performance_id,
+            'session_id', NEW.session_id,
+            'exercise_id', NEW.exercise_id,
+            'set_number', COALESCE(NEW.set_number, 0),
+            'weight', COALESCE(NEW.weight, 0),
+            'reps', COALESCE(NEW.reps, 0),
+            'rir', NEW.rir,
+            'duration_seconds', NEW.duration_seconds,
+            'distance_meters', NEW.distance_meters,
+            'calories', NEW.calories,
+            'heart_rate', NEW.heart_rate
+        ), changed);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS sync_change_trigger ON SessionExercisePerformance;
+CREATE TRIGGER sync_change_trigger AFTER INSERT OR UPDATE OR DELETE ON SessionExercisePerformance
+FOR EACH ROW EXECUTE FUNCTION sync_set_change();
+
+-- change_id is taken when a row is written, so a transaction that commits late can land behind
+-- a cursor a client already has. Pulls instead hand out positions, in order, only to changes
+-- whose transaction ended before any still running, and page by those. Changes from before
+-- positions keep their change_id as position so existing cursors stay valid.
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_name = 'changelog' AND column_name = 'position'
+    ) THEN
+        ALTER TABLE ChangeLog
+            ADD COLUMN txid xid8 NOT NULL DEFAULT pg_current_xact_id(),
+            ADD COLUMN position BIGINT UNIQUE;
+        UPDATE ChangeLog SET position = change_id;
+        CREATE SEQUENCE IF NOT EXISTS changelog_position_seq;
+        PERFORM setval('changelog_position_seq', GREATEST((SELECT MAX(change_id) FROM ChangeLog), 1));
+    END IF;
+END;
+$$;
 
+CREATE INDEX IF NOT EXISTS changelog_user_position_idx ON ChangeLog (user_id, position);
+CREATE INDEX IF NOT EXISTS changelog_unpositioned_idx ON ChangeLog (user_id, txid, change_id)
+    WHERE position IS NULL;
 
+-- Sessions from before ownership belong to whoever owns the routine they were logged against
+UPDATE Sessions SET user_id = day_owner(day_id)
+WHERE user_id IS NULL AND day_owner(day_id) IS NOT NULL;
@@ -0,0 +1,93 @@
+use actix_web::{
+    http::header::{self, ContentDisposition, DispositionParam, DispositionType},
+    web::{Data, Json, ReqData},
+    HttpResponse,
+};
+use chrono::Utc;
+use shared::models::{Preferences, TokenClaims, WeightUnit};
+use uuid::Uuid;
+
+use crate::routines_repository::RoutinesRepository;
+
+// Everything the user owns as one JSON document, served as a download
+pub(crate) async fn export_account<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    repo: Data<R>,
+) -> HttpResponse {
+    match repo.export_account(&claims.token_id).await {
+        Ok(Some(export)) => {
+            let filename = format!("account-export-{}.json", Utc::now().format("%Y-%m-%d"));
+            HttpResponse::Ok()
+                .insert_header(ContentDisposition {
+                    disposition: DispositionType::Attachment,
+                    parameters: vec![DispositionParam::Filename(filename)],
+                })
+                .insert_header((header::CACHE_CONTROL, "no-store"))
+                .json(export)
+        }
+        Ok(None) => HttpResponse::NotFound().body(format!("User {} not found", claims.token_id)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+// Deletes the user and everything they own. Tokens already issued still verify until their
+// `exp`, at most 12 hours after login, but no longer match a user.
+pub(crate) async fn delete_account<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    repo: Data<R>,
+) -> HttpResponse {
+    match repo.delete_account(&claims.token_id).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().body(format!("User {} not found", claims.token_id)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+// The unit a request reads and writes weights in: the one it names, else the user's preference.
+// Falls back to the storage unit rather than failing the request over a preference.
+pub(crate) async fn preferred_unit<R: RoutinesRepository>(
+    repo: &R,
+    user_id: &Uuid,
+    requested: Option<WeightUnit>,
+) -> WeightUnit {
+    if let Some(unit) = requested {
+        return unit;
+    }
+    repo.get_weight_unit(user_id).await.unwrap_or_else(|e| {
+        log::error!("Failed to load the weight unit of {}: {}", user_id, e);
+        WeightUnit::Kg
+    })
+}
+
+pub(crate) async fn get_preferences<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    repo: Data<R>,
+) -> HttpResponse {
+    match repo.get_weight_unit(&claims.token_id).await {
+        Ok(weight_unit) => HttpResponse::Ok().json(Preferences { weight_unit }),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+pub(crate) async fn update_preferences<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    preferences: Json<Preferences>,
+    repo: Data<R>,
+) -> HttpResponse {
+    match repo
+        .set_weight_unit(&claims.token_id, preferences.weight_unit)
+        .await
+    {
+        Ok(Some(user)) => HttpResponse::Ok().json(user),
+        Ok(None) => HttpResponse::NotFound().body(format!("User {} not found", claims.token_id)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::Duration,
+};
+
+use actix_web::{
+    http::header::{self, HeaderName},
+    web::{Bytes, Data, ReqData},
+    HttpRequest, HttpResponse,
+};
+use chrono::Utc;
+use shared::models::{
+    ActivityEvent, Routine, SessionEvent, SessionWithExercisePerformance, SetPerformance,
+    TokenClaims,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+use crate::live::SessionHub;
+use crate::routines_repository::RoutinesRepository;
+
+const LAST_EVENT_ID: HeaderName = HeaderName::from_static("last-event-id");
+
+const DEFAULT_REPLAY_BUFFER: usize = 100;
+// Events buffered for `subscribe_all` listeners (e.g. webhooks) across all users
+const ALL_USERS_CAPACITY: usize = 1024;
+const DEFAULT_SESSION_AUTO_CLOSE_HOURS: i64 = 12;
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone)]
+pub struct ActivityEnvelope {
+    pub id: u64,
+    pub user_id: Uuid,
+    pub event: ActivityEvent,
+}
+
+impl ActivityEnvelope {
+    fn to_sse(&self) -> Bytes {
+        let data = serde_json::to_string(&self.event).unwrap_or_default();
+        Bytes::from(format!(
+            "id: {}\nevent: {}\ndata: {}\n\n",
+            self.id,
+            self.event.kind(),
+            data
+        ))
+    }
+}
+
+struct UserStream {
+    sender: broadcast::Sender<ActivityEnvelope>,
+    recent: VecDeque<ActivityEnvelope>,
+    // id of the newest event pushed out of `recent`
+    evicted_up_to: u64,
+}
+
+impl UserStream {
+    fn new(replay_buffer: usize) -> Self {
+        Self {
+            sender: broadcast::channel(replay_buffer.max(1)).0,
+            recent: VecDeque::with_capacity(replay_buffer),
+            evicted_up_to: 0,
+        }
+    }
+}
+
+struct Streams {
+    next_id: u64,
+    users: HashMap<Uuid, UserStream>,
+}
+
+/// In-process bus for a user's training activity.
+///
+/// Every user gets their own channel plus a bounded buffer of recent events
+/// (`ACTIVITY_REPLAY_BUFFER`, default 100) that reconnecting streams resume from.
+/// Like `SessionHub`, create one per process and register it as app data.
+pub struct ActivityBus {
+    replay_buffer: usize,
+    streams: Mutex<Streams>,
+    all_users: broadcast::Sender<ActivityEnvelope>,
+}
+
+impl ActivityBus {
+    pub fn new() -> Self {
+        let replay_buffer = std::env::var("ACTIVITY_REPLAY_BUFFER")
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_REPLAY_BUFFER)
+            .max(1);
+        Self {
+            replay_buffer,
+            streams: Mutex::new(Streams {
+                next_id: 1,
+                users: HashMap::new(),
+            }),
+            all_users: broadcast::channel(ALL_USERS_CAPACITY).0,
+        }
+    }
+
+    pub fn publish(&self, user_id: Uuid, event: ActivityEvent) -> ActivityEnvelope {
+        let mut streams = self.streams.lock().unwrap();
+        let envelope = ActivityEnvelope {
+            id: streams.next_id,
+            user_id,
+            event,
+        };
+        streams.next_id += 1;
+
+        let replay_buffer = self.replay_buffer;
+        let stream = streams
+            .users
+            .entry(user_id)
+            .or_insert_with(|| UserStream::new(replay_buffer));
+        if stream.recent.len() >= replay_buffer {
+            if let Some(evicted) = stream.recent.pop_front() {
+                stream.evicted_up_to = evicted.id;
+            }
+        }
+        stream.recent.push_back(envelope.clone());
+        // nobody listening is fine, the event is still kept for replay
+        stream.sender.send(envelope.clone()).ok();
+        self.all_users.send(envelope.clone()).ok();
+
+        envelope
+    }
+
+    /// Subscribe to a user's events, returning the buffered ones after `last_event_id`.
+    /// `None` in the first slot means events after `last_event_id` were already dropped
+    /// from the buffer (or the server restarted) and the client has to re-fetch.
+    pub fn subscribe(
+        &self,
+        user_id: Uuid,
+        last_event_id: Option<u64>,
+    ) -> (
+        Option<Vec<ActivityEnvelope>>,
+        broadcast::Receiver<ActivityEnvelope>,
+    ) {
+        // Replay and subscribe under one lock so no event falls in between
+        let mut streams = self.streams.lock().unwrap();
+        let latest_id = streams.next_id - 1;
+        let replay_buffer = self.replay_buffer;
+        let stream = streams
+            .users
+            .entry(user_id)
+            .or_insert_with(|| UserStream::new(replay_buffer));
+        let receiver = stream.sender.subscribe();
+
+        let Some(last_event_id) = last_event_id else {
+            return (Some(Vec::new()), receiver);
+        };
+        let missed: Vec<_> = stream
+            .recent
+            .iter()
+            .filter(|envelope| envelope.id > last_event_id)
+            .cloned()
+            .collect();
+        let complete = last_event_id <= latest_id && stream.evicted_up_to <= last_event_id;
+
+        (complete.then_some(missed), receiver)
+    }
+
+    /// Every user's events as they are published, without replay.
+    pub fn subscribe_all(&self) -> broadcast::Receiver<ActivityEnvelope> {
+        self.all_users.subscribe()
+    }
+}
+
+impl Default for ActivityBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub(crate) async fn stream(
+    req: HttpRequest,
+    claims: ReqData<TokenClaims>,
+    bus: Data<ActivityBus>,
+) -> HttpResponse {
+    let user_id = claims.token_id;
+    let last_event_id = req
+        .headers()
+        .get(LAST_EVENT_ID)
+        .and_then(|id| id.to_str().ok())
+        .and_then(|id| id.trim().parse::<u64>().ok());
+
+    let (replay, receiver) = bus.subscribe(user_id, last_event_id);
+    let mut pending: VecDeque<Bytes> = match replay {
+        Some(missed) => missed.iter().map(ActivityEnvelope::to_sse).collect(),
+        None => VecDeque::from([reset()]),
+    };
+    // tell EventSource how long to wait before reconnecting
+    pending.push_front(Bytes::from_static(b"retry: 3000\n\n"));
+
+    let events = futures_util::stream::unfold(
+        (pending, receiver),
+        |(mut pending, mut receiver)| async move {
+            if let Some(chunk) = pending.pop_front() {
+                return Some((Ok::<_, actix_web::Error>(chunk), (pending, receiver)));
+            }
+            let chunk = match tokio::time::timeout(KEEP_ALIVE, receiver.recv()).await {
+                Ok(Ok(envelope)) => envelope.to_sse(),
+                // Events were skipped, the client has to re-fetch to catch up
+                Ok(Err(RecvError::Lagged(_))) => reset(),
+                Ok(Err(RecvError::Closed)) => return None,
+                // comment lines keep proxies from closing an idle stream
+                Err(_) => Bytes::from_static(b": keep-alive\n\n"),
+            };
+            Some((Ok(chunk), (pending, receiver)))
+        },
+    );
+
+    HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "text/event-stream"))
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(events)
+}
+
+fn reset() -> Bytes {
+    Bytes::from_static(b"event: reset\ndata: {}\n\n")
+}
+
+// End sessions left in progress for longer than `SESSION_AUTO_CLOSE_HOURS` (default 12)
+// and tell the user, closing any sockets still open on them. Runs before a new session is
+// created, so an abandoned session doesn't block the next one.
+pub(crate) async fn close_stale_sessions<R: RoutinesRepository>(
+    repo: &R,
+    bus: &ActivityBus,
+    hub: &SessionHub,
+    user_id: Uuid,
+) {
+    let hours = std::env::var("SESSION_AUTO_CLOSE_HOURS")
+        .ok()
+        .and_then(|hours| hours.parse().ok())
+        .unwrap_or(DEFAULT_SESSION_AUTO_CLOSE_HOURS);
+    let started_before = Utc::now() - chrono::Duration::hours(hours);
+
+    match repo.close_stale_sessions(&user_id, started_before).await {
+        Ok(sessions) => {
+            for session in sessions {
+                hub.publish(
+                    session.session_id,
+                    SessionEvent::SessionEnded {
+                        session_id: session.session_id,
+                    },
+                );
+                bus.publish(
+                    user_id,
+                    ActivityEvent::SessionAutoClosed {
+                        session_id: session.session_id,
+                        day_id: session.day_id,
+                    },
+                );
+            }
+        }
+        Err(e) => log::error!("Failed to close stale sessions: {:?}", e),
+    }
+}
+
+// Activity is the owner's, whoever wrote it, so an athlete hears about what their coach logs
+fn publish_to_owner(bus: &ActivityBus, owner: Result<Option<Uuid>, String>, event: ActivityEvent) {
+    match owner {
+        Ok(Some(owner)) => {
+            bus.publish(owner, event);
+        }
+        Ok(None) => {}
+        Err(e) => log::error!("Failed to look up who gets {}: {}", event.kind(), e),
+    }
+}
+
+// Check a freshly written set for a PR. Failures are only logged, the set itself was saved.
+pub(crate) async fn check_personal_record<R: RoutinesRepository>(
+    repo: &R,
+    bus: &ActivityBus,
+    session_id: Uuid,
+    exercise_id: Uuid,
+    set_performance: &SetPerformance,
+) {
+    match repo
+        .record_personal_record(&session_id, &exercise_id, set_performance)
+        .await
+    {
+        Ok(Some(record)) => {
+            let owner = repo.get_session_owner(&session_id).await;
+            publish_to_owner(bus, owner, ActivityEvent::PersonalRecord { record });
+        }
+        Ok(None) => {}
+        Err(e) => log::error!("Failed to record personal record: {}", e),
+    }
+}
+
+pub(crate) async fn session_completed<R: RoutinesRepository>(
+    repo: &R,
+    bus: &ActivityBus,
+    session_id: Uuid,
+) {
+    let owner = repo.get_session_owner(&session_id).await;
+    publish_to_owner(bus, owner, ActivityEvent::SessionCompleted { session_id });
+}
+
+pub(crate) fn session_started(
+    bus: &ActivityBus,
+    user_id: Uuid,
+    session: &SessionWithExercisePerformance,
+) {
+    bus.publish(
+        user_id,
+        ActivityEvent::SessionStarted {
+            session_id: session.session_id,
+            day_id: session.day_id,
+            day_name: session.day_name.clone(),
+        },
+    );
+}
+
+pub(crate) fn routine_activated(bus: &ActivityBus, user_id: Uuid, routine: &Routine) {
+    bus.publish(
+        user_id,
+        ActivityEvent::RoutineActivated {
+            routine_id: routine.routine_id,
+            name: routine.name.clone(),
+        },
+    );
+}
+
+// For changes to a routine that may not be the caller's
+pub(crate) async fn owner_routine_activated<R: RoutinesRepository>(
+    repo: &R,
+    bus: &ActivityBus,
+    routine: &Routine,
+) {
+    match repo.get_routine_owner(&routine.routine_id).await {
+        Ok(Some(owner)) => routine_activated(bus, owner, routine),
+        Ok(None) => {}
+        Err(e) => log::error!("Failed to look up who gets routine_activated: {}", e),
+    }
+}
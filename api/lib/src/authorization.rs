@@ -0,0 +1,92 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpMessage, HttpResponse,
+};
+use shared::models::{Role, TokenClaims};
+
+/// Middleware that only lets users holding one of the given roles through, everyone else gets 403.
+///
+/// Attach it to a scope or a single resource in `service()`. The role comes from the token
+/// claims, so it has to run inside the bearer middleware, and a role change only takes effect
+/// once the user's token expires and they log in again.
+#[derive(Clone)]
+pub struct RequireRole {
+    roles: Rc<[Role]>,
+}
+
+impl RequireRole {
+    pub fn new(roles: &[Role]) -> Self {
+        Self {
+            roles: Rc::from(roles),
+        }
+    }
+
+    pub fn admin() -> Self {
+        Self::new(&[Role::Admin])
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequireRole
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RequireRoleMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireRoleMiddleware {
+            service: Rc::new(service),
+            roles: Rc::clone(&self.roles),
+        }))
+    }
+}
+
+pub struct RequireRoleMiddleware<S> {
+    service: Rc<S>,
+    roles: Rc<[Role]>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireRoleMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let role = req
+            .extensions()
+            .get::<TokenClaims>()
+            .map(|claims| claims.role);
+
+        let denied = match role {
+            Some(role) if self.roles.contains(&role) => None,
+            Some(role) => Some(
+                HttpResponse::Forbidden().body(format!("Not allowed for role {}", role.as_str())),
+            ),
+            // registered outside the bearer middleware, or no token was checked
+            None => Some(HttpResponse::Unauthorized().finish()),
+        };
+        if let Some(response) = denied {
+            return Box::pin(ready(Ok(req.into_response(response).map_into_right_body())));
+        }
+
+        let service = Rc::clone(&self.service);
+        Box::pin(async move { service.call(req).await.map(|res| res.map_into_left_body()) })
+    }
+}
@@ -0,0 +1,238 @@
+use actix_web::{
+    web::{Data, Json, Path, Query, ReqData},
+    HttpResponse,
+};
+use chrono::{Duration, Utc};
+use rust_decimal::Decimal;
+use shared::models::{
+    BodyMeasurementsQuery, BodyMetric, BodyTrendQuery, BodyweightAsOf, BodyweightAsOfQuery,
+    CreateBodyMeasurement, PatchBodyMeasurement, TokenClaims,
+};
+use shared::utils::{with_moving_average, InWeightUnit};
+use uuid::Uuid;
+
+use crate::account;
+use crate::routines_repository::RoutinesRepository;
+
+const DEFAULT_TREND_WINDOW: i64 = 7;
+const MAX_TREND_WINDOW: i64 = 365;
+
+// The database refuses the same, this answers with what was wrong instead of a 500
+fn check_values(
+    bodyweight: Option<Decimal>,
+    body_fat_percent: Option<Decimal>,
+    tape: [Option<Decimal>; 4],
+) -> Result<(), &'static str> {
+    if bodyweight.is_some_and(|bodyweight| bodyweight <= Decimal::ZERO) {
+        return Err("Bodyweight must be positive");
+    }
+    if body_fat_percent
+        .is_some_and(|percent| percent <= Decimal::ZERO || percent >= Decimal::ONE_HUNDRED)
+    {
+        return Err("Body fat must be between 0 and 100 percent");
+    }
+    if tape.into_iter().flatten().any(|cm| cm <= Decimal::ZERO) {
+        return Err("Tape measurements must be positive");
+    }
+    Ok(())
+}
+
+// Logs the day's measurements, bodyweight in the request's unit or else the caller's. Sets on
+// bodyweight exercises logged from then on count it in their effective load.
+pub(crate) async fn log_body_measurement<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    measurement: Json<CreateBodyMeasurement>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let measurement = measurement.into_inner();
+    let values = [
+        measurement.waist_cm,
+        measurement.chest_cm,
+        measurement.arms_cm,
+        measurement.thighs_cm,
+    ];
+    if measurement.bodyweight.is_none()
+        && measurement.body_fat_percent.is_none()
+        && values.iter().all(Option::is_none)
+    {
+        return HttpResponse::BadRequest().body("Nothing to log");
+    }
+    if let Err(e) = check_values(measurement.bodyweight, measurement.body_fat_percent, values) {
+        return HttpResponse::BadRequest().body(e);
+    }
+
+    let unit = account::preferred_unit(repo.get_ref(), &claims.token_id, measurement.unit).await;
+    let measurement = measurement.into_storage_unit(unit);
+    match repo
+        .log_body_measurement(&claims.token_id, &measurement)
+        .await
+    {
+        Ok(measurement) => HttpResponse::Ok().json(measurement.in_unit(unit)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+pub(crate) async fn get_body_measurements<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    query: Query<BodyMeasurementsQuery>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let unit = account::preferred_unit(repo.get_ref(), &claims.token_id, query.unit).await;
+    match repo
+        .get_body_measurements(&claims.token_id, query.from, query.to)
+        .await
+    {
+        Ok(measurements) => HttpResponse::Ok().json(measurements.in_unit(unit)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+pub(crate) async fn get_body_measurement<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    query: Query<BodyMeasurementsQuery>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let measurement_id = path.into_inner();
+    let unit = account::preferred_unit(repo.get_ref(), &claims.token_id, query.unit).await;
+    match repo
+        .get_body_measurement(&claims.token_id, &measurement_id)
+        .await
+    {
+        Ok(Some(measurement)) => HttpResponse::Ok().json(measurement.in_unit(unit)),
+        Ok(None) => {
+            HttpResponse::NotFound().body(format!("Measurement {} not found", measurement_id))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+// Moving a measurement onto a day that already has one fails, the two aren't merged
+pub(crate) async fn patch_body_measurement<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    patch: Json<PatchBodyMeasurement>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let measurement_id = path.into_inner();
+    let patch = patch.into_inner();
+    if let Err(e) = check_values(
+        patch.bodyweight,
+        patch.body_fat_percent,
+        [
+            patch.waist_cm,
+            patch.chest_cm,
+            patch.arms_cm,
+            patch.thighs_cm,
+        ],
+    ) {
+        return HttpResponse::BadRequest().body(e);
+    }
+
+    let unit = account::preferred_unit(repo.get_ref(), &claims.token_id, patch.unit).await;
+    let patch = patch.into_storage_unit(unit);
+    match repo
+        .patch_body_measurement(&claims.token_id, &measurement_id, &patch)
+        .await
+    {
+        Ok(Some(measurement)) => HttpResponse::Ok().json(measurement.in_unit(unit)),
+        Ok(None) => {
+            HttpResponse::NotFound().body(format!("Measurement {} not found", measurement_id))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+// Effective loads already logged keep the bodyweight they were computed with
+pub(crate) async fn delete_body_measurement<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let measurement_id = path.into_inner();
+    match repo
+        .delete_body_measurement(&claims.token_id, &measurement_id)
+        .await
+    {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => {
+            HttpResponse::NotFound().body(format!("Measurement {} not found", measurement_id))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+// One metric over time with its moving average, oldest first, for charts. The average at `from`
+// takes in the days before it that fall in its window.
+pub(crate) async fn get_body_trend<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    query: Query<BodyTrendQuery>,
+    repo: Data<R>,
+) -> HttpResponse {
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from > to {
+            return HttpResponse::BadRequest().body(format!("from {} is after to {}", from, to));
+        }
+    }
+    let window = query
+        .window
+        .unwrap_or(DEFAULT_TREND_WINDOW)
+        .clamp(1, MAX_TREND_WINDOW);
+    let lookback = query.from.map(|from| from - Duration::days(window - 1));
+
+    let points = match repo
+        .get_body_trend(&claims.token_id, query.metric, lookback, query.to)
+        .await
+    {
+        Ok(points) => points,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Internal server error: {:?}", e))
+        }
+    };
+    let mut points = with_moving_average(points, window);
+    if let Some(from) = query.from {
+        points.retain(|point| point.measured_on >= from);
+    }
+
+    if query.metric == BodyMetric::Bodyweight {
+        let unit = account::preferred_unit(repo.get_ref(), &claims.token_id, query.unit).await;
+        points = points.in_unit(unit);
+    }
+    HttpResponse::Ok().json(points)
+}
+
+// The bodyweight other features go by on a given day: the latest one logged on or before it
+pub(crate) async fn get_bodyweight_as_of<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    query: Query<BodyweightAsOfQuery>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let date = query.date.unwrap_or_else(|| Utc::now().date_naive());
+    let unit = account::preferred_unit(repo.get_ref(), &claims.token_id, query.unit).await;
+    match repo.get_bodyweight_as_of(&claims.token_id, date).await {
+        Ok(Some(measurement)) => {
+            let measurement = measurement.in_unit(unit);
+            HttpResponse::Ok().json(BodyweightAsOf {
+                date,
+                measured_on: measurement.measured_on,
+                // only measurements with a bodyweight are looked up
+                bodyweight: measurement.bodyweight.unwrap_or_default(),
+            })
+        }
+        Ok(None) => HttpResponse::NotFound().body(format!("No bodyweight logged by {}", date)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use actix_web::{
+    web::{Data, Json, Path, ReqData},
+    HttpResponse,
+};
+use shared::models::{CoachPermissions, CreateCoachInvitation, TokenClaims};
+use uuid::Uuid;
+
+use crate::routines_repository::RoutinesRepository;
+
+// A coach invites an athlete by username. Nothing is shared until the athlete accepts.
+pub(crate) async fn create_invitation<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    invitation: Json<CreateCoachInvitation>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let athlete = match repo.get_user(&invitation.athlete_username).await {
+        Ok(athlete) => athlete,
+        Err(_) => {
+            return HttpResponse::NotFound()
+                .body(format!("User {} not found", invitation.athlete_username))
+        }
+    };
+    if athlete.user_id == claims.token_id {
+        return HttpResponse::BadRequest().body("You can't coach yourself");
+    }
+
+    match repo
+        .create_coach_invitation(&claims.token_id, &athlete.user_id, &invitation.permissions)
+        .await
+    {
+        Ok(Some(link)) => HttpResponse::Created().json(link),
+        Ok(None) => HttpResponse::Conflict().body(format!(
+            "You already coach or have invited {}",
+            invitation.athlete_username
+        )),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+pub(crate) async fn get_coaches<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    repo: Data<R>,
+) -> HttpResponse {
+    match repo.get_coaches(&claims.token_id).await {
+        Ok(links) => HttpResponse::Ok().json(links),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+pub(crate) async fn get_athletes<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    repo: Data<R>,
+) -> HttpResponse {
+    match repo.get_athletes(&claims.token_id).await {
+        Ok(links) => HttpResponse::Ok().json(links),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+pub(crate) async fn accept_invitation<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let link_id = path.into_inner();
+    match repo
+        .accept_coach_invitation(&claims.token_id, &link_id)
+        .await
+    {
+        Ok(Some(link)) => HttpResponse::Ok().json(link),
+        Ok(None) => {
+            HttpResponse::NotFound().body(format!("Pending invitation {} not found", link_id))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+pub(crate) async fn update_permissions<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    permissions: Json<CoachPermissions>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let link_id = path.into_inner();
+    match repo
+        .update_coach_permissions(&claims.token_id, &link_id, &permissions)
+        .await
+    {
+        Ok(Some(link)) => HttpResponse::Ok().json(link),
+        Ok(None) => HttpResponse::NotFound().body(format!("Coach {} not found", link_id)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+// Revokes an accepted link or declines a pending invitation, from either side
+pub(crate) async fn delete_link<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let link_id = path.into_inner();
+    match repo.delete_coaching_link(&claims.token_id, &link_id).await {
+        Ok(Some(link_id)) => HttpResponse::Ok().json(link_id),
+        Ok(None) => HttpResponse::NotFound().body(format!("Coaching link {} not found", link_id)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+// Every accepted athlete with their active routine, last session and recent PRs
+pub(crate) async fn get_dashboard<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    repo: Data<R>,
+) -> HttpResponse {
+    match repo.get_coach_dashboard(&claims.token_id).await {
+        Ok(dashboard) => HttpResponse::Ok().json(dashboard),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
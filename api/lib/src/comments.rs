@@ -0,0 +1,159 @@
+use actix_web::{
+    web::{Data, Json, Path, ReqData},
+    HttpResponse,
+};
+use shared::models::{ActivityEvent, CreateComment, TokenClaims, UpdateComment};
+use uuid::Uuid;
+
+use crate::activity::ActivityBus;
+use crate::routines_repository::{RoutinesRepository, SavedComment};
+
+// Same as the CHECK on Comments.body
+const MAX_COMMENT_LENGTH: usize = 5000;
+
+fn invalid_body(body: &str) -> Option<HttpResponse> {
+    if body.trim().is_empty() {
+        Some(HttpResponse::BadRequest().body("Comment can't be empty"))
+    } else if body.chars().count() > MAX_COMMENT_LENGTH {
+        Some(HttpResponse::BadRequest().body(format!(
+            "Comment is longer than {} characters",
+            MAX_COMMENT_LENGTH
+        )))
+    } else {
+        None
+    }
+}
+
+// The session's owner and the author both see the thread move in their activity stream
+fn publish(bus: &ActivityBus, author_id: Uuid, session_owner: Option<Uuid>, event: ActivityEvent) {
+    if let Some(owner) = session_owner.filter(|owner| *owner != author_id) {
+        bus.publish(owner, event.clone());
+    }
+    bus.publish(author_id, event);
+}
+
+pub(crate) async fn get_comments<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let session_id = path.into_inner();
+    match repo.get_session(&claims.token_id, &session_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return HttpResponse::NotFound().body(format!("Session {} not found", session_id))
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Internal server error: {:?}", e))
+        }
+    }
+    match repo.get_comments(&claims.token_id, &session_id).await {
+        Ok(comments) => HttpResponse::Ok().json(comments),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+pub(crate) async fn create_comment<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    create_comment: Json<CreateComment>,
+    repo: Data<R>,
+    bus: Data<ActivityBus>,
+) -> HttpResponse {
+    let session_id = path.into_inner();
+    if let Some(response) = invalid_body(&create_comment.body) {
+        return response;
+    }
+    match repo
+        .create_comment(&claims.token_id, &session_id, &create_comment)
+        .await
+    {
+        Ok(Some(SavedComment {
+            comment,
+            session_owner,
+        })) => {
+            publish(
+                &bus,
+                claims.token_id,
+                session_owner,
+                ActivityEvent::CommentAdded {
+                    comment: comment.clone(),
+                },
+            );
+            HttpResponse::Created().json(comment)
+        }
+        Ok(None) => HttpResponse::NotFound().body(format!(
+            "Session {} not found, or the exercise, set or comment replied to isn't part of it",
+            session_id
+        )),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+pub(crate) async fn update_comment<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    update_comment: Json<UpdateComment>,
+    repo: Data<R>,
+    bus: Data<ActivityBus>,
+) -> HttpResponse {
+    let comment_id = path.into_inner();
+    if let Some(response) = invalid_body(&update_comment.body) {
+        return response;
+    }
+    match repo
+        .update_comment(&claims.token_id, &comment_id, &update_comment.body)
+        .await
+    {
+        Ok(Some(SavedComment {
+            comment,
+            session_owner,
+        })) => {
+            publish(
+                &bus,
+                claims.token_id,
+                session_owner,
+                ActivityEvent::CommentUpdated {
+                    comment: comment.clone(),
+                },
+            );
+            HttpResponse::Ok().json(comment)
+        }
+        Ok(None) => HttpResponse::NotFound().body(format!("Comment {} not found", comment_id)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+pub(crate) async fn delete_comment<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    repo: Data<R>,
+    bus: Data<ActivityBus>,
+) -> HttpResponse {
+    let comment_id = path.into_inner();
+    match repo.delete_comment(&claims.token_id, &comment_id).await {
+        Ok(Some(removed)) => {
+            publish(
+                &bus,
+                removed.author_id,
+                removed.session_owner,
+                ActivityEvent::CommentDeleted {
+                    comment_id: removed.comment_id,
+                    session_id: removed.session_id,
+                },
+            );
+            HttpResponse::Ok().json(removed.comment_id)
+        }
+        Ok(None) => HttpResponse::NotFound().body(format!("Comment {} not found", comment_id)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt;
+
+use actix_web::{
+    http::{header, StatusCode},
+    HttpRequest, HttpResponse, HttpResponseBuilder, ResponseError,
+};
+use chrono::{DateTime, Utc};
+
+// A row's ETag is its updated_at in microseconds (Postgres' timestamp precision),
+// falling back to created_at for rows that were never updated
+pub(crate) fn etag(
+    created_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+) -> Option<String> {
+    updated_at
+        .or(created_at)
+        .map(|version| format!("\"{}\"", version.timestamp_micros()))
+}
+
+pub(crate) fn ok_with_etag(
+    created_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+) -> HttpResponseBuilder {
+    let mut response = HttpResponse::Ok();
+    if let Some(tag) = etag(created_at, updated_at) {
+        response.insert_header((header::ETAG, tag));
+    }
+    response
+}
+
+// An `If-Match` tag we never issued, answered with 412
+#[derive(Debug)]
+pub(crate) struct UnknownVersion;
+
+impl fmt::Display for UnknownVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "If-Match does not match any version")
+    }
+}
+
+impl ResponseError for UnknownVersion {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::PRECONDITION_FAILED
+    }
+}
+
+// Read the version a client expects from `If-Match`.
+// No header or `*` means an unconditional write, a tag we never issued can't match anything.
+pub(crate) fn if_match(req: &HttpRequest) -> Result<Option<DateTime<Utc>>, UnknownVersion> {
+    let Some(value) = req.headers().get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+    let value = value.to_str().unwrap_or_default().trim();
+    if value == "*" {
+        return Ok(None);
+    }
+    parse_etag(value).map(Some).ok_or(UnknownVersion)
+}
+
+fn parse_etag(value: &str) -> Option<DateTime<Utc>> {
+    let micros = value
+        .trim_start_matches("W/")
+        .trim_matches('"')
+        .parse::<i64>()
+        .ok()?;
+    DateTime::from_timestamp_micros(micros)
+}
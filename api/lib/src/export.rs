@@ -0,0 +1,113 @@
+use actix_web::{
+    http::header,
+    web::{Bytes, Data, Query, ReqData},
+    HttpResponse,
+};
+use futures_util::{stream, StreamExt};
+use shared::models::{HistoryExportQuery, HistoryExportRow, TokenClaims, WeightUnit};
+use shared::utils::InWeightUnit;
+
+use crate::account;
+use crate::routines_repository::RoutinesRepository;
+
+const HEADER: [&str; 16] = [
+    "session_date",
+    "routine",
+    "training_day",
+    "exercise",
+    "set_number",
+    "set_type",
+    "weight",
+    "effective_load",
+    "unit",
+    "reps",
+    "rir",
+    "duration_seconds",
+    "distance_meters",
+    "calories",
+    "heart_rate",
+    "e1rm",
+];
+
+// Streams every logged set as CSV, one row per set, optionally limited to a date range
+// (`from`/`to`, inclusive) and a routine. Coaches with history access pass `user_id` to export an
+// athlete. Weights are in `unit`, else the caller's, and the e1RM is on the effective load. Rows
+// are written as they come out of the database.
+pub(crate) async fn export_history<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    query: Query<HistoryExportQuery>,
+    repo: Data<R>,
+) -> HttpResponse {
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from > to {
+            return HttpResponse::BadRequest().body(format!("from {} is after to {}", from, to));
+        }
+    }
+
+    let unit = account::preferred_unit(repo.get_ref(), &claims.token_id, query.unit).await;
+    let rows = repo
+        .export_history(&claims.token_id, &query)
+        .map(move |row| match row {
+            Ok(row) => csv_line(&row_fields(&row.in_unit(unit), unit)),
+            Err(e) => {
+                // the status line is long gone, all we can do is cut the download short
+                log::error!("History export failed: {}", e);
+                Err(actix_web::error::ErrorInternalServerError(e))
+            }
+        });
+    let body = stream::once(async { csv_line(&HEADER) }).chain(rows);
+
+    HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "text/csv; charset=utf-8"))
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"training-history.csv\"",
+        ))
+        .streaming(body)
+}
+
+fn row_fields(row: &HistoryExportRow, unit: WeightUnit) -> [String; 16] {
+    [
+        row.session_date.format("%Y-%m-%d %H:%M:%S").to_string(),
+        text_field(&row.routine),
+        text_field(&row.training_day),
+        text_field(&row.exercise),
+        row.set_number.to_string(),
+        text_field(&row.set_type),
+        row.weight.to_string(),
+        row.effective_load.to_string(),
+        unit.as_str().to_string(),
+        row.reps.to_string(),
+        optional_field(row.rir),
+        optional_field(row.duration_seconds),
+        optional_field(row.distance_meters),
+        optional_field(row.calories),
+        optional_field(row.heart_rate),
+        format!("{:.1}", row.estimated_one_rep_max),
+    ]
+}
+
+// Names are typed by users. Spreadsheets run a cell starting with one of these as a formula, the
+// quote makes them show it as text instead.
+fn text_field(text: &str) -> String {
+    if text.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn optional_field<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+fn csv_line<T: AsRef<[u8]>>(fields: &[T]) -> Result<Bytes, actix_web::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(fields)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    writer
+        .into_inner()
+        .map(Bytes::from)
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
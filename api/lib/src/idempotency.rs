@@ -0,0 +1,246 @@
+use std::{
+    future::{ready, Future, Ready},
+    marker::PhantomData,
+    pin::Pin,
+    rc::Rc,
+};
+
+use actix_web::{
+    body::{self, BoxBody, MessageBody},
+    dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        header::{self, HeaderName, HeaderValue},
+        Method, StatusCode,
+    },
+    web::{BytesMut, Data},
+    Error, HttpMessage, HttpResponse,
+};
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
+use shared::models::TokenClaims;
+
+use crate::import::IMPORT_BODY_LIMIT;
+use crate::routines_repository::{IdempotencyReservation, RoutinesRepository};
+
+const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+const DEFAULT_TTL_SECONDS: i64 = 24 * 60 * 60;
+
+// The largest body any route takes. Each route's extractor still holds the body to its own
+// limit once it's handed back.
+const MAX_BODY_BYTES: usize = IMPORT_BODY_LIMIT;
+
+/// Middleware that makes POST requests carrying an `Idempotency-Key` header safe to retry.
+///
+/// The first request with a key runs normally and its response is stored. A retry with the
+/// same key and body gets the stored response back, a retry with a different body gets 422.
+/// Keys are scoped per user and expire after `IDEMPOTENCY_KEY_TTL_SECONDS` (default 24h).
+/// Register it inside the bearer middleware so the token claims are available, requests without
+/// them pass straight through since their keys couldn't be told apart.
+pub struct Idempotency<R> {
+    ttl_seconds: i64,
+    repository: PhantomData<R>,
+}
+
+impl<R> Idempotency<R> {
+    pub fn new() -> Self {
+        let ttl_seconds = std::env::var("IDEMPOTENCY_KEY_TTL_SECONDS")
+            .ok()
+            .and_then(|ttl| ttl.parse().ok())
+            .unwrap_or(DEFAULT_TTL_SECONDS);
+        Self {
+            ttl_seconds,
+            repository: PhantomData,
+        }
+    }
+}
+
+impl<R> Default for Idempotency<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S, B, R> Transform<S, ServiceRequest> for Idempotency<R>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+    R: RoutinesRepository,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = IdempotencyMiddleware<S, R>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(IdempotencyMiddleware {
+            service: Rc::new(service),
+            ttl_seconds: self.ttl_seconds,
+            repository: PhantomData,
+        }))
+    }
+}
+
+pub struct IdempotencyMiddleware<S, R> {
+    service: Rc<S>,
+    ttl_seconds: i64,
+    repository: PhantomData<R>,
+}
+
+impl<S, B, R> Service<ServiceRequest> for IdempotencyMiddleware<S, R>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+    R: RoutinesRepository,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let ttl_seconds = self.ttl_seconds;
+
+        Box::pin(async move {
+            let key = req
+                .headers()
+                .get(IDEMPOTENCY_KEY)
+                .and_then(|key| key.to_str().ok())
+                .map(str::to_string)
+                .filter(|_| req.method() == Method::POST);
+            let repo = req.app_data::<Data<R>>().cloned();
+
+            let (Some(key), Some(repo)) = (key, repo) else {
+                return service.call(req).await.map(|res| res.map_into_boxed_body());
+            };
+
+            let user_id = req
+                .extensions()
+                .get::<TokenClaims>()
+                .map(|claims| claims.token_id);
+            let Some(user_id) = user_id else {
+                return service.call(req).await.map(|res| res.map_into_boxed_body());
+            };
+            let method = req.method().to_string();
+            let path = req.path().to_string();
+
+            // Buffer the body so it can be hashed, then hand it back to the handler
+            let mut payload = req.take_payload();
+            let mut request_body = BytesMut::new();
+            while let Some(chunk) = payload.next().await {
+                let chunk = chunk?;
+                if request_body.len() + chunk.len() > MAX_BODY_BYTES {
+                    return Ok(req.into_response(HttpResponse::PayloadTooLarge().finish()));
+                }
+                request_body.extend_from_slice(&chunk);
+            }
+            let request_body = request_body.freeze();
+            let request_hash = request_hash(&method, &path, &request_body);
+            req.set_payload(Payload::from(request_body));
+
+            let reservation = repo
+                .reserve_idempotency_key(&user_id, &key, &method, &path, &request_hash, ttl_seconds)
+                .await;
+
+            match reservation {
+                Err(e) => Ok(req.into_response(
+                    HttpResponse::InternalServerError()
+                        .body(format!("Internal server error: {:?}", e)),
+                )),
+                Ok(IdempotencyReservation::Existing(record)) => {
+                    let response = if record.request_hash != request_hash {
+                        HttpResponse::UnprocessableEntity()
+                            .body("Idempotency-Key was already used with a different request")
+                    } else {
+                        match record.status_code {
+                            None => HttpResponse::Conflict()
+                                .body("A request with this Idempotency-Key is still in progress"),
+                            Some(status_code) => {
+                                let status = StatusCode::from_u16(status_code as u16)
+                                    .unwrap_or(StatusCode::OK);
+                                let mut response = HttpResponse::build(status);
+                                response.insert_header((
+                                    IDEMPOTENT_REPLAYED,
+                                    HeaderValue::from_static("true"),
+                                ));
+                                if let Some(content_type) = record.content_type {
+                                    response.insert_header((header::CONTENT_TYPE, content_type));
+                                }
+                                response.body(record.response_body.unwrap_or_default())
+                            }
+                        }
+                    };
+                    Ok(req.into_response(response))
+                }
+                Ok(IdempotencyReservation::Reserved) => {
+                    let res = match service.call(req).await {
+                        Ok(res) => res,
+                        Err(e) => {
+                            repo.release_idempotency_key(&user_id, &key).await.ok();
+                            return Err(e);
+                        }
+                    };
+
+                    // Server errors are not stored so the client can retry them
+                    if res.status().is_server_error() {
+                        repo.release_idempotency_key(&user_id, &key).await.ok();
+                        return Ok(res.map_into_boxed_body());
+                    }
+
+                    let (req, res) = res.into_parts();
+                    let (res, response_body) = res.into_parts();
+                    let response_body = match body::to_bytes(response_body).await {
+                        Ok(response_body) => response_body,
+                        Err(_) => {
+                            repo.release_idempotency_key(&user_id, &key).await.ok();
+                            return Ok(ServiceResponse::new(
+                                req,
+                                HttpResponse::InternalServerError()
+                                    .body("Failed to read response body"),
+                            ));
+                        }
+                    };
+                    let content_type = res
+                        .headers()
+                        .get(header::CONTENT_TYPE)
+                        .and_then(|content_type| content_type.to_str().ok())
+                        .map(str::to_string);
+
+                    if let Err(e) = repo
+                        .complete_idempotency_key(
+                            &user_id,
+                            &key,
+                            res.status().as_u16() as i16,
+                            content_type.as_deref(),
+                            &response_body,
+                        )
+                        .await
+                    {
+                        // Don't leave the key stuck as in progress
+                        log::error!("Failed to store idempotent response for {}: {}", key, e);
+                        repo.release_idempotency_key(&user_id, &key).await.ok();
+                    }
+
+                    Ok(
+                        ServiceResponse::new(req, res.set_body(response_body))
+                            .map_into_boxed_body(),
+                    )
+                }
+            }
+        })
+    }
+}
+
+fn request_hash(method: &str, path: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_bytes());
+    hasher.update(b" ");
+    hasher.update(path.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body);
+    format!("{:x}", hasher.finalize())
+}
@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+
+use actix_web::{
+    web::{Data, Json, ReqData},
+    HttpResponse,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rust_decimal::Decimal;
+use shared::models::{
+    ImportReport, ImportRequest, ImportRowError, ImportSource, SetPerformancePayload, TokenClaims,
+    TrackedMetrics, UnmatchedExercise, WeightUnit,
+};
+use shared::utils::to_storage_weight;
+use uuid::Uuid;
+
+use crate::account;
+use crate::routines_repository::{ImportedSession, ImportedSet, RoutinesRepository};
+
+// Exports cover years of training, well past the default JSON limit
+pub(crate) const IMPORT_BODY_LIMIT: usize = 20 * 1024 * 1024;
+
+// Sessions.day_name is a VARCHAR(50)
+const MAX_DAY_NAME: usize = 50;
+
+const METERS_PER_KILOMETER: Decimal = Decimal::from_parts(1000, 0, 0, false, 0);
+const METERS_PER_MILE: Decimal = Decimal::from_parts(1609344, 0, 0, false, 3);
+
+// Where each field lives in a source's export
+struct Columns {
+    date: usize,
+    workout: usize,
+    exercise: usize,
+    weight: Option<usize>,
+    reps: usize,
+    rpe: Option<usize>,
+    // Hevy exports pounds in `weight_lbs` when the account is set to imperial. Strong writes
+    // whatever the app was set to and doesn't say which.
+    weight_unit: Option<WeightUnit>,
+    // Cardio and timed sets. Distances are in kilometers or miles, Strong's again going by the
+    // app's units, taken to be metric for kilograms and imperial for pounds.
+    duration: Option<usize>,
+    distance: Option<usize>,
+    distance_unit: Option<WeightUnit>,
+}
+
+impl Columns {
+    fn find(source: ImportSource, headers: &csv::StringRecord) -> Result<Self, String> {
+        let position = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header.trim().eq_ignore_ascii_case(name))
+        };
+        let required = |name: &str| {
+            position(name)
+                .ok_or_else(|| format!("Missing column {} for a {:?} export", name, source))
+        };
+
+        match source {
+            ImportSource::Strong => Ok(Self {
+                date: required("Date")?,
+                workout: required("Workout Name")?,
+                exercise: required("Exercise Name")?,
+                weight: position("Weight"),
+                reps: required("Reps")?,
+                rpe: position("RPE"),
+                weight_unit: None,
+                duration: position("Seconds"),
+                distance: position("Distance"),
+                distance_unit: None,
+            }),
+            ImportSource::Hevy => {
+                let (weight, weight_unit) = match position("weight_kg") {
+                    Some(weight) => (Some(weight), WeightUnit::Kg),
+                    None => (position("weight_lbs"), WeightUnit::Lb),
+                };
+                let (distance, distance_unit) = match position("distance_km") {
+                    Some(distance) => (Some(distance), WeightUnit::Kg),
+                    None => (position("distance_miles"), WeightUnit::Lb),
+                };
+                Ok(Self {
+                    date: required("start_time")?,
+                    workout: required("title")?,
+                    exercise: required("exercise_title")?,
+                    weight,
+                    reps: required("reps")?,
+                    rpe: position("rpe"),
+                    weight_unit: Some(weight_unit),
+                    duration: position("duration_seconds"),
+                    distance,
+                    distance_unit: Some(distance_unit),
+                })
+            }
+        }
+    }
+}
+
+struct ParsedRow {
+    line: usize,
+    workout: String,
+    started_at: DateTime<Utc>,
+    exercise: String,
+    weight: Decimal,
+    reps: i16,
+    rir: Option<i16>,
+    duration_seconds: Option<i32>,
+    distance_meters: Option<Decimal>,
+}
+
+struct ParsedFile {
+    rows: Vec<ParsedRow>,
+    skipped_rows: usize,
+    errors: Vec<ImportRowError>,
+}
+
+// Accepts a Strong or Hevy CSV export and turns it into completed, backdated sessions.
+// Nothing is written for a dry run, or when an exercise can't be matched or a row can't be
+// read; the report lists what needs fixing (usually an entry in `aliases`).
+pub(crate) async fn import_history<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    import_request: Json<ImportRequest>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let unit = account::preferred_unit(repo.get_ref(), &claims.token_id, import_request.unit).await;
+    let parsed = match parse(import_request.source, &import_request.csv, unit) {
+        Ok(parsed) => parsed,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+
+    let exercises = match repo.get_exercises(&claims.token_id).await {
+        Ok(exercises) => exercises,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Internal server error: {:?}", e))
+        }
+    };
+    let mut exercise_ids: HashMap<String, Uuid> = exercises
+        .iter()
+        .map(|exercise| (normalize(&exercise.exercise_name), exercise.exercise_id))
+        .collect();
+    let tracked_metrics: HashMap<Uuid, &TrackedMetrics> = exercises
+        .iter()
+        .map(|exercise| (exercise.exercise_id, &exercise.tracked_metrics))
+        .collect();
+    for (alias, exercise_id) in &import_request.aliases {
+        if !exercises
+            .iter()
+            .any(|exercise| exercise.exercise_id == *exercise_id)
+        {
+            return HttpResponse::BadRequest().body(format!(
+                "Alias {} points at unknown exercise {}",
+                alias, exercise_id
+            ));
+        }
+        exercise_ids.insert(normalize(alias), *exercise_id);
+    }
+
+    let mut report = ImportReport {
+        source: import_request.source,
+        dry_run: import_request.dry_run,
+        skipped_rows: parsed.skipped_rows,
+        errors: parsed.errors,
+        ..Default::default()
+    };
+
+    // Rows are grouped into sessions by start time and workout name, in file order
+    let mut sessions: Vec<ImportedSession> = Vec::new();
+    let mut session_index: HashMap<(DateTime<Utc>, String), usize> = HashMap::new();
+    let mut set_numbers: HashMap<(usize, Uuid), i16> = HashMap::new();
+    let mut unmatched: Vec<UnmatchedExercise> = Vec::new();
+
+    for row in parsed.rows {
+        let Some(exercise_id) = exercise_ids.get(&normalize(&row.exercise)).copied() else {
+            match unmatched
+                .iter_mut()
+                .find(|unmatched| unmatched.name == row.exercise)
+            {
+                Some(unmatched) => unmatched.rows += 1,
+                None => unmatched.push(UnmatchedExercise {
+                    name: row.exercise,
+                    rows: 1,
+                }),
+            }
+            continue;
+        };
+
+        // Held to what the exercise tracks, like a set logged in the app
+        let set = SetPerformancePayload {
+            weight: row.weight,
+            reps: row.reps,
+            rir: row.rir,
+            duration_seconds: row.duration_seconds,
+            distance_meters: row.distance_meters,
+            ..Default::default()
+        };
+        if let Err(e) = tracked_metrics[&exercise_id].check_set(&set) {
+            report.errors.push(ImportRowError {
+                line: row.line,
+                message: format!("{}: {}", row.exercise, e),
+            });
+            continue;
+        }
+
+        let index = *session_index
+            .entry((row.started_at, row.workout.clone()))
+            .or_insert_with(|| {
+                sessions.push(ImportedSession {
+                    day_name: row.workout.chars().take(MAX_DAY_NAME).collect(),
+                    started_at: row.started_at,
+                    sets: Vec::new(),
+                });
+                sessions.len() - 1
+            });
+
+        // Renumbered from 1 per exercise, the sources number sets differently (and Hevy from 0)
+        let set_number = set_numbers.entry((index, exercise_id)).or_insert(0);
+        *set_number += 1;
+
+        sessions[index].sets.push(ImportedSet {
+            exercise_id,
+            set_number: *set_number,
+            weight: row.weight,
+            reps: row.reps,
+            rir: row.rir,
+            duration_seconds: row.duration_seconds,
+            distance_meters: row.distance_meters,
+        });
+    }
+
+    report.sessions = sessions.len();
+    report.sets = sessions.iter().map(|session| session.sets.len()).sum();
+    report.unmatched_exercises = unmatched;
+
+    let clean = report.unmatched_exercises.is_empty() && report.errors.is_empty();
+    if import_request.dry_run {
+        return HttpResponse::Ok().json(report);
+    }
+    if !clean {
+        return HttpResponse::UnprocessableEntity().json(report);
+    }
+
+    match repo.import_sessions(&claims.token_id, &sessions).await {
+        Ok(_) => {
+            report.imported = true;
+            HttpResponse::Ok().json(report)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+// `unit` is what weights are in when the file doesn't say
+fn parse(source: ImportSource, csv: &str, unit: WeightUnit) -> Result<ParsedFile, String> {
+    // Strong uses semicolons in some locales
+    let header_line = csv.lines().next().unwrap_or_default();
+    let delimiter = if header_line.matches(';').count() > header_line.matches(',').count() {
+        b';'
+    } else {
+        b','
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(csv.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("Can't read the header row: {}", e))?
+        .clone();
+    let columns = Columns::find(source, &headers)?;
+
+    let mut parsed = ParsedFile {
+        rows: Vec::new(),
+        skipped_rows: 0,
+        errors: Vec::new(),
+    };
+    for (index, record) in reader.records().enumerate() {
+        // the header is line 1
+        let line = index + 2;
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                parsed.errors.push(ImportRowError {
+                    line,
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        match parse_row(source, &columns, unit, line, &record) {
+            Ok(Some(row)) => parsed.rows.push(row),
+            Ok(None) => parsed.skipped_rows += 1,
+            Err(message) => parsed.errors.push(ImportRowError { line, message }),
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn parse_row(
+    source: ImportSource,
+    columns: &Columns,
+    unit: WeightUnit,
+    line: usize,
+    record: &csv::StringRecord,
+) -> Result<Option<ParsedRow>, String> {
+    let field = |index: usize| record.get(index).unwrap_or_default().trim();
+    // Strong fills the columns a set doesn't use with 0
+    let number = |index: Option<usize>, name: &str| -> Result<Option<Decimal>, String> {
+        match index.map(field).filter(|value| !value.is_empty()) {
+            Some(value) => value
+                .parse::<Decimal>()
+                .map(|value| Some(value).filter(|value| !value.is_zero()))
+                .map_err(|_| format!("Invalid {} {}", name, value)),
+            None => Ok(None),
+        }
+    };
+
+    let duration_seconds = number(columns.duration, "duration")?
+        .map(|seconds| {
+            i32::try_from(seconds.round()).map_err(|_| format!("Invalid duration {}", seconds))
+        })
+        .transpose()?;
+    let distance_meters = number(columns.distance, "distance")?.map(|distance| {
+        let per_unit = match columns.distance_unit.unwrap_or(unit) {
+            WeightUnit::Kg => METERS_PER_KILOMETER,
+            WeightUnit::Lb => METERS_PER_MILE,
+        };
+        (distance * per_unit).round_dp(2)
+    });
+
+    // Sets with neither reps nor a duration or distance were never done
+    let reps = number(Some(columns.reps), "reps")?
+        .filter(|reps| reps.is_sign_positive())
+        .unwrap_or_default();
+    if reps.is_zero() && duration_seconds.is_none() && distance_meters.is_none() {
+        return Ok(None);
+    }
+    let reps = i16::try_from(reps.round()).map_err(|_| format!("Invalid reps {}", reps))?;
+
+    let weight = match columns
+        .weight
+        .map(field)
+        .filter(|weight| !weight.is_empty())
+    {
+        Some(weight) => weight
+            .parse::<Decimal>()
+            .map_err(|_| format!("Invalid weight {}", weight))?,
+        None => Decimal::ZERO,
+    };
+    let weight = to_storage_weight(weight, columns.weight_unit.unwrap_or(unit));
+
+    let rir = match columns.rpe.map(field).filter(|rpe| !rpe.is_empty()) {
+        Some(rpe) => {
+            let rpe = rpe
+                .parse::<f32>()
+                .map_err(|_| format!("Invalid RPE {}", rpe))?;
+            Some((10.0 - rpe).round().clamp(0.0, 10.0) as i16)
+        }
+        None => None,
+    };
+
+    let exercise = field(columns.exercise);
+    if exercise.is_empty() {
+        return Err("Missing exercise name".to_string());
+    }
+
+    Ok(Some(ParsedRow {
+        line,
+        workout: field(columns.workout).to_string(),
+        started_at: parse_date(source, field(columns.date))?,
+        exercise: exercise.to_string(),
+        weight,
+        reps,
+        rir,
+        duration_seconds,
+        distance_meters,
+    }))
+}
+
+// Neither app exports a time zone, the times are taken as UTC
+fn parse_date(source: ImportSource, date: &str) -> Result<DateTime<Utc>, String> {
+    let formats: &[&str] = match source {
+        ImportSource::Strong => &["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"],
+        ImportSource::Hevy => &["%d %b %Y, %H:%M", "%d %b %Y %H:%M", "%Y-%m-%d %H:%M:%S"],
+    };
+    formats
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date, format).ok())
+        .map(|date| date.and_utc())
+        .ok_or_else(|| format!("Invalid date {}", date))
+}
+
+fn normalize(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
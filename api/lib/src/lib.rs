@@ -1,2 +1,20 @@
+mod account;
+pub mod activity;
+pub mod authorization;
+mod body;
+mod coaching;
+mod comments;
+mod etag;
+mod export;
+pub mod idempotency;
+mod import;
+pub mod library;
+pub mod live;
+mod merge;
+mod notes;
 pub mod routines;
 pub mod routines_repository;
+pub mod routines_v2;
+mod substitutes;
+pub mod sync;
+pub mod webhooks;
@@ -0,0 +1,17 @@
+use shared::models::CreateExercise;
+
+use crate::routines_repository::RoutinesRepository;
+
+// The curated exercises every user starts with, built into the binary
+const EXERCISE_LIBRARY: &str = include_str!("../../db/exercise_library.json");
+
+/// Loads the built-in exercise library, meant to run once at startup after the schema.
+///
+/// Missing exercises are added and changed ones updated, matched by name, so running it again
+/// writes nothing. Exercises taken out of the file stay in the library, routines and history may
+/// still use them. Returns how many exercises were written.
+pub async fn seed<R: RoutinesRepository>(repo: &R) -> Result<u64, String> {
+    let exercises: Vec<CreateExercise> = serde_json::from_str(EXERCISE_LIBRARY)
+        .map_err(|e| format!("Invalid exercise library: {}", e))?;
+    repo.seed_exercise_library(&exercises).await
+}
@@ -0,0 +1,322 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use actix_web::{
+    web::{Data, Path, Payload, Query, ReqData},
+    HttpRequest, HttpResponse,
+};
+use actix_ws::{Message, MessageStream, Session as Socket};
+use shared::models::{
+    SessionCommand, SessionEvent, TokenClaims, TrackedMetrics, UnitQuery, WeightUnit,
+};
+use shared::utils::InWeightUnit;
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+use crate::account;
+use crate::activity::{self, ActivityBus};
+use crate::routines_repository::RoutinesRepository;
+use crate::substitutes;
+
+// Events buffered per session before a slow socket starts skipping them
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Fans session events out to every socket subscribed to that session.
+///
+/// Create one per process and register it as app data next to the repository,
+/// handlers that write sets publish through it.
+#[derive(Default)]
+pub struct SessionHub {
+    channels: Mutex<HashMap<Uuid, broadcast::Sender<SessionEvent>>>,
+}
+
+impl SessionHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self, session_id: Uuid) -> broadcast::Receiver<SessionEvent> {
+        let mut channels = self.channels.lock().unwrap();
+        channels
+            .entry(session_id)
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    pub fn publish(&self, session_id: Uuid, event: SessionEvent) {
+        let mut channels = self.channels.lock().unwrap();
+        let Some(sender) = channels.get(&session_id) else {
+            return;
+        };
+        let ended = matches!(event, SessionEvent::SessionEnded { .. });
+        // Dropping the sender closes the subscribed sockets once they've drained the channel
+        if sender.send(event).is_err() || ended {
+            channels.remove(&session_id);
+        }
+    }
+}
+
+pub(crate) async fn session_socket<R: RoutinesRepository>(
+    req: HttpRequest,
+    body: Payload,
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    repo: Data<R>,
+    hub: Data<SessionHub>,
+    bus: Data<ActivityBus>,
+) -> HttpResponse {
+    let session_id = path.into_inner();
+    match repo.get_session(&claims.token_id, &session_id).await {
+        Ok(Some(session)) if session.in_progress => {}
+        Ok(Some(_)) => {
+            return HttpResponse::Conflict().body(format!("Session {} has ended", session_id))
+        }
+        Ok(None) => {
+            return HttpResponse::NotFound().body(format!("Session {} not found", session_id))
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Internal server error: {:?}", e))
+        }
+    }
+
+    let (response, socket, messages) = match actix_ws::handle(&req, body) {
+        Ok(handshake) => handshake,
+        Err(e) => return HttpResponse::from_error(e),
+    };
+    let requested = Query::<UnitQuery>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| query.unit);
+    let weight_unit = account::preferred_unit(repo.get_ref(), &claims.token_id, requested).await;
+    let events = hub.subscribe(session_id);
+    let writer = Writer {
+        user_id: claims.token_id,
+        session_id,
+        weight_unit,
+        repo,
+        hub,
+        bus,
+    };
+    actix_web::rt::spawn(run_socket(writer, socket, messages, events));
+
+    response
+}
+
+// Everything a socket needs to run set writes on behalf of the user that opened it
+struct Writer<R> {
+    user_id: Uuid,
+    session_id: Uuid,
+    // the hub carries kilograms, each socket reads and writes in its own user's unit
+    weight_unit: WeightUnit,
+    repo: Data<R>,
+    hub: Data<SessionHub>,
+    bus: Data<ActivityBus>,
+}
+
+async fn run_socket<R: RoutinesRepository>(
+    writer: Writer<R>,
+    mut socket: Socket,
+    mut messages: MessageStream,
+    mut events: broadcast::Receiver<SessionEvent>,
+) {
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let event = event.in_unit(writer.weight_unit);
+                    if send_event(&mut socket, &event).await.is_err() {
+                        return;
+                    }
+                }
+                // A slow socket missed some events, the client re-fetches the session if it cares
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            message = messages.recv() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let reply = match handle_command(&writer, &text).await {
+                        Ok(reply) => reply,
+                        Err(message) => Some(SessionEvent::Error { message }),
+                    };
+                    if let Some(reply) = reply {
+                        if send_event(&mut socket, &reply).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                Some(Ok(Message::Ping(bytes))) => {
+                    if socket.pong(&bytes).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    socket.close(None).await.ok();
+}
+
+async fn send_event(socket: &mut Socket, event: &SessionEvent) -> Result<(), actix_ws::Closed> {
+    match serde_json::to_string(event) {
+        Ok(json) => socket.text(json).await,
+        Err(e) => {
+            log::error!("Failed to serialize session event: {}", e);
+            Ok(())
+        }
+    }
+}
+
+async fn tracked_metrics<R: RoutinesRepository>(
+    repo: &R,
+    exercise_id: &Uuid,
+) -> Result<TrackedMetrics, String> {
+    repo.get_tracked_metrics(exercise_id)
+        .await?
+        .ok_or_else(|| format!("Exercise {} not found", exercise_id))
+}
+
+// Run a command sent over the socket. Writes reach this socket the same way they reach every
+// other subscriber, through the hub, so only failures and suggestions are answered directly.
+async fn handle_command<R: RoutinesRepository>(
+    writer: &Writer<R>,
+    text: &str,
+) -> Result<Option<SessionEvent>, String> {
+    let Writer {
+        user_id,
+        session_id,
+        weight_unit,
+        repo,
+        hub,
+        bus,
+    } = writer;
+    let session_id = *session_id;
+    let command = serde_json::from_str::<SessionCommand>(text)
+        .map_err(|e| format!("Invalid command: {}", e))?;
+
+    match command {
+        SessionCommand::AddSet { exercise_id, set } => {
+            tracked_metrics(repo.get_ref(), &exercise_id)
+                .await?
+                .check_set(&set)?;
+            let set = set.into_storage_unit(*weight_unit);
+            let set = repo
+                .add_set_performance_to_session(user_id, &session_id, &exercise_id, &set)
+                .await
+                .map_err(|e| format!("{:?}", e))?;
+            activity::check_personal_record(repo.get_ref(), bus, session_id, exercise_id, &set)
+                .await;
+            hub.publish(
+                session_id,
+                SessionEvent::SetAdded {
+                    session_id,
+                    exercise_id,
+                    set,
+                },
+            );
+        }
+        SessionCommand::UpdateSet {
+            exercise_id,
+            performance_id,
+            patch,
+        } => {
+            tracked_metrics(repo.get_ref(), &exercise_id)
+                .await?
+                .check_patch(&patch)?;
+            let patch = patch.into_storage_unit(*weight_unit);
+            let set = repo
+                .patch_set_performance(
+                    user_id,
+                    &session_id,
+                    &exercise_id,
+                    &performance_id,
+                    &patch,
+                    None,
+                )
+                .await
+                .map_err(|e| format!("{:?}", e))?;
+            activity::check_personal_record(repo.get_ref(), bus, session_id, exercise_id, &set)
+                .await;
+            hub.publish(
+                session_id,
+                SessionEvent::SetUpdated {
+                    session_id,
+                    exercise_id,
+                    set,
+                },
+            );
+        }
+        SessionCommand::RemoveSet {
+            exercise_id,
+            performance_id,
+        } => {
+            repo.remove_set_performance_from_session_exercise(
+                user_id,
+                &session_id,
+                &exercise_id,
+                &performance_id,
+            )
+            .await
+            .map_err(|e| format!("{:?}", e))?
+            .ok_or_else(|| format!("Set {} not found", performance_id))?;
+            hub.publish(
+                session_id,
+                SessionEvent::SetRemoved {
+                    session_id,
+                    exercise_id,
+                    performance_id,
+                },
+            );
+        }
+        SessionCommand::SuggestSubstitutes {
+            exercise_id,
+            equipment,
+            limit,
+        } => {
+            let substitutes = substitutes::suggest(
+                repo.get_ref(),
+                user_id,
+                &exercise_id,
+                &equipment,
+                limit.unwrap_or(substitutes::DEFAULT_SUBSTITUTE_LIMIT),
+            )
+            .await?
+            .ok_or_else(|| format!("Exercise {} not found", exercise_id))?;
+            return Ok(Some(SessionEvent::Substitutes {
+                exercise_id,
+                substitutes,
+            }));
+        }
+        // Nothing is stored, the sets logged under the substitute are the swap
+        SessionCommand::SwapExercise {
+            exercise_id,
+            substitute_id,
+        } => {
+            if substitute_id == exercise_id {
+                return Err("An exercise can't be swapped for itself".to_string());
+            }
+            if repo
+                .get_visible_exercise(user_id, &exercise_id)
+                .await?
+                .is_none()
+            {
+                return Err(format!("Exercise {} not found", exercise_id));
+            }
+            let substitute = repo
+                .get_exercises_by_id(&[substitute_id])
+                .await?
+                .pop()
+                .filter(|substitute| substitute.builtin || substitute.owner_id == Some(*user_id))
+                .ok_or_else(|| format!("Exercise {} not found", substitute_id))?;
+            hub.publish(
+                session_id,
+                SessionEvent::ExerciseSwapped {
+                    session_id,
+                    exercise_id,
+                    substitute,
+                },
+            );
+        }
+    }
+
+    Ok(None)
+}
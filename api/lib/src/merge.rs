@@ -0,0 +1,110 @@
+use std::collections::HashSet;
+
+use actix_web::{
+    web::{Data, Json, ReqData},
+    HttpResponse,
+};
+use shared::models::{Exercise, MergeExercises, Role, TokenClaims};
+use shared::search::normalize;
+use uuid::Uuid;
+
+use crate::routines_repository::RoutinesRepository;
+
+// The sources' names and aliases the target doesn't go by yet, compared the way search does
+fn new_aliases(target: &Exercise, sources: &[&Exercise]) -> Vec<String> {
+    let mut known: HashSet<String> = std::iter::once(&target.exercise_name)
+        .chain(&target.aliases)
+        .map(|name| normalize(name))
+        .collect();
+    sources
+        .iter()
+        .flat_map(|source| std::iter::once(&source.exercise_name).chain(&source.aliases))
+        .filter(|name| known.insert(normalize(name)))
+        .cloned()
+        .collect()
+}
+
+// Admins can merge any exercises. Everyone else merges their own into one of theirs or into a
+// library exercise, which doesn't take their names as aliases since nobody but admins edits the
+// library, the report lists them as dropped. A dry run reports the same as the real merge.
+pub(crate) async fn merge_exercises<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    merge: Json<MergeExercises>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let merge = merge.into_inner();
+    if merge.source_ids.is_empty() {
+        return HttpResponse::BadRequest().body("Nothing to merge");
+    }
+    if merge.source_ids.contains(&merge.target_id) {
+        return HttpResponse::BadRequest().body("An exercise can't be merged into itself");
+    }
+
+    let mut seen = HashSet::new();
+    let source_ids: Vec<Uuid> = merge
+        .source_ids
+        .into_iter()
+        .filter(|source_id| seen.insert(*source_id))
+        .collect();
+    let mut exercise_ids = source_ids.clone();
+    exercise_ids.push(merge.target_id);
+    let exercises = match repo.get_exercises_by_id(&exercise_ids).await {
+        Ok(exercises) => exercises,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Internal server error: {:?}", e))
+        }
+    };
+    let find = |exercise_id: &Uuid| {
+        exercises
+            .iter()
+            .find(|exercise| exercise.exercise_id == *exercise_id)
+    };
+
+    let is_admin = claims.role == Role::Admin;
+    let owns = |exercise: &Exercise| exercise.owner_id == Some(claims.token_id);
+    let target = match find(&merge.target_id) {
+        Some(target) if is_admin || target.builtin || owns(target) => target,
+        _ => {
+            return HttpResponse::NotFound().body(format!("Exercise {} not found", merge.target_id))
+        }
+    };
+    let mut sources = Vec::with_capacity(source_ids.len());
+    for source_id in &source_ids {
+        match find(source_id) {
+            Some(source) if is_admin || owns(source) => sources.push(source),
+            Some(source) => {
+                return HttpResponse::Forbidden().body(format!(
+                    "Only your own exercises can be merged, {} isn't",
+                    source.exercise_name
+                ))
+            }
+            None => {
+                return HttpResponse::NotFound().body(format!("Exercise {} not found", source_id))
+            }
+        }
+    }
+
+    let (aliases, dropped_aliases) = if is_admin || owns(target) {
+        (new_aliases(target, &sources), vec![])
+    } else {
+        (vec![], new_aliases(target, &sources))
+    };
+    match repo
+        .merge_exercises(&source_ids, &target.exercise_id, &aliases, merge.dry_run)
+        .await
+    {
+        Ok(mut report) => {
+            report.merged = sources
+                .iter()
+                .map(|source| source.exercise_name.clone())
+                .collect();
+            report.aliases_added = aliases;
+            report.aliases_dropped = dropped_aliases;
+            HttpResponse::Ok().json(report)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use actix_web::{
+    web::{Data, Json, Path, Query, ReqData},
+    HttpResponse,
+};
+use shared::models::{NoteSearchQuery, SessionNotes, TokenClaims};
+use uuid::Uuid;
+
+use crate::routines_repository::RoutinesRepository;
+
+const DEFAULT_SEARCH_LIMIT: i64 = 50;
+const MAX_SEARCH_LIMIT: i64 = 200;
+
+pub(crate) async fn set_session_notes<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    session_notes: Json<SessionNotes>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let session_id = path.into_inner();
+    match repo
+        .set_session_notes(
+            &claims.token_id,
+            &session_id,
+            session_notes.notes.as_deref(),
+        )
+        .await
+    {
+        Ok(Some(session)) => HttpResponse::Ok().json(session),
+        Ok(None) => HttpResponse::NotFound().body(format!("Session {} not found", session_id)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+// Searches the caller's own notes only, coaches don't see their athletes' notes here
+pub(crate) async fn search_notes<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    query: Query<NoteSearchQuery>,
+    repo: Data<R>,
+) -> HttpResponse {
+    if query.q.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Search query can't be empty");
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_SEARCH_LIMIT);
+
+    match repo.search_notes(&claims.token_id, &query.q, limit).await {
+        Ok(hits) => HttpResponse::Ok().json(hits),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
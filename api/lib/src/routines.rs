@@ -1,7 +1,10 @@
 use actix_web::{
     dev::ServiceRequest,
     error::Error,
-    web::{self, delete, get, post, put, scope, Data, Json, Path, Query, ServiceConfig},
+    web::{
+        self, delete, get, post, put, resource, scope, Data, Json, JsonConfig, Path, Query,
+        ReqData, ServiceConfig,
+    },
     HttpMessage, HttpResponse,
 };
 
@@ -15,7 +18,7 @@ use actix_web_httpauth::{
     middleware::HttpAuthentication,
 };
 use argonautica::{Hasher, Verifier};
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, Utc};
 use hmac::{Hmac, Mac};
 use jwt::SignWithKey;
 use jwt::VerifyWithKey;
@@ -23,14 +26,35 @@ use sha2::Sha256;
 use shared::models::TokenClaims;
 
 use shared::models::{
-    AuthUser, CreateExercise, CreateRoutine, CreateTrainingDay, CreateUser, Routine, SearchQuery,
-    SetPerformancePayload, UserNoPassword,
+    AuthUser, CreateExercise, CreateRoutine, CreateTrainingDay, CreateUser, OwnerQuery, Role,
+    Routine, SearchQuery, SessionEvent, SetPerformancePayload, TrackedMetrics, UnitQuery,
+    UpdateRole, UserNoPassword,
 };
+use shared::utils::InWeightUnit;
 use uuid::Uuid;
 
+use crate::account;
+use crate::activity::{self, ActivityBus};
+use crate::authorization::RequireRole;
+use crate::body;
+use crate::coaching;
+use crate::comments;
+use crate::export;
+use crate::idempotency::Idempotency;
+use crate::import;
+use crate::live::{self, SessionHub};
+use crate::merge;
+use crate::notes;
 use crate::routines_repository::RoutinesRepository;
+use crate::substitutes;
+use crate::webhooks;
+
+const DEFAULT_SEARCH_LIMIT: i64 = 50;
+const MAX_SEARCH_LIMIT: i64 = 200;
+// Also how long a role change takes to reach tokens already issued
+const TOKEN_LIFETIME_HOURS: i64 = 12;
 
-async fn validator(
+pub(crate) async fn validator(
     req: ServiceRequest,
     credentials: BearerAuth,
 ) -> Result<ServiceRequest, (Error, ServiceRequest)> {
@@ -40,7 +64,14 @@ async fn validator(
 
     let claims: Result<TokenClaims, &str> = token_string
         .verify_with_key(&key)
-        .map_err(|_| "Invalid token");
+        .map_err(|_| "Invalid token")
+        .and_then(|claims: TokenClaims| {
+            if claims.exp > Utc::now().timestamp() {
+                Ok(claims)
+            } else {
+                Err("Expired token")
+            }
+        });
 
     match claims {
         Ok(value) => {
@@ -66,11 +97,23 @@ pub fn service<R: RoutinesRepository>(cfg: &mut ServiceConfig) {
             .service(
                 scope("/users")
                     .route("/auth", get().to(basic_auth::<R>))
-                    .route("/all", get().to(get_users::<R>))
+                    .service(
+                        resource("/all")
+                            .wrap(RequireRole::admin())
+                            .wrap(HttpAuthentication::bearer(validator))
+                            .route(get().to(get_users::<R>)),
+                    )
+                    .service(
+                        resource("/{user_id}/role")
+                            .wrap(RequireRole::admin())
+                            .wrap(HttpAuthentication::bearer(validator))
+                            .route(put().to(set_user_role::<R>)),
+                    )
                     .route("/create", post().to(create_user::<R>)),
             )
             .service(
                 scope("")
+                    .wrap(Idempotency::<R>::new())
                     .wrap(bearer_middleware)
                     .service(
                         scope("/routines")
@@ -106,6 +149,11 @@ pub fn service<R: RoutinesRepository>(cfg: &mut ServiceConfig) {
                             .route("/bulk", post().to(create_exercises::<R>))
                             .route("", get().to(get_exercises::<R>))
                             .route("/search", get().to(search_exercises::<R>))
+                            .route("/merge", post().to(merge::merge_exercises::<R>))
+                            .route(
+                                "/{exercise_id}/substitutes",
+                                get().to(substitutes::get_substitutes::<R>),
+                            )
                             .route(
                                 "/{exercise_id}/{day_id}",
                                 post().to(add_exercise_to_training_day::<R>),
@@ -118,6 +166,27 @@ pub fn service<R: RoutinesRepository>(cfg: &mut ServiceConfig) {
                     )
                     .service(
                         scope("/session")
+                            .route("/live/{session_id}", get().to(live::session_socket::<R>))
+                            .route(
+                                "/{session_id}/comments",
+                                get().to(comments::get_comments::<R>),
+                            )
+                            .route(
+                                "/{session_id}/comments",
+                                post().to(comments::create_comment::<R>),
+                            )
+                            .route(
+                                "/comments/{comment_id}",
+                                put().to(comments::update_comment::<R>),
+                            )
+                            .route(
+                                "/comments/{comment_id}",
+                                delete().to(comments::delete_comment::<R>),
+                            )
+                            .route(
+                                "/{session_id}/notes",
+                                put().to(notes::set_session_notes::<R>),
+                            )
                             .route("/{day_id}", post().to(create_session::<R>))
                             .route("/{day_id}/all", get().to(get_sessions_by_day_id::<R>))
                             .route(
@@ -142,14 +211,95 @@ pub fn service<R: RoutinesRepository>(cfg: &mut ServiceConfig) {
                             )
                             .route("/end/{session_id}", put().to(end_session::<R>)),
                     )
-                    .route("/debug/link_table", get().to(get_link_table_data::<R>))
-                    .route("/debug/clear_data", get().to(clear_data::<R>)),
+                    .service(
+                        scope("/sync")
+                            .route("/push", post().to(crate::sync::push::<R>))
+                            .route("/pull", get().to(crate::sync::pull::<R>)),
+                    )
+                    .route("/activity", get().to(activity::stream))
+                    .route("/notes/search", get().to(notes::search_notes::<R>))
+                    .service(
+                        scope("/body")
+                            .route("/measurements", post().to(body::log_body_measurement::<R>))
+                            .route("/measurements", get().to(body::get_body_measurements::<R>))
+                            .route(
+                                "/measurements/{measurement_id}",
+                                get().to(body::get_body_measurement::<R>),
+                            )
+                            .route(
+                                "/measurements/{measurement_id}",
+                                put().to(body::patch_body_measurement::<R>),
+                            )
+                            .route(
+                                "/measurements/{measurement_id}",
+                                delete().to(body::delete_body_measurement::<R>),
+                            )
+                            .route("/trend", get().to(body::get_body_trend::<R>))
+                            .route("/bodyweight", get().to(body::get_bodyweight_as_of::<R>)),
+                    )
+                    .service(
+                        scope("/account")
+                            .route("", delete().to(account::delete_account::<R>))
+                            .route("/export", get().to(account::export_account::<R>))
+                            .route("/preferences", get().to(account::get_preferences::<R>))
+                            .route("/preferences", put().to(account::update_preferences::<R>)),
+                    )
+                    .service(
+                        scope("/coaching")
+                            .service(
+                                resource("/invitations")
+                                    .wrap(RequireRole::new(&[Role::Coach, Role::Admin]))
+                                    .route(post().to(coaching::create_invitation::<R>)),
+                            )
+                            .route(
+                                "/invitations/{link_id}/accept",
+                                put().to(coaching::accept_invitation::<R>),
+                            )
+                            .route("/coaches", get().to(coaching::get_coaches::<R>))
+                            .route(
+                                "/coaches/{link_id}/permissions",
+                                put().to(coaching::update_permissions::<R>),
+                            )
+                            .route("/athletes", get().to(coaching::get_athletes::<R>))
+                            .route("/links/{link_id}", delete().to(coaching::delete_link::<R>))
+                            .route("/dashboard", get().to(coaching::get_dashboard::<R>)),
+                    )
+                    .route("/export/history", get().to(export::export_history::<R>))
+                    .service(
+                        resource("/import")
+                            .app_data(JsonConfig::default().limit(import::IMPORT_BODY_LIMIT))
+                            .route(post().to(import::import_history::<R>)),
+                    )
+                    .service(
+                        scope("/webhooks")
+                            .route("", get().to(webhooks::get_webhooks::<R>))
+                            .route("", post().to(webhooks::create_webhook::<R>))
+                            .route("/{webhook_id}", delete().to(webhooks::delete_webhook::<R>))
+                            .route(
+                                "/{webhook_id}/deliveries",
+                                get().to(webhooks::get_webhook_deliveries::<R>),
+                            )
+                            .route(
+                                "/{webhook_id}/deliveries/{delivery_id}/redeliver",
+                                post().to(webhooks::redeliver::<R>),
+                            ),
+                    )
+                    .service(
+                        scope("/debug")
+                            .wrap(RequireRole::admin())
+                            .route("/link_table", get().to(get_link_table_data::<R>))
+                            .route("/clear_data", delete().to(clear_data::<R>)),
+                    ),
             ),
     );
+    cfg.configure(crate::routines_v2::service::<R>);
 }
 
 //Auth
-async fn basic_auth<R: RoutinesRepository>(credentials: BasicAuth, repo: Data<R>) -> HttpResponse {
+pub(crate) async fn basic_auth<R: RoutinesRepository>(
+    credentials: BasicAuth,
+    repo: Data<R>,
+) -> HttpResponse {
     println!("hello from basic auth");
     let jwt_secret: Hmac<Sha256> = Hmac::new_from_slice(
         std::env::var("JWT_SECRET")
@@ -176,6 +326,9 @@ async fn basic_auth<R: RoutinesRepository>(credentials: BasicAuth, repo: Data<R>
                 if is_valid {
                     let claims = TokenClaims {
                         token_id: user.user_id,
+                        role: user.role,
+                        exp: (Utc::now() + chrono::Duration::hours(TOKEN_LIFETIME_HOURS))
+                            .timestamp(),
                     };
                     let token_str = claims.sign_with_key(&jwt_secret).unwrap();
                     HttpResponse::Ok().json(token_str)
@@ -189,7 +342,7 @@ async fn basic_auth<R: RoutinesRepository>(credentials: BasicAuth, repo: Data<R>
 }
 
 // USERS
-async fn create_user<R: RoutinesRepository>(
+pub(crate) async fn create_user<R: RoutinesRepository>(
     create_user: Json<CreateUser>,
     repo: Data<R>,
 ) -> HttpResponse {
@@ -214,34 +367,71 @@ async fn create_user<R: RoutinesRepository>(
     }
 }
 
-async fn get_users<R: RoutinesRepository>(repo: Data<R>) -> HttpResponse {
+pub(crate) async fn get_users<R: RoutinesRepository>(repo: Data<R>) -> HttpResponse {
     match repo.get_users().await {
         Ok(users) => HttpResponse::Ok().json(users),
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
     }
 }
 
+pub(crate) async fn set_user_role<R: RoutinesRepository>(
+    path: Path<Uuid>,
+    update_role: Json<UpdateRole>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let user_id = path.into_inner();
+    match repo.set_user_role(&user_id, update_role.role).await {
+        Ok(Some(user)) => HttpResponse::Ok().json(user),
+        Ok(None) => HttpResponse::NotFound().body(format!("User {} not found", user_id)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
 // ROUTINES
-async fn get_all_routines<R: RoutinesRepository>(repo: Data<R>) -> HttpResponse {
-    match repo.get_routines().await {
+pub(crate) async fn get_all_routines<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    query: Query<OwnerQuery>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let owner_id = query.user_id.unwrap_or(claims.token_id);
+    match repo.get_routines(&claims.token_id, &owner_id).await {
         Ok(routines) => HttpResponse::Ok().json(routines),
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
     }
 }
 
-async fn get_active_routine<R: RoutinesRepository>(repo: Data<R>) -> HttpResponse {
-    match repo.get_active_routines().await {
+pub(crate) async fn get_active_routine<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    query: Query<OwnerQuery>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let owner_id = query.user_id.unwrap_or(claims.token_id);
+    match repo.get_active_routines(&claims.token_id, &owner_id).await {
         Ok(routine_id) => HttpResponse::Ok().json(routine_id),
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
     }
 }
 
-async fn create_routine<R: RoutinesRepository>(
+pub(crate) async fn create_routine<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     create_routine: Json<CreateRoutine>,
     repo: Data<R>,
+    bus: Data<ActivityBus>,
 ) -> HttpResponse {
-    match repo.create_routine(&create_routine).await {
-        Ok(routine) => HttpResponse::Ok().json(routine),
+    let owner_id = create_routine.user_id.unwrap_or(claims.token_id);
+    match repo.create_routine(&claims.token_id, &create_routine).await {
+        Ok(Some(routine)) => {
+            if routine.is_active {
+                activity::routine_activated(&bus, owner_id, &routine);
+            }
+            HttpResponse::Ok().json(routine)
+        }
+        Ok(None) => HttpResponse::Forbidden().body(format!(
+            "Not allowed to create routines for user {}",
+            owner_id
+        )),
         Err(e) => {
             HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
         }
@@ -249,23 +439,31 @@ async fn create_routine<R: RoutinesRepository>(
 }
 
 async fn update_routine<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     routine: Json<Routine>,
     repo: Data<R>,
+    bus: Data<ActivityBus>,
 ) -> HttpResponse {
-    match repo.update_routine(&routine).await {
-        Ok(routine) => HttpResponse::Ok().json(routine),
+    match repo.update_routine(&claims.token_id, &routine).await {
+        Ok(routine) => {
+            if routine.is_active {
+                activity::owner_routine_activated(repo.get_ref(), &bus, &routine).await;
+            }
+            HttpResponse::Ok().json(routine)
+        }
         Err(e) => {
             HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
         }
     }
 }
 
-async fn delete_routine<R: RoutinesRepository>(
+pub(crate) async fn delete_routine<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     routine_id: Path<Uuid>,
     repo: Data<R>,
 ) -> HttpResponse {
     let routine_id = routine_id.into_inner();
-    match repo.delete_routine(&routine_id).await {
+    match repo.delete_routine(&claims.token_id, &routine_id).await {
         Ok(routine_id) => HttpResponse::Ok().json(routine_id),
         Err(e) => {
             HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
@@ -274,30 +472,42 @@ async fn delete_routine<R: RoutinesRepository>(
 }
 
 // TRAINING DAYS
-async fn get_training_days<R: RoutinesRepository>(path: Path<Uuid>, repo: Data<R>) -> HttpResponse {
+async fn get_training_days<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    repo: Data<R>,
+) -> HttpResponse {
     let routine_id = path.into_inner();
-    match repo.get_training_days(&routine_id).await {
+    match repo.get_training_days(&claims.token_id, &routine_id).await {
         Ok(training_days) => HttpResponse::Ok().json(training_days),
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
     }
 }
 
 async fn get_training_days_with_exercises<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     path: Path<Uuid>,
     repo: Data<R>,
 ) -> HttpResponse {
     let routine_id = path.into_inner();
-    match repo.get_training_days_with_exercises(&routine_id).await {
+    match repo
+        .get_training_days_with_exercises(&claims.token_id, &routine_id)
+        .await
+    {
         Ok(training_days) => HttpResponse::Ok().json(training_days),
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
     }
 }
 
 async fn create_training_day<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     create_training_day: Json<CreateTrainingDay>,
     repo: Data<R>,
 ) -> HttpResponse {
-    match repo.create_training_day(&create_training_day).await {
+    match repo
+        .create_training_day(&claims.token_id, &create_training_day)
+        .await
+    {
         Ok(day) => HttpResponse::Ok().json(day),
         Err(e) => {
             HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
@@ -306,11 +516,12 @@ async fn create_training_day<R: RoutinesRepository>(
 }
 
 async fn delete_training_day<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     path: Path<Uuid>,
     repo: Data<R>,
 ) -> HttpResponse {
     let day_id = path.into_inner();
-    match repo.delete_training_day(&day_id).await {
+    match repo.delete_training_day(&claims.token_id, &day_id).await {
         Ok(day_id) => HttpResponse::Ok().json(day_id),
         Err(e) => {
             HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
@@ -319,10 +530,14 @@ async fn delete_training_day<R: RoutinesRepository>(
 }
 
 async fn create_training_days<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     create_training_days: Json<Vec<CreateTrainingDay>>,
     repo: Data<R>,
 ) -> HttpResponse {
-    match repo.create_training_days(&create_training_days).await {
+    match repo
+        .create_training_days(&claims.token_id, &create_training_days)
+        .await
+    {
         Ok(days) => HttpResponse::Ok().json(days),
         Err(e) => {
             HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
@@ -331,41 +546,65 @@ async fn create_training_days<R: RoutinesRepository>(
 }
 
 // EXERCISES
-async fn get_exercises<R: RoutinesRepository>(repo: Data<R>) -> HttpResponse {
-    match repo.get_exercises().await {
+// The library and the caller's own exercises
+pub(crate) async fn get_exercises<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    repo: Data<R>,
+) -> HttpResponse {
+    match repo.get_exercises(&claims.token_id).await {
         Ok(exercises) => HttpResponse::Ok().json(exercises),
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
     }
 }
 
-async fn search_exercises<R: RoutinesRepository>(
+// Fuzzy search over names and aliases, best match first with the caller's recently logged
+// exercises ahead of the rest
+pub(crate) async fn search_exercises<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     query: Query<SearchQuery>,
     repo: Data<R>,
 ) -> HttpResponse {
-    let name: &String = &query.name;
-    match repo.search_exercises(&name).await {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_SEARCH_LIMIT);
+    match repo.search_exercises(&claims.token_id, &query, limit).await {
         Ok(exercises) => HttpResponse::Ok().json(exercises),
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
     }
 }
 
-async fn create_exercise<R: RoutinesRepository>(
+// A custom exercise only the caller sees, in place of a library exercise with the same name
+pub(crate) async fn create_exercise<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     create_exercise: Json<CreateExercise>,
     repo: Data<R>,
 ) -> HttpResponse {
-    match repo.create_exercise(&create_exercise).await {
-        Ok(exercise) => HttpResponse::Ok().json(exercise),
+    match repo
+        .create_exercise(&claims.token_id, &create_exercise)
+        .await
+    {
+        Ok(Some(exercise)) => HttpResponse::Ok().json(exercise),
+        Ok(None) => HttpResponse::Conflict().body(format!(
+            "You already have an exercise named {}",
+            create_exercise.exercise_name
+        )),
         Err(e) => {
             HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
         }
     }
 }
 
-async fn create_exercises<R: RoutinesRepository>(
+// Names the caller already has are skipped, so the same list can be posted again
+pub(crate) async fn create_exercises<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     create_exercises: Json<Vec<CreateExercise>>,
     repo: Data<R>,
 ) -> HttpResponse {
-    match repo.create_exercises(&create_exercises).await {
+    match repo
+        .create_exercises(&claims.token_id, &create_exercises)
+        .await
+    {
         Ok(exercises) => HttpResponse::Ok().json(exercises),
         Err(e) => {
             HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
@@ -374,12 +613,13 @@ async fn create_exercises<R: RoutinesRepository>(
 }
 
 async fn add_exercise_to_training_day<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     path: Path<(Uuid, Uuid)>,
     repo: Data<R>,
 ) -> HttpResponse {
     let (exercise_id, day_id) = path.into_inner();
     match repo
-        .add_exercise_to_training_day(&exercise_id, &day_id)
+        .add_exercise_to_training_day(&claims.token_id, &exercise_id, &day_id)
         .await
     {
         Ok(exercise_day_link) => HttpResponse::Ok().json(exercise_day_link),
@@ -390,22 +630,30 @@ async fn add_exercise_to_training_day<R: RoutinesRepository>(
 }
 
 async fn get_exercises_for_training_day<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     path: Path<Uuid>,
     repo: web::Data<R>,
 ) -> HttpResponse {
     let day_id = path.into_inner();
-    match repo.get_exercises_for_training_day(&day_id).await {
+    match repo
+        .get_exercises_for_training_day(&claims.token_id, &day_id)
+        .await
+    {
         Ok(exercises) => HttpResponse::Ok().json(exercises),
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
     }
 }
 
 async fn delete_exercise_from_training_day<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     path: web::Path<Uuid>,
     repo: web::Data<R>,
 ) -> HttpResponse {
     let link_id = path.into_inner();
-    match repo.remove_exercise_from_training_day(&link_id).await {
+    match repo
+        .remove_exercise_from_training_day(&claims.token_id, &link_id)
+        .await
+    {
         Ok(link_id) => HttpResponse::Ok().json(link_id),
         Err(e) => {
             HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
@@ -413,7 +661,7 @@ async fn delete_exercise_from_training_day<R: RoutinesRepository>(
     }
 }
 
-async fn get_link_table_data<R: RoutinesRepository>(repo: web::Data<R>) -> HttpResponse {
+pub(crate) async fn get_link_table_data<R: RoutinesRepository>(repo: web::Data<R>) -> HttpResponse {
     match repo.get_link_table_data().await {
         Ok(link_table_data) => HttpResponse::Ok().json(link_table_data),
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
@@ -422,101 +670,196 @@ async fn get_link_table_data<R: RoutinesRepository>(repo: web::Data<R>) -> HttpR
 
 //SESSIONS
 async fn create_session<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     path: web::Path<Uuid>,
+    query: Query<UnitQuery>,
     repo: web::Data<R>,
+    bus: web::Data<ActivityBus>,
+    hub: web::Data<SessionHub>,
 ) -> HttpResponse {
     let day_id = path.into_inner();
-    match repo.create_session(&day_id).await {
-        Ok(session_with_exercises) => HttpResponse::Ok().json(session_with_exercises),
+    activity::close_stale_sessions(repo.get_ref(), &bus, &hub, claims.token_id).await;
+    match repo.create_session(&claims.token_id, &day_id).await {
+        Ok(session_with_exercises) => {
+            activity::session_started(&bus, claims.token_id, &session_with_exercises);
+            let unit = account::preferred_unit(repo.get_ref(), &claims.token_id, query.unit).await;
+            HttpResponse::Ok().json(session_with_exercises.in_unit(unit))
+        }
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
     }
 }
 
 async fn get_sessions_by_day_id<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     path: web::Path<Uuid>,
     repo: web::Data<R>,
 ) -> HttpResponse {
     let day_id = path.into_inner();
-    match repo.get_all_sessions_by_day_id(&day_id).await {
+    match repo
+        .get_all_sessions_by_day_id(&claims.token_id, &day_id)
+        .await
+    {
         Ok(sessions) => HttpResponse::Ok().json(sessions),
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
     }
 }
 
 async fn get_sessions_with_exercises_by_day_id<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     path: web::Path<Uuid>,
     repo: web::Data<R>,
 ) -> HttpResponse {
     let day_id = path.into_inner();
-    match repo.get_sessions_with_exercises(&day_id).await {
+    match repo
+        .get_sessions_with_exercises(&claims.token_id, &day_id)
+        .await
+    {
         Ok(sessions_with_exercises) => HttpResponse::Ok().json(sessions_with_exercises),
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error {:?}", e)),
     }
 }
 
-async fn get_session_in_progress<R: RoutinesRepository>(
+pub(crate) async fn get_session_in_progress<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     path: web::Path<Uuid>,
+    query: Query<UnitQuery>,
     repo: web::Data<R>,
 ) -> HttpResponse {
     let routine_id = path.into_inner();
-    match repo.get_session_in_progress(&routine_id).await {
-        Ok(session_with_exercises) => HttpResponse::Ok().json(session_with_exercises),
+    match repo
+        .get_session_in_progress(&claims.token_id, &routine_id)
+        .await
+    {
+        Ok(session_with_exercises) => {
+            let unit = account::preferred_unit(repo.get_ref(), &claims.token_id, query.unit).await;
+            HttpResponse::Ok().json(session_with_exercises.in_unit(unit))
+        }
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
     }
 }
 
-async fn get_all_sessions_by_routine_id<R: RoutinesRepository>(
+pub(crate) async fn get_all_sessions_by_routine_id<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     path: web::Path<Uuid>,
     repo: web::Data<R>,
 ) -> HttpResponse {
     let routine_id = path.into_inner();
-    match repo.get_all_sessions_by_routine_id(&routine_id).await {
+    match repo
+        .get_all_sessions_by_routine_id(&claims.token_id, &routine_id)
+        .await
+    {
         Ok(sessions) => HttpResponse::Ok().json(sessions),
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
     }
 }
 
-async fn end_session<R: RoutinesRepository>(
+pub(crate) async fn end_session<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     path: web::Path<Uuid>,
     repo: web::Data<R>,
+    hub: web::Data<SessionHub>,
+    bus: web::Data<ActivityBus>,
 ) -> HttpResponse {
     let session_id = path.into_inner();
-    match repo.end_session(&session_id).await {
-        Ok(session_id) => HttpResponse::Ok().json(session_id),
+    match repo.end_session(&claims.token_id, &session_id).await {
+        Ok(session_id) => {
+            hub.publish(session_id, SessionEvent::SessionEnded { session_id });
+            activity::session_completed(repo.get_ref(), &bus, session_id).await;
+            HttpResponse::Ok().json(session_id)
+        }
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
     }
 }
 
+// Refuses a set that doesn't record what its exercise tracks, the response to send back if so
+pub(crate) async fn check_set_metrics<R: RoutinesRepository>(
+    repo: &R,
+    exercise_id: &Uuid,
+    check: impl FnOnce(&TrackedMetrics) -> Result<(), String>,
+) -> Result<(), HttpResponse> {
+    match repo.get_tracked_metrics(exercise_id).await {
+        Ok(Some(metrics)) => check(&metrics).map_err(|e| HttpResponse::BadRequest().body(e)),
+        Ok(None) => {
+            Err(HttpResponse::NotFound().body(format!("Exercise {} not found", exercise_id)))
+        }
+        Err(e) => {
+            Err(HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e)))
+        }
+    }
+}
+
 async fn add_set_performance_to_session<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     path: web::Path<(Uuid, Uuid)>,
     set_performance: web::Json<SetPerformancePayload>,
     repo: web::Data<R>,
+    hub: web::Data<SessionHub>,
+    bus: web::Data<ActivityBus>,
 ) -> HttpResponse {
     let (session_id, exercise_id) = path.into_inner();
+    if let Err(response) = check_set_metrics(repo.get_ref(), &exercise_id, |metrics| {
+        metrics.check_set(&set_performance)
+    })
+    .await
+    {
+        return response;
+    }
+    let unit =
+        account::preferred_unit(repo.get_ref(), &claims.token_id, set_performance.unit).await;
+    let set_performance = set_performance.into_inner().into_storage_unit(unit);
     match repo
-        .add_set_performance_to_session(&session_id, &exercise_id, &set_performance)
+        .add_set_performance_to_session(
+            &claims.token_id,
+            &session_id,
+            &exercise_id,
+            &set_performance,
+        )
         .await
     {
-        Ok(session_id) => HttpResponse::Ok().json(session_id),
+        Ok(set) => {
+            activity::check_personal_record(repo.get_ref(), &bus, session_id, exercise_id, &set)
+                .await;
+            hub.publish(
+                session_id,
+                SessionEvent::SetAdded {
+                    session_id,
+                    exercise_id,
+                    set: set.clone(),
+                },
+            );
+            HttpResponse::Ok().json(set.in_unit(unit))
+        }
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
     }
 }
 
 async fn remove_set_performance_from_session<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
     path: web::Path<Uuid>,
     repo: web::Data<R>,
+    hub: web::Data<SessionHub>,
 ) -> HttpResponse {
     let performance_id = path.into_inner();
     match repo
-        .remove_set_performance_from_session(&performance_id)
+        .remove_set_performance_from_session(&claims.token_id, &performance_id)
         .await
     {
-        Ok(session_id) => HttpResponse::Ok().json(session_id),
+        Ok(removed) => {
+            hub.publish(
+                removed.session_id,
+                SessionEvent::SetRemoved {
+                    session_id: removed.session_id,
+                    exercise_id: removed.exercise_id,
+                    performance_id: removed.performance_id,
+                },
+            );
+            HttpResponse::Ok().json(removed.performance_id)
+        }
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
     }
 }
 
-async fn clear_data<R: RoutinesRepository>(repo: web::Data<R>) -> HttpResponse {
+pub(crate) async fn clear_data<R: RoutinesRepository>(repo: web::Data<R>) -> HttpResponse {
     match repo.clear_data().await {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
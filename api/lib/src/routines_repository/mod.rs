@@ -1,8 +1,17 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::stream::BoxStream;
 pub use postgres_routines_repository::PostgresRoutinesRepository;
+use rust_decimal::Decimal;
 use shared::models::{
-    CreateExercise, CreateRoutine, CreateTrainingDay, CreateUser, Exercise, ExerciseToTrainingDay,
-    ExerciseWithLinkId, Routine, Session, SessionWithExercisePerformance, SessionWithExercises,
-    SetPerformance, SetPerformancePayload, TrainingDay, TrainingDayWithExercises, User,
+    AccountExport, BodyMeasurement, BodyMetric, BodyTrendPoint, CoachDashboardEntry,
+    CoachPermissions, CoachingLink, Comment, CreateBodyMeasurement, CreateComment, CreateExercise,
+    CreateRoutine, CreateTrainingDay, CreateUser, Exercise, ExerciseSearchHit,
+    ExerciseToTrainingDay, ExerciseWithLinkId, HistoryExportQuery, HistoryExportRow, MergeReport,
+    NoteSearchHit, PatchBodyMeasurement, PatchExercise, PatchExerciseToTrainingDay, PatchRoutine,
+    PatchSetPerformance, PatchTrainingDay, PersonalRecord, Role, Routine, SearchQuery, Session,
+    SessionWithExercisePerformance, SessionWithExercises, SetPerformance, SetPerformancePayload,
+    SyncMutation, SyncPullResponse, SyncPushResponse, TrackedMetrics, TrainingDay,
+    TrainingDayWithExercises, User, UserNoPassword, Webhook, WebhookDelivery, WeightUnit,
 };
 
 use uuid::Uuid;
@@ -38,78 +47,296 @@ impl<'a> From<&'a str> for SessionError {
 
 pub type SessionResult<T> = Result<T, SessionError>;
 
+#[derive(Debug)]
+pub enum PatchError {
+    NotFound,
+    // the row exists but its updated_at no longer matches the version the client sent
+    PreconditionFailed,
+    // the patch would clash with another row, like a set number already used
+    Conflict(String),
+    Error(String),
+}
+
+impl From<String> for PatchError {
+    fn from(msg: String) -> Self {
+        PatchError::Error(msg)
+    }
+}
+
+pub type PatchResult<T> = Result<T, PatchError>;
+
+// What a deleted set belonged to, so live subscribers of the session can be told
+#[derive(sqlx::FromRow, Debug, Clone, Copy)]
+pub struct RemovedSetPerformance {
+    pub performance_id: Uuid,
+    pub session_id: Uuid,
+    pub exercise_id: Uuid,
+}
+
+// A set with the session and exercise it was logged for, to check sets written by sync for PRs
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct LoggedSet {
+    pub session_id: Uuid,
+    pub exercise_id: Uuid,
+    #[sqlx(flatten)]
+    pub set: SetPerformance,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct IdempotencyRecord {
+    pub request_hash: String,
+    pub status_code: Option<i16>,
+    pub content_type: Option<String>,
+    pub response_body: Option<Vec<u8>>,
+}
+
+// Where and how to send a webhook delivery
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct WebhookTarget {
+    pub url: String,
+    pub secret: String,
+    pub is_active: bool,
+}
+
+// A finished session from another app, ready to be written
+#[derive(Debug, Clone)]
+pub struct ImportedSession {
+    pub day_name: String,
+    pub started_at: DateTime<Utc>,
+    pub sets: Vec<ImportedSet>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedSet {
+    pub exercise_id: Uuid,
+    pub set_number: i16,
+    pub weight: Decimal,
+    pub reps: i16,
+    pub rir: Option<i16>,
+    pub duration_seconds: Option<i32>,
+    pub distance_meters: Option<Decimal>,
+}
+
+// A written comment and the owner of the session it is on, both of whom are told about it
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct SavedComment {
+    #[sqlx(flatten)]
+    pub comment: Comment,
+    pub session_owner: Option<Uuid>,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, Copy)]
+pub struct RemovedComment {
+    pub comment_id: Uuid,
+    pub session_id: Uuid,
+    pub author_id: Uuid,
+    pub session_owner: Option<Uuid>,
+}
+
+pub enum IdempotencyReservation {
+    // the key is new (or its previous use expired), the caller should run the request
+    Reserved,
+    Existing(IdempotencyRecord),
+}
+
+#[cfg_attr(test, mockall::automock)]
 #[async_trait::async_trait]
 pub trait RoutinesRepository: Send + Sync + 'static {
     //users
     async fn create_user(&self, create_user: &CreateUser) -> RoutineResult<User>;
-    async fn get_users(&self) -> RoutineResult<Vec<User>>;
+    async fn get_users(&self) -> RoutineResult<Vec<UserNoPassword>>;
     async fn get_user(&self, username: &str) -> RoutineResult<User>;
+    async fn set_user_role(
+        &self,
+        user_id: &Uuid,
+        role: Role,
+    ) -> RoutineResult<Option<UserNoPassword>>;
+    // The unit the user reads and writes weights in
+    async fn get_weight_unit(&self, user_id: &Uuid) -> RoutineResult<WeightUnit>;
+    async fn set_weight_unit(
+        &self,
+        user_id: &Uuid,
+        weight_unit: WeightUnit,
+    ) -> RoutineResult<Option<UserNoPassword>>;
+    // Everything below that reads or writes routines, days, links, sessions or sets runs for
+    // `user_id`, the caller. Rows the caller may not see (see `has_access` in the schema)
+    // behave as if they didn't exist.
+
     // routines
-    async fn get_routines(&self) -> RoutineResult<Vec<Routine>>;
-    async fn get_active_routines(&self) -> RoutineResult<Vec<Routine>>;
-    // async fn get_routine(&self, id: &Uuid) -> RoutineResult<Routine>;
-    async fn create_routine(&self, create_routine: &CreateRoutine) -> RoutineResult<Routine>;
-    async fn update_routine(&self, routine: &Routine) -> RoutineResult<Routine>;
-    async fn delete_routine(&self, routine_id: &Uuid) -> RoutineResult<Uuid>;
+    // Routines owned by `owner_id`
+    async fn get_routines(&self, user_id: &Uuid, owner_id: &Uuid) -> RoutineResult<Vec<Routine>>;
+    async fn get_active_routines(
+        &self,
+        user_id: &Uuid,
+        owner_id: &Uuid,
+    ) -> RoutineResult<Vec<Routine>>;
+    async fn get_routine(
+        &self,
+        user_id: &Uuid,
+        routine_id: &Uuid,
+    ) -> RoutineResult<Option<Routine>>;
+    // Created for `create_routine.user_id` when given, None when the caller can't edit their routines
+    async fn create_routine(
+        &self,
+        user_id: &Uuid,
+        create_routine: &CreateRoutine,
+    ) -> RoutineResult<Option<Routine>>;
+    async fn update_routine(&self, user_id: &Uuid, routine: &Routine) -> RoutineResult<Routine>;
+    async fn delete_routine(&self, user_id: &Uuid, routine_id: &Uuid) -> RoutineResult<Uuid>;
 
     // training days
-    async fn get_training_days(&self, routine_id: &Uuid) -> TrainingDayResult<Vec<TrainingDay>>;
+    async fn get_training_days(
+        &self,
+        user_id: &Uuid,
+        routine_id: &Uuid,
+    ) -> TrainingDayResult<Vec<TrainingDay>>;
+    async fn get_training_day(
+        &self,
+        user_id: &Uuid,
+        day_id: &Uuid,
+    ) -> TrainingDayResult<Option<TrainingDay>>;
     async fn get_training_days_with_exercises(
         &self,
+        user_id: &Uuid,
         day_id: &Uuid,
     ) -> Result<Vec<TrainingDayWithExercises>, sqlx::Error>;
     async fn create_training_day(
         &self,
+        user_id: &Uuid,
         create_training_day: &CreateTrainingDay,
     ) -> TrainingDayResult<TrainingDay>;
-    async fn delete_training_day(&self, day_id: &Uuid) -> TrainingDayResult<Option<Uuid>>;
+    async fn delete_training_day(
+        &self,
+        user_id: &Uuid,
+        day_id: &Uuid,
+    ) -> TrainingDayResult<Option<Uuid>>;
     async fn create_training_days(
         &self,
+        user_id: &Uuid,
         create_training_days: &[CreateTrainingDay],
     ) -> TrainingDayResult<Vec<TrainingDay>>;
 
     // exercises
-    async fn get_exercises(&self) -> ExerciseResult<Vec<Exercise>>;
-    async fn search_exercises(&self, name: &String) -> ExerciseResult<Vec<Exercise>>;
-    async fn create_exercise(&self, id: &CreateExercise) -> ExerciseResult<Exercise>;
-    async fn create_exercises(&self, exercises: &[CreateExercise])
-        -> ExerciseResult<Vec<Exercise>>;
+    // The library and `user_id`'s own exercises, theirs in place of library ones with the same
+    // name
+    async fn get_exercises(&self, user_id: &Uuid) -> ExerciseResult<Vec<Exercise>>;
+    // Filters on the query's attributes and ranks by how well the name or an alias matches,
+    // exercises `user_id` logged lately first. See `shared::search`.
+    async fn search_exercises(
+        &self,
+        user_id: &Uuid,
+        query: &SearchQuery,
+        limit: i64,
+    ) -> ExerciseResult<Vec<ExerciseSearchHit>>;
+    // None when `user_id` already has an exercise by that name
+    async fn create_exercise(
+        &self,
+        user_id: &Uuid,
+        exercise: &CreateExercise,
+    ) -> ExerciseResult<Option<Exercise>>;
+    // Names `user_id` already has are skipped, only the created exercises are returned
+    async fn create_exercises(
+        &self,
+        user_id: &Uuid,
+        exercises: &[CreateExercise],
+    ) -> ExerciseResult<Vec<Exercise>>;
+    // Adds library exercises that are missing and updates the ones that changed, matched by
+    // name. Returns how many were written.
+    async fn seed_exercise_library(&self, exercises: &[CreateExercise]) -> ExerciseResult<u64>;
+    async fn get_exercises_by_id(&self, exercise_ids: &[Uuid]) -> ExerciseResult<Vec<Exercise>>;
+    // The exercise if the user may look at it: a library exercise, one of theirs, or one in a
+    // routine or session of someone else's they can read, like their coach's
+    async fn get_visible_exercise(
+        &self,
+        user_id: &Uuid,
+        exercise_id: &Uuid,
+    ) -> ExerciseResult<Option<Exercise>>;
+    // Moves the links, sets, records and comments of `source_ids` to `target_id`, appends
+    // `aliases` to it and deletes the sources, all in one transaction that a dry run rolls back.
+    // Moved sets are numbered after the target's in their session. The report's names are left
+    // to the caller.
+    async fn merge_exercises(
+        &self,
+        source_ids: &[Uuid],
+        target_id: &Uuid,
+        aliases: &[String],
+        dry_run: bool,
+    ) -> ExerciseResult<MergeReport>;
     async fn add_exercise_to_training_day(
         &self,
+        user_id: &Uuid,
         exercise_id: &Uuid,
         day_id: &Uuid,
     ) -> ExerciseToTrainingDayResult<ExerciseToTrainingDay>;
     async fn get_exercises_for_training_day(
         &self,
+        user_id: &Uuid,
         day_id: &Uuid,
     ) -> SelectedExercisesWithLinkIdResult<Vec<ExerciseWithLinkId>>;
 
     async fn remove_exercise_from_training_day(
         &self,
+        user_id: &Uuid,
         link_id: &Uuid,
     ) -> ExerciseToTrainingDayResult<Uuid>;
 
     async fn get_link_table_data(&self) -> ExerciseToTrainingDayResult<Vec<ExerciseToTrainingDay>>;
 
-    async fn is_previous_session_in_progress(&self, day_id: &Uuid) -> SessionResult<bool>;
-    async fn create_session(&self, day_id: &Uuid) -> SessionResult<SessionWithExercisePerformance>;
-    async fn get_all_sessions_by_day_id(&self, day_id: &Uuid) -> SessionResult<Vec<Session>>;
+    async fn is_previous_session_in_progress(
+        &self,
+        user_id: &Uuid,
+        day_id: &Uuid,
+    ) -> SessionResult<bool>;
+    async fn create_session(
+        &self,
+        user_id: &Uuid,
+        day_id: &Uuid,
+    ) -> SessionResult<SessionWithExercisePerformance>;
+    async fn get_session(
+        &self,
+        user_id: &Uuid,
+        session_id: &Uuid,
+    ) -> SessionResult<Option<Session>>;
+    async fn get_session_with_performance(
+        &self,
+        user_id: &Uuid,
+        session_id: &Uuid,
+    ) -> SessionResult<Option<SessionWithExercisePerformance>>;
+    async fn get_all_sessions_by_day_id(
+        &self,
+        user_id: &Uuid,
+        day_id: &Uuid,
+    ) -> SessionResult<Vec<Session>>;
     async fn get_sessions_with_exercises(
         &self,
+        user_id: &Uuid,
         day_id: &Uuid,
     ) -> SessionResult<Vec<SessionWithExercises>>;
     async fn get_session_in_progress(
         &self,
+        user_id: &Uuid,
         routine_id: &Uuid,
     ) -> SessionResult<Option<SessionWithExercisePerformance>>;
     async fn get_all_sessions_by_routine_id(
         &self,
+        user_id: &Uuid,
         routine_id: &Uuid,
     ) -> SessionResult<Vec<Session>>;
-    async fn end_session(&self, session_id: &Uuid) -> SessionResult<Uuid>;
+    async fn end_session(&self, user_id: &Uuid, session_id: &Uuid) -> SessionResult<Uuid>;
+
+    // Who activity about the session or routine goes to, None when nobody owns it
+    async fn get_session_owner(&self, session_id: &Uuid) -> RoutineResult<Option<Uuid>>;
+    async fn get_routine_owner(&self, routine_id: &Uuid) -> RoutineResult<Option<Uuid>>;
+
+    // What the exercise's sets record, None if there is no such exercise
+    async fn get_tracked_metrics(
+        &self,
+        exercise_id: &Uuid,
+    ) -> RoutineResult<Option<TrackedMetrics>>;
 
     async fn add_set_performance_to_session(
         &self,
+        user_id: &Uuid,
         session_id: &Uuid,
         exercise_id: &Uuid,
         set_performance: &SetPerformancePayload,
@@ -117,8 +344,293 @@ pub trait RoutinesRepository: Send + Sync + 'static {
 
     async fn remove_set_performance_from_session(
         &self,
+        user_id: &Uuid,
         performance_id: &Uuid,
-    ) -> SessionResult<Uuid>;
+    ) -> SessionResult<RemovedSetPerformance>;
+
+    // Ends the user's sessions that were started before `started_before` and never ended
+    async fn close_stale_sessions(
+        &self,
+        user_id: &Uuid,
+        started_before: DateTime<Utc>,
+    ) -> SessionResult<Vec<Session>>;
+
+    // Stores the set as a PR when no other set of the exercise logged by the session's owner
+    // has a higher estimated 1RM, and drops its PR when an edit means it isn't one anymore
+    async fn record_personal_record(
+        &self,
+        session_id: &Uuid,
+        exercise_id: &Uuid,
+        set_performance: &SetPerformance,
+    ) -> RoutineResult<Option<PersonalRecord>>;
+
+    // Only deletes the set if it belongs to the given session and exercise
+    async fn remove_set_performance_from_session_exercise(
+        &self,
+        user_id: &Uuid,
+        session_id: &Uuid,
+        exercise_id: &Uuid,
+        performance_id: &Uuid,
+    ) -> SessionResult<Option<Uuid>>;
+
+    // partial updates, `expected_version` is the updated_at the client last saw (If-Match)
+    async fn patch_routine(
+        &self,
+        user_id: &Uuid,
+        routine_id: &Uuid,
+        patch: &PatchRoutine,
+        expected_version: Option<DateTime<Utc>>,
+    ) -> PatchResult<Routine>;
+    async fn patch_training_day(
+        &self,
+        user_id: &Uuid,
+        day_id: &Uuid,
+        patch: &PatchTrainingDay,
+        expected_version: Option<DateTime<Utc>>,
+    ) -> PatchResult<TrainingDay>;
+    // Only the owner can change an exercise, library ones are read-only
+    async fn patch_exercise(
+        &self,
+        user_id: &Uuid,
+        exercise_id: &Uuid,
+        patch: &PatchExercise,
+        expected_version: Option<DateTime<Utc>>,
+    ) -> PatchResult<Exercise>;
+    async fn patch_exercise_link(
+        &self,
+        user_id: &Uuid,
+        link_id: &Uuid,
+        patch: &PatchExerciseToTrainingDay,
+        expected_version: Option<DateTime<Utc>>,
+    ) -> PatchResult<ExerciseToTrainingDay>;
+    async fn patch_set_performance(
+        &self,
+        user_id: &Uuid,
+        session_id: &Uuid,
+        exercise_id: &Uuid,
+        performance_id: &Uuid,
+        patch: &PatchSetPerformance,
+        expected_version: Option<DateTime<Utc>>,
+    ) -> PatchResult<SetPerformance>;
+
+    // idempotency keys
+    async fn reserve_idempotency_key(
+        &self,
+        user_id: &Uuid,
+        key: &str,
+        method: &str,
+        path: &str,
+        request_hash: &str,
+        ttl_seconds: i64,
+    ) -> RoutineResult<IdempotencyReservation>;
+    async fn complete_idempotency_key<'a>(
+        &self,
+        user_id: &Uuid,
+        key: &str,
+        status_code: i16,
+        content_type: Option<&'a str>,
+        response_body: &[u8],
+    ) -> RoutineResult<()>;
+    async fn release_idempotency_key(&self, user_id: &Uuid, key: &str) -> RoutineResult<()>;
+
+    // offline sync
+    async fn push_sync_mutations(
+        &self,
+        user_id: &Uuid,
+        mutations: &[SyncMutation],
+    ) -> RoutineResult<SyncPushResponse>;
+    async fn get_logged_sets(&self, performance_ids: &[Uuid]) -> RoutineResult<Vec<LoggedSet>>;
+    async fn pull_sync_changes(
+        &self,
+        user_id: &Uuid,
+        cursor: i64,
+        limit: i64,
+    ) -> RoutineResult<SyncPullResponse>;
+
+    // export
+    // Streams rows as the database returns them instead of collecting them first
+    fn export_history(
+        &self,
+        user_id: &Uuid,
+        query: &HistoryExportQuery,
+    ) -> BoxStream<'static, RoutineResult<HistoryExportRow>>;
+
+    // import
+    // Writes every session and its sets in one transaction, all or nothing
+    async fn import_sessions(
+        &self,
+        user_id: &Uuid,
+        sessions: &[ImportedSession],
+    ) -> SessionResult<usize>;
+
+    // webhooks
+    async fn create_webhook(
+        &self,
+        user_id: &Uuid,
+        url: &str,
+        events: &[String],
+        secret: &str,
+    ) -> RoutineResult<Webhook>;
+    async fn get_webhooks(&self, user_id: &Uuid) -> RoutineResult<Vec<Webhook>>;
+    async fn delete_webhook(
+        &self,
+        user_id: &Uuid,
+        webhook_id: &Uuid,
+    ) -> RoutineResult<Option<Uuid>>;
+    async fn get_webhook_target(&self, webhook_id: &Uuid) -> RoutineResult<Option<WebhookTarget>>;
+    // Queues the payload for every active webhook of the user subscribed to `event_type`
+    async fn create_webhook_deliveries(
+        &self,
+        user_id: &Uuid,
+        event_type: &str,
+        payload: &serde_json::Value,
+    ) -> RoutineResult<Vec<WebhookDelivery>>;
+    async fn get_webhook_deliveries(
+        &self,
+        user_id: &Uuid,
+        webhook_id: &Uuid,
+        limit: i64,
+    ) -> RoutineResult<Vec<WebhookDelivery>>;
+    async fn get_pending_webhook_deliveries(&self) -> RoutineResult<Vec<WebhookDelivery>>;
+    // Queues a new delivery with the same payload, the original stays in the log
+    async fn redeliver_webhook_delivery(
+        &self,
+        user_id: &Uuid,
+        webhook_id: &Uuid,
+        delivery_id: &Uuid,
+    ) -> RoutineResult<Option<WebhookDelivery>>;
+    async fn record_webhook_attempt<'a>(
+        &self,
+        delivery_id: &Uuid,
+        status: &str,
+        response_status: Option<i16>,
+        last_error: Option<&'a str>,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> RoutineResult<()>;
+
+    // coaching
+    // None when the two are already linked
+    async fn create_coach_invitation(
+        &self,
+        coach_id: &Uuid,
+        athlete_id: &Uuid,
+        permissions: &CoachPermissions,
+    ) -> RoutineResult<Option<CoachingLink>>;
+    // Links where the user is the athlete, pending ones included
+    async fn get_coaches(&self, athlete_id: &Uuid) -> RoutineResult<Vec<CoachingLink>>;
+    // Links where the user is the coach, pending ones included
+    async fn get_athletes(&self, coach_id: &Uuid) -> RoutineResult<Vec<CoachingLink>>;
+    async fn accept_coach_invitation(
+        &self,
+        athlete_id: &Uuid,
+        link_id: &Uuid,
+    ) -> RoutineResult<Option<CoachingLink>>;
+    // Only the athlete decides what their coach may do
+    async fn update_coach_permissions(
+        &self,
+        athlete_id: &Uuid,
+        link_id: &Uuid,
+        permissions: &CoachPermissions,
+    ) -> RoutineResult<Option<CoachingLink>>;
+    // Either side can end a link, which also declines a pending invitation
+    async fn delete_coaching_link(
+        &self,
+        user_id: &Uuid,
+        link_id: &Uuid,
+    ) -> RoutineResult<Option<Uuid>>;
+    async fn get_coach_dashboard(&self, coach_id: &Uuid)
+        -> RoutineResult<Vec<CoachDashboardEntry>>;
+
+    // comments
+    // Comments on a session the user may read the history of, oldest first
+    async fn get_comments(&self, user_id: &Uuid, session_id: &Uuid) -> RoutineResult<Vec<Comment>>;
+    // None when the session isn't there for the author to comment on, or the exercise, set or
+    // parent comment isn't part of it
+    async fn create_comment(
+        &self,
+        author_id: &Uuid,
+        session_id: &Uuid,
+        create_comment: &CreateComment,
+    ) -> RoutineResult<Option<SavedComment>>;
+    // Only the author edits a comment
+    async fn update_comment(
+        &self,
+        author_id: &Uuid,
+        comment_id: &Uuid,
+        body: &str,
+    ) -> RoutineResult<Option<SavedComment>>;
+    // The author or the session's owner can delete a comment, its replies go with it
+    async fn delete_comment(
+        &self,
+        user_id: &Uuid,
+        comment_id: &Uuid,
+    ) -> RoutineResult<Option<RemovedComment>>;
+
+    // notes
+    // None when the session isn't there for the user to log against
+    async fn set_session_notes<'a>(
+        &self,
+        user_id: &Uuid,
+        session_id: &Uuid,
+        notes: Option<&'a str>,
+    ) -> SessionResult<Option<Session>>;
+    // Full-text search over the user's own session, set and day exercise notes
+    async fn search_notes(
+        &self,
+        user_id: &Uuid,
+        query: &str,
+        limit: i64,
+    ) -> RoutineResult<Vec<NoteSearchHit>>;
+
+    // body measurements, always the caller's own
+    // Logging a day that already has a measurement fills in the given fields
+    async fn log_body_measurement(
+        &self,
+        user_id: &Uuid,
+        measurement: &CreateBodyMeasurement,
+    ) -> RoutineResult<BodyMeasurement>;
+    // Latest first, `from` and `to` inclusive
+    async fn get_body_measurements(
+        &self,
+        user_id: &Uuid,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> RoutineResult<Vec<BodyMeasurement>>;
+    async fn get_body_measurement(
+        &self,
+        user_id: &Uuid,
+        measurement_id: &Uuid,
+    ) -> RoutineResult<Option<BodyMeasurement>>;
+    async fn patch_body_measurement(
+        &self,
+        user_id: &Uuid,
+        measurement_id: &Uuid,
+        patch: &PatchBodyMeasurement,
+    ) -> RoutineResult<Option<BodyMeasurement>>;
+    async fn delete_body_measurement(
+        &self,
+        user_id: &Uuid,
+        measurement_id: &Uuid,
+    ) -> RoutineResult<bool>;
+    // The days `metric` was logged on, oldest first, moving averages left at zero
+    async fn get_body_trend(
+        &self,
+        user_id: &Uuid,
+        metric: BodyMetric,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> RoutineResult<Vec<BodyTrendPoint>>;
+    // The latest measurement with a bodyweight on or before `day`
+    async fn get_bodyweight_as_of(
+        &self,
+        user_id: &Uuid,
+        day: NaiveDate,
+    ) -> RoutineResult<Option<BodyMeasurement>>;
+
+    // account
+    async fn export_account(&self, user_id: &Uuid) -> RoutineResult<Option<AccountExport>>;
+    // Removes the user and every row they own in one transaction, false if there was no such user
+    async fn delete_account(&self, user_id: &Uuid) -> RoutineResult<bool>;
 
     async fn clear_data(&self) -> Result<(), sqlx::Error>;
 }
@@ -1,18 +1,86 @@
 use std::collections::HashMap;
 
+use futures_util::stream::{BoxStream, StreamExt, TryStreamExt};
+use serde_json::Value;
+use sqlx::{Acquire, PgConnection};
+
 use super::{
-    ExerciseToTrainingDayResult, RoutineResult, RoutinesRepository,
-    SelectedExercisesWithLinkIdResult, SessionError, SessionResult, TrainingDayResult,
+    ExerciseToTrainingDayResult, IdempotencyRecord, IdempotencyReservation, ImportedSession,
+    LoggedSet, PatchError, PatchResult, RemovedComment, RemovedSetPerformance, RoutineResult,
+    RoutinesRepository, SavedComment, SelectedExercisesWithLinkIdResult, SessionError,
+    SessionResult, TrainingDayResult, WebhookTarget,
 };
 
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
 use shared::models::{
-    CreateExercise, CreateRoutine, CreateTrainingDay, CreateUser, Exercise, ExerciseToTrainingDay,
-    ExerciseWithLinkId, Routine, Session, SessionPerformance, SessionWithExercisePerformance,
+    AccountExport, BodyMeasurement, BodyMetric, BodyTrendPoint, CoachDashboardEntry,
+    CoachPermissions, CoachingLink, Comment, CreateBodyMeasurement, CreateComment, CreateExercise,
+    CreateRoutine, CreateTrainingDay, CreateUser, Exercise, ExerciseSearchHit,
+    ExerciseToTrainingDay, ExerciseWithLinkId, HistoryExportQuery, HistoryExportRow, MergeReport,
+    Muscles, NoteSearchHit, PatchBodyMeasurement, PatchExercise, PatchExerciseToTrainingDay,
+    PatchRoutine, PatchSetPerformance, PatchTrainingDay, PersonalRecord, RenumberedSet, Role,
+    Routine, SearchQuery, Session, SessionPerformance, SessionWithExercisePerformance,
     SessionWithExercises, SessionsWithExercisesQuery, SetPerformance, SetPerformancePayload,
-    TrainingDay, TrainingDayWithExercises, TrainingDayWithExercisesQuery, User,
+    SyncChange, SyncConflict, SyncEntity, SyncMutation, SyncOperation, SyncPullResponse,
+    SyncPushResponse, SyncRejection, SyncRoutine, SyncSession, SyncSetPerformance, TrackedMetrics,
+    TrainingDay, TrainingDayWithExercises, TrainingDayWithExercisesQuery, User, UserNoPassword,
+    Webhook, WebhookDelivery, WeightUnit,
 };
+use shared::search;
+use shared::utils::estimated_one_rep_max;
 use uuid::Uuid;
 
+use crate::sync::{self, FieldVersion, Resolution};
+
+// What a user owns, as subqueries on $1: their routines with the days under them, and their
+// sessions plus any session logged against one of their days. Everything else hangs off these.
+const OWNED_ROUTINES: &str = "SELECT routine_id FROM Routines WHERE user_id = $1";
+const OWNED_DAYS: &str = "SELECT day_id FROM TrainingDays \
+    WHERE routine_id IN (SELECT routine_id FROM Routines WHERE user_id = $1)";
+const OWNED_SESSIONS: &str = "SELECT session_id FROM Sessions \
+    WHERE user_id = $1 OR day_id IN (SELECT day_id FROM TrainingDays \
+    WHERE routine_id IN (SELECT routine_id FROM Routines WHERE user_id = $1))";
+
+// A coaching link with both usernames, selected from `ca` (CoachAthletes or a CTE over it)
+const COACHING_LINK_COLUMNS: &str = "ca.link_id, ca.coach_id, coach.username AS coach_username, \
+    ca.athlete_id, athlete.username AS athlete_username, ca.status, ca.can_read_history, \
+    ca.can_edit_routines, ca.can_comment, ca.accepted_at, ca.created_at";
+const COACHING_LINK_JOINS: &str = "JOIN Users coach ON coach.user_id = ca.coach_id \
+    JOIN Users athlete ON athlete.user_id = ca.athlete_id";
+
+// A comment with its author's username, selected from `c` (Comments or a CTE over it)
+const COMMENT_COLUMNS: &str = "c.comment_id, c.session_id, c.exercise_id, c.performance_id, \
+    c.parent_id, c.author_id, author.username AS author_username, c.body, c.created_at, \
+    c.updated_at";
+const COMMENT_JOINS: &str = "JOIN Users author ON author.user_id = c.author_id";
+const BODY_MEASUREMENT_COLUMNS: &str = "measurement_id, measured_on, bodyweight, \
+    body_fat_percent, waist_cm, chest_cm, arms_cm, thighs_cm, created_at, updated_at";
+const EXERCISE_COLUMNS: &str = "exercise_id, exercise_name, exercise_description, load_type, \
+    tracked_metrics, equipment, movement_pattern, unilateral, primary_muscles, secondary_muscles, \
+    aliases, builtin, owner_id, created_at, updated_at";
+// The library exercises and `$1`'s own, minus the library exercises they shadow by name. For
+// `FROM Exercises e`.
+const VISIBLE_EXERCISES: &str = "(e.owner_id = $1 OR (e.builtin AND NOT EXISTS (\
+    SELECT 1 FROM Exercises mine \
+    WHERE mine.owner_id = $1 AND mine.exercise_name = e.exercise_name)))";
+// A set as returned to clients, selected from SessionExercisePerformance
+const SET_COLUMNS: &str = "performance_id, set_number, weight, \
+    COALESCE(effective_load, weight) AS effective_load, reps, rir, duration_seconds, \
+    distance_meters, calories, heart_rate, notes, created_at, updated_at";
+
+// A set with the session and exercise it belongs to, for the account export
+#[derive(sqlx::FromRow)]
+struct AccountSetRow {
+    session_id: Uuid,
+    exercise_id: Uuid,
+    exercise_name: String,
+    #[sqlx(try_from = "Vec<String>")]
+    tracked_metrics: TrackedMetrics,
+    #[sqlx(flatten)]
+    set: SetPerformance,
+}
+
 pub struct PostgresRoutinesRepository {
     pool: sqlx::PgPool,
 }
@@ -21,6 +89,162 @@ impl PostgresRoutinesRepository {
     pub fn new(pool: sqlx::PgPool) -> Self {
         Self { pool }
     }
+
+    // A conditional update matched no row: tell a missing row apart from a stale version
+    async fn patch_miss(&self, exists_query: &str, ids: &[&Uuid]) -> PatchError {
+        let mut query = sqlx::query_scalar::<_, bool>(exists_query);
+        for id in ids {
+            query = query.bind(*id);
+        }
+        match query.fetch_one(&self.pool).await {
+            Ok(true) => PatchError::PreconditionFailed,
+            Ok(false) => PatchError::NotFound,
+            Err(e) => PatchError::Error(e.to_string()),
+        }
+    }
+
+    async fn remove_personal_record(&self, performance_id: &Uuid) -> RoutineResult<()> {
+        sqlx::query("DELETE FROM PersonalRecords WHERE performance_id = $1")
+            .bind(performance_id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    // The latest set note per exercise from the session owner's earlier sessions
+    async fn load_previous_notes(
+        &self,
+        session_id: &Uuid,
+        exercises: &[ExerciseWithLinkId],
+    ) -> SessionResult<HashMap<Uuid, String>> {
+        let exercise_ids: Vec<Uuid> = exercises.iter().map(|e| e.exercise_id).collect();
+        let notes = sqlx::query_as::<_, (Uuid, String)>(
+            r#"
+        SELECT DISTINCT ON (sep.exercise_id) sep.exercise_id, sep.notes
+        FROM SessionExercisePerformance sep
+        JOIN Sessions s ON s.session_id = sep.session_id
+        WHERE sep.exercise_id = ANY($1)
+          AND sep.session_id <> $2
+          AND sep.notes IS NOT NULL
+          AND s.user_id IS NOT DISTINCT FROM session_owner($2)
+          AND s.created_at < (SELECT created_at FROM Sessions WHERE session_id = $2)
+        ORDER BY sep.exercise_id, s.created_at DESC, sep.set_number DESC
+        "#,
+        )
+        .bind(&exercise_ids)
+        .bind(session_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| SessionError::Error(e.to_string()))?;
+
+        Ok(notes.into_iter().collect())
+    }
+
+    // Load the exercises of a session's training day together with the sets logged against each
+    async fn load_session_performance(
+        &self,
+        session: Session,
+    ) -> SessionResult<SessionWithExercisePerformance> {
+        let exercises_query = sqlx::query_as::<_, ExerciseWithLinkId>(
+            r#"
+        SELECT
+            e.exercise_id,
+            etdl.link_id,
+            e.exercise_name,
+            e.exercise_description,
+            e.load_type,
+            e.tracked_metrics,
+            etdl.notes,
+            e.created_at,
+            e.updated_at
+        FROM
+            ExerciseTrainingDayLink etdl
+        LEFT JOIN
+            Exercises e ON etdl.exercise_id = e.exercise_id
+        WHERE
+            etdl.day_id = $1
+        "#,
+        )
+        .bind(session.day_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| SessionError::Error(e.to_string()))?;
+
+        let mut previous_notes = self
+            .load_previous_notes(&session.session_id, &exercises_query)
+            .await?;
+
+        // Initialize a vector to hold SessionPerformance objects
+        let mut session_performance_vec = Vec::new();
+
+        // Iterate over fetched exercises
+        for exercise in exercises_query.iter() {
+            // Query SessionExercisePerformance table to get set data for this exercise within the active session
+            let sets_query = sqlx::query_as::<_, SetPerformance>(&format!(
+                r#"
+            SELECT {SET_COLUMNS}
+            FROM
+                SessionExercisePerformance
+            WHERE
+                session_id = $1 AND
+                exercise_id = $2
+            ORDER BY
+                set_number
+            "#,
+            ))
+            .bind(session.session_id)
+            .bind(exercise.exercise_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| SessionError::Error(e.to_string()))?;
+
+            // Create SessionPerformance object for this exercise, populating it with fetched set data
+            let session_performance = SessionPerformance {
+                session_id: session.session_id,
+                exercise_id: exercise.exercise_id,
+                exercise_name: exercise.exercise_name.clone(),
+                tracked_metrics: exercise.tracked_metrics.clone(),
+                sets: sets_query,
+                previous_note: previous_notes.remove(&exercise.exercise_id),
+                created_at: None, // Modify as needed
+                updated_at: None, // Modify as needed
+            };
+
+            // Add the SessionPerformance object to the vector
+            session_performance_vec.push(session_performance);
+        }
+
+        let comments = sqlx::query_as::<_, Comment>(&format!(
+            r#"
+        SELECT {COMMENT_COLUMNS}
+        FROM Comments c
+        {COMMENT_JOINS}
+        WHERE c.session_id = $1
+        ORDER BY c.created_at
+        "#
+        ))
+        .bind(session.session_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| SessionError::Error(e.to_string()))?;
+
+        // Construct the SessionWithExercisePerformance object
+        let session_with_exercises = SessionWithExercisePerformance {
+            session_id: session.session_id,
+            day_id: session.day_id,
+            day_name: session.day_name,
+            in_progress: session.in_progress,
+            notes: session.notes,
+            exercises: exercises_query,
+            performance: session_performance_vec,
+            comments,
+            created_at: session.created_at,
+            updated_at: session.updated_at,
+        };
+
+        Ok(session_with_exercises)
+    }
 }
 
 #[async_trait::async_trait]
@@ -55,11 +279,12 @@ impl RoutinesRepository for PostgresRoutinesRepository {
         .map_err(|e| e.to_string())
     }
 
-    async fn get_users(&self) -> RoutineResult<Vec<User>> {
-        sqlx::query_as::<_, User>(
+    async fn get_users(&self) -> RoutineResult<Vec<UserNoPassword>> {
+        sqlx::query_as::<_, UserNoPassword>(
             r#"
-            SELECT *
+            SELECT user_id, username, role, weight_unit
             FROM users
+            ORDER BY created_at
             "#,
         )
         .fetch_all(&self.pool)
@@ -67,68 +292,148 @@ impl RoutinesRepository for PostgresRoutinesRepository {
         .map_err(|e| e.to_string())
     }
 
+    async fn set_user_role(
+        &self,
+        user_id: &Uuid,
+        role: Role,
+    ) -> RoutineResult<Option<UserNoPassword>> {
+        sqlx::query_as::<_, UserNoPassword>(
+            r#"
+            UPDATE users
+            SET role = $2
+            WHERE user_id = $1
+            RETURNING user_id, username, role, weight_unit
+            "#,
+        )
+        .bind(user_id)
+        .bind(role.as_str())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn get_weight_unit(&self, user_id: &Uuid) -> RoutineResult<WeightUnit> {
+        let unit = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT weight_unit
+            FROM users
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        // tokens can outlive their user, they read in the storage unit
+        unit.map_or(Ok(WeightUnit::Kg), WeightUnit::try_from)
+    }
+
+    async fn set_weight_unit(
+        &self,
+        user_id: &Uuid,
+        weight_unit: WeightUnit,
+    ) -> RoutineResult<Option<UserNoPassword>> {
+        sqlx::query_as::<_, UserNoPassword>(
+            r#"
+            UPDATE users
+            SET weight_unit = $2
+            WHERE user_id = $1
+            RETURNING user_id, username, role, weight_unit
+            "#,
+        )
+        .bind(user_id)
+        .bind(weight_unit.as_str())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
     //routines
-    async fn get_routines(&self) -> RoutineResult<Vec<Routine>> {
+    async fn get_routines(&self, user_id: &Uuid, owner_id: &Uuid) -> RoutineResult<Vec<Routine>> {
         sqlx::query_as::<_, Routine>(
             r#"
       SELECT routine_id, name, description, is_active, created_at, updated_at
       FROM routines
+      WHERE (user_id = $2 OR (user_id IS NULL AND $1 = $2))
+        AND has_access($1, user_id, 'read_routines')
       "#,
         )
+        .bind(user_id)
+        .bind(owner_id)
         .fetch_all(&self.pool)
         .await
         .map_err(|e| e.to_string())
     }
 
-    async fn get_active_routines(&self) -> RoutineResult<Vec<Routine>> {
+    async fn get_active_routines(
+        &self,
+        user_id: &Uuid,
+        owner_id: &Uuid,
+    ) -> RoutineResult<Vec<Routine>> {
         sqlx::query_as::<_, Routine>(
             r#"
       SELECT routine_id, name, description, is_active, created_at, updated_at
       FROM routines
       WHERE is_active = true
+        AND (user_id = $2 OR (user_id IS NULL AND $1 = $2))
+        AND has_access($1, user_id, 'read_routines')
       "#,
         )
+        .bind(user_id)
+        .bind(owner_id)
         .fetch_all(&self.pool)
         .await
         .map_err(|e| e.to_string())
     }
 
-    async fn create_routine(&self, create_routine: &CreateRoutine) -> RoutineResult<Routine> {
+    async fn create_routine(
+        &self,
+        user_id: &Uuid,
+        create_routine: &CreateRoutine,
+    ) -> RoutineResult<Option<Routine>> {
+        let owner_id = create_routine.user_id.unwrap_or(*user_id);
         sqlx::query_as::<_, Routine>(
             r#"
-      INSERT INTO routines (name, description, is_active)
-      VALUES ($1, $2, $3)
+      INSERT INTO routines (user_id, name, description, is_active)
+      SELECT $2, $3, $4, $5
+      WHERE has_access($1, $2, 'edit_routines')
       RETURNING routine_id, name, description,is_active, created_at, updated_at
       "#,
         )
+        .bind(user_id)
+        .bind(owner_id)
         .bind(&create_routine.name)
         .bind(&create_routine.description)
         .bind(&create_routine.is_active)
-        .fetch_one(&self.pool)
+        .fetch_optional(&self.pool)
         .await
         .map_err(|e| e.to_string())
     }
 
-    async fn delete_routine(&self, routine_id: &uuid::Uuid) -> RoutineResult<Uuid> {
+    async fn delete_routine(&self, user_id: &Uuid, routine_id: &uuid::Uuid) -> RoutineResult<Uuid> {
         sqlx::query_scalar::<_, Uuid>(
             r#"
       DELETE FROM routines
       WHERE routine_id = $1
+        AND has_access($2, user_id, 'edit_routines')
         RETURNING routine_id
       "#,
         )
         .bind(&routine_id)
+        .bind(user_id)
         .fetch_one(&self.pool)
         .await
         .map_err(|e| e.to_string())
     }
 
-    async fn update_routine(&self, routine: &Routine) -> RoutineResult<Routine> {
+    async fn update_routine(&self, user_id: &Uuid, routine: &Routine) -> RoutineResult<Routine> {
         sqlx::query_as::<_, Routine>(
             r#"
       UPDATE routines
       SET name = $1, description = $2, is_active = $3
       WHERE routine_id = $4
+        AND has_access($5, user_id, 'edit_routines')
       RETURNING routine_id, name, description, is_active, created_at, updated_at
       "#,
         )
@@ -136,27 +441,35 @@ impl RoutinesRepository for PostgresRoutinesRepository {
         .bind(&routine.description)
         .bind(&routine.is_active)
         .bind(&routine.routine_id)
+        .bind(user_id)
         .fetch_one(&self.pool)
         .await
         .map_err(|e| e.to_string())
     }
 
-    // async fn get_routine(&self, routine_id: &uuid::Uuid) -> RoutineResult<Routine> {
-    //     sqlx::query_as::<_, Routine>(
-    //         r#"
-    //   SELECT id, title, description, created_at, updated_at
-    //   FROM routines
-    //   WHERE id = $1
-    //   "#,
-    //     )
-    //     .bind(routine_id)
-    //     .fetch_one(&self.pool)
-    //     .await
-    //     .map_err(|e| e.to_string())
-    // }
+    async fn get_routine(
+        &self,
+        user_id: &Uuid,
+        routine_id: &uuid::Uuid,
+    ) -> RoutineResult<Option<Routine>> {
+        sqlx::query_as::<_, Routine>(
+            r#"
+      SELECT routine_id, name, description, is_active, created_at, updated_at
+      FROM routines
+      WHERE routine_id = $1
+        AND has_access($2, user_id, 'read_routines')
+      "#,
+        )
+        .bind(routine_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
 
     async fn get_training_days(
         &self,
+        user_id: &Uuid,
         routine_id: &uuid::Uuid,
     ) -> TrainingDayResult<Vec<TrainingDay>> {
         sqlx::query_as::<_, TrainingDay>(
@@ -164,35 +477,76 @@ impl RoutinesRepository for PostgresRoutinesRepository {
       SELECT day_id, day_name, routine_id, created_at, updated_at
       FROM trainingdays
       WHERE routine_id = $1
+        AND has_access($2, routine_owner(routine_id), 'read_routines')
       "#,
         )
         .bind(routine_id)
+        .bind(user_id)
         .fetch_all(&self.pool)
         .await
         .map_err(|e| e.to_string())
     }
 
+    async fn get_training_day(
+        &self,
+        user_id: &Uuid,
+        day_id: &Uuid,
+    ) -> TrainingDayResult<Option<TrainingDay>> {
+        sqlx::query_as::<_, TrainingDay>(
+            r#"
+      SELECT day_id, day_name, routine_id, created_at, updated_at
+      FROM trainingdays
+      WHERE day_id = $1
+        AND has_access($2, routine_owner(routine_id), 'read_routines')
+      "#,
+        )
+        .bind(day_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
     async fn create_training_day(
         &self,
+        user_id: &Uuid,
         create_training_day: &CreateTrainingDay,
     ) -> TrainingDayResult<TrainingDay> {
         sqlx::query_as::<_, TrainingDay>(
             r#"
       INSERT INTO trainingdays (day_name, routine_id)
-      VALUES ($1, $2)
+      SELECT $1, $2
+      WHERE has_access($3, routine_owner($2), 'edit_routines')
       RETURNING day_id, day_name, routine_id, created_at, updated_at
       "#,
         )
         .bind(&create_training_day.day_name)
         .bind(&create_training_day.routine_id)
-        .fetch_one(&self.pool)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Routine {} not found", create_training_day.routine_id))
     }
 
-    async fn delete_training_day(&self, day_id: &Uuid) -> TrainingDayResult<Option<Uuid>> {
+    async fn delete_training_day(
+        &self,
+        user_id: &Uuid,
+        day_id: &Uuid,
+    ) -> TrainingDayResult<Option<Uuid>> {
         let mut transaction = self.pool.begin().await.map_err(|e| e.to_string())?;
 
+        let allowed =
+            sqlx::query_scalar::<_, bool>("SELECT has_access($2, day_owner($1), 'edit_routines')")
+                .bind(day_id)
+                .bind(user_id)
+                .fetch_one(transaction.as_mut())
+                .await
+                .map_err(|e| e.to_string())?;
+        if !allowed {
+            return Ok(None);
+        }
+
         // Step 1: Delete references in the link table (ExerciseTrainingDayLink)
         let link_table_result =
             sqlx::query("DELETE FROM ExerciseTrainingDayLink WHERE day_id = $1")
@@ -241,6 +595,7 @@ impl RoutinesRepository for PostgresRoutinesRepository {
 
     async fn create_training_days(
         &self,
+        user_id: &Uuid,
         create_training_days: &[CreateTrainingDay],
     ) -> TrainingDayResult<Vec<TrainingDay>> {
         if create_training_days.is_empty() {
@@ -255,7 +610,8 @@ impl RoutinesRepository for PostgresRoutinesRepository {
             let query = format!(
                 r#"
         INSERT INTO trainingdays (day_name, routine_id)
-        VALUES ($1, $2)
+        SELECT $1, $2
+        WHERE has_access($3, routine_owner($2), 'edit_routines')
         RETURNING day_id, day_name, routine_id, created_at, updated_at
         "#,
             );
@@ -263,9 +619,11 @@ impl RoutinesRepository for PostgresRoutinesRepository {
             let result = sqlx::query_as::<_, TrainingDay>(&query)
                 .bind(&create_training_day.day_name)
                 .bind(&create_training_day.routine_id)
-                .fetch_one(&self.pool)
+                .bind(user_id)
+                .fetch_optional(&self.pool)
                 .await
-                .map_err(|e| e.to_string())?;
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("Routine {} not found", create_training_day.routine_id))?;
 
             results.push(result);
         }
@@ -273,27 +631,72 @@ impl RoutinesRepository for PostgresRoutinesRepository {
         Ok(results)
     }
 
-    async fn get_exercises(&self) -> TrainingDayResult<Vec<Exercise>> {
-        sqlx::query_as::<_, Exercise>(
+    async fn get_exercises(&self, user_id: &Uuid) -> TrainingDayResult<Vec<Exercise>> {
+        sqlx::query_as::<_, Exercise>(&format!(
             r#"
-      SELECT exercise_id, exercise_name, exercise_description, created_at, updated_at
-      FROM exercises
+      SELECT {EXERCISE_COLUMNS}
+      FROM Exercises e
+      WHERE {VISIBLE_EXERCISES}
       "#,
-        )
+        ))
+        .bind(user_id)
         .fetch_all(&self.pool)
         .await
         .map_err(|e| e.to_string())
     }
 
-    async fn search_exercises(&self, name: &String) -> TrainingDayResult<Vec<Exercise>> {
-        sqlx::query_as::<_, Exercise>(
+    async fn search_exercises(
+        &self,
+        user_id: &Uuid,
+        query: &SearchQuery,
+        limit: i64,
+    ) -> TrainingDayResult<Vec<ExerciseSearchHit>> {
+        // Scored against the query as typed and with its abbreviations expanded, names and
+        // aliases alike. Mirrors `shared::search::rank_exercises`.
+        sqlx::query_as::<_, ExerciseSearchHit>(&format!(
             r#"
-      SELECT exercise_id, exercise_name, exercise_description, created_at, updated_at
-      FROM exercises
-      WHERE exercise_name ILIKE $1
+      WITH recent AS (
+          SELECT DISTINCT sep.exercise_id
+          FROM SessionExercisePerformance sep
+          JOIN Sessions s ON s.session_id = sep.session_id
+          WHERE s.user_id = $1
+          AND sep.created_at > CURRENT_TIMESTAMP - make_interval(days => $9)
+      ),
+      scored AS (
+          SELECT e.*,
+              (
+                  SELECT COALESCE(MAX(search_text_score(t, q)), 0)
+                  FROM unnest(array_prepend(e.exercise_name, e.aliases)) t,
+                      unnest(ARRAY[$2, $3]) q
+              ) AS match_score,
+              e.exercise_id IN (SELECT exercise_id FROM recent) AS recently_used
+          FROM Exercises e
+          WHERE {VISIBLE_EXERCISES}
+          AND ($4::text IS NULL OR e.equipment = $4)
+          AND ($5::text IS NULL OR e.movement_pattern = $5)
+          AND ($6::text IS NULL OR $6 = ANY(e.primary_muscles) OR $6 = ANY(e.secondary_muscles))
+          AND ($7::boolean IS NULL OR e.unilateral = $7)
+      )
+      SELECT {EXERCISE_COLUMNS},
+          (match_score + CASE WHEN recently_used THEN $10 ELSE 0 END)::real AS score,
+          recently_used
+      FROM scored
+      WHERE $2 = '' OR match_score >= $11
+      ORDER BY score DESC, exercise_name
+      LIMIT $8
       "#,
-        )
-        .bind(format!("%{}%", name))
+        ))
+        .bind(user_id)
+        .bind(search::normalize(&query.name))
+        .bind(search::expand_abbreviations(&query.name))
+        .bind(query.equipment.map(|equipment| equipment.as_str()))
+        .bind(query.movement_pattern.map(|pattern| pattern.as_str()))
+        .bind(query.muscle.map(|muscle| muscle.as_str()))
+        .bind(query.unilateral)
+        .bind(limit)
+        .bind(search::RECENT_DAYS)
+        .bind(search::RECENT_BOOST)
+        .bind(search::MIN_SCORE)
         .fetch_all(&self.pool)
         .await
         .map_err(|e| e.to_string())
@@ -301,24 +704,38 @@ impl RoutinesRepository for PostgresRoutinesRepository {
 
     async fn create_exercise(
         &self,
+        user_id: &Uuid,
         create_exercise: &CreateExercise,
-    ) -> TrainingDayResult<Exercise> {
-        sqlx::query_as::<_, Exercise>(
+    ) -> TrainingDayResult<Option<Exercise>> {
+        sqlx::query_as::<_, Exercise>(&format!(
             r#"
-      INSERT INTO Exercises (exercise_name, exercise_description)
-      VALUES ($1, $2)
-      RETURNING exercise_id, exercise_name, exercise_description, created_at, updated_at
+      INSERT INTO Exercises (exercise_name, exercise_description, load_type, tracked_metrics,
+          equipment, movement_pattern, unilateral, primary_muscles, secondary_muscles, aliases,
+          owner_id)
+      VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+      ON CONFLICT (owner_id, exercise_name) WHERE owner_id IS NOT NULL DO NOTHING
+      RETURNING {EXERCISE_COLUMNS}
       "#,
-        )
+        ))
         .bind(&create_exercise.exercise_name)
         .bind(&create_exercise.exercise_description)
-        .fetch_one(&self.pool)
+        .bind(create_exercise.load_type.as_str())
+        .bind(create_exercise.tracked_metrics.as_strs())
+        .bind(create_exercise.equipment.as_str())
+        .bind(create_exercise.movement_pattern.as_str())
+        .bind(create_exercise.unilateral)
+        .bind(create_exercise.primary_muscles.as_strs())
+        .bind(create_exercise.secondary_muscles.as_strs())
+        .bind(&create_exercise.aliases)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
         .await
         .map_err(|e| e.to_string())
     }
 
     async fn create_exercises(
         &self,
+        user_id: &Uuid,
         exercises: &[CreateExercise],
     ) -> TrainingDayResult<Vec<Exercise>> {
         if exercises.is_empty() {
@@ -330,48 +747,288 @@ impl RoutinesRepository for PostgresRoutinesRepository {
 
         // Execute the query using the database pool
         for exercise in exercises {
-            let query = format!(
+            results.extend(self.create_exercise(user_id, exercise).await?);
+        }
+
+        Ok(results)
+    }
+
+    async fn seed_exercise_library(&self, exercises: &[CreateExercise]) -> TrainingDayResult<u64> {
+        let mut transaction = self.pool.begin().await.map_err(|e| e.to_string())?;
+        let mut written = 0;
+        for exercise in exercises {
+            // Unchanged rows aren't touched, so their updated_at and ETag stay put
+            written += sqlx::query(
                 r#"
-        INSERT INTO Exercises (exercise_name, exercise_description)
-        VALUES ($1, $2)
-        RETURNING exercise_id, exercise_name, exercise_description, created_at, updated_at
+        INSERT INTO Exercises (exercise_name, exercise_description, load_type, tracked_metrics,
+            equipment, movement_pattern, unilateral, primary_muscles, secondary_muscles, aliases,
+            builtin)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, TRUE)
+        ON CONFLICT (exercise_name) WHERE builtin DO UPDATE SET
+            exercise_description = EXCLUDED.exercise_description,
+            load_type = EXCLUDED.load_type,
+            tracked_metrics = EXCLUDED.tracked_metrics,
+            equipment = EXCLUDED.equipment,
+            movement_pattern = EXCLUDED.movement_pattern,
+            unilateral = EXCLUDED.unilateral,
+            primary_muscles = EXCLUDED.primary_muscles,
+            secondary_muscles = EXCLUDED.secondary_muscles,
+            aliases = EXCLUDED.aliases
+        WHERE (Exercises.exercise_description, Exercises.load_type, Exercises.tracked_metrics,
+            Exercises.equipment, Exercises.movement_pattern, Exercises.unilateral,
+            Exercises.primary_muscles, Exercises.secondary_muscles, Exercises.aliases)
+        IS DISTINCT FROM (EXCLUDED.exercise_description, EXCLUDED.load_type,
+            EXCLUDED.tracked_metrics, EXCLUDED.equipment, EXCLUDED.movement_pattern,
+            EXCLUDED.unilateral, EXCLUDED.primary_muscles, EXCLUDED.secondary_muscles,
+            EXCLUDED.aliases)
         "#,
-            );
+            )
+            .bind(&exercise.exercise_name)
+            .bind(&exercise.exercise_description)
+            .bind(exercise.load_type.as_str())
+            .bind(exercise.tracked_metrics.as_strs())
+            .bind(exercise.equipment.as_str())
+            .bind(exercise.movement_pattern.as_str())
+            .bind(exercise.unilateral)
+            .bind(exercise.primary_muscles.as_strs())
+            .bind(exercise.secondary_muscles.as_strs())
+            .bind(&exercise.aliases)
+            .execute(transaction.as_mut())
+            .await
+            .map_err(|e| e.to_string())?
+            .rows_affected();
+        }
+        transaction.commit().await.map_err(|e| e.to_string())?;
+        Ok(written)
+    }
+
+    async fn get_exercises_by_id(&self, exercise_ids: &[Uuid]) -> TrainingDayResult<Vec<Exercise>> {
+        sqlx::query_as::<_, Exercise>(&format!(
+            r#"
+      SELECT {EXERCISE_COLUMNS}
+      FROM Exercises
+      WHERE exercise_id = ANY($1)
+      "#,
+        ))
+        .bind(exercise_ids)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn get_visible_exercise(
+        &self,
+        user_id: &Uuid,
+        exercise_id: &Uuid,
+    ) -> TrainingDayResult<Option<Exercise>> {
+        sqlx::query_as::<_, Exercise>(&format!(
+            r#"
+      SELECT {EXERCISE_COLUMNS}
+      FROM Exercises e
+      WHERE e.exercise_id = $2
+        AND (e.builtin OR e.owner_id = $1
+            OR EXISTS (
+                SELECT 1 FROM ExerciseTrainingDayLink l
+                WHERE l.exercise_id = e.exercise_id
+                    AND has_access($1, day_owner(l.day_id), 'read_routines')
+            )
+            OR EXISTS (
+                SELECT 1 FROM SessionExercisePerformance sep
+                WHERE sep.exercise_id = e.exercise_id
+                    AND has_access($1, session_owner(sep.session_id), 'read_history')
+            ))
+      "#,
+        ))
+        .bind(user_id)
+        .bind(exercise_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn merge_exercises(
+        &self,
+        source_ids: &[Uuid],
+        target_id: &Uuid,
+        aliases: &[String],
+        dry_run: bool,
+    ) -> TrainingDayResult<MergeReport> {
+        let mut transaction = self.pool.begin().await.map_err(|e| e.to_string())?;
+
+        // Numbered after the target's sets in the session, in the order they were logged, so
+        // `unique_set_number` holds at every row. Effective loads follow the target's load type.
+        let moved = sqlx::query_as::<_, RenumberedSet>(
+            r#"
+        WITH moved AS (
+            SELECT sep.performance_id, sep.session_id, sep.set_number AS from_set_number,
+                (COALESCE((
+                    SELECT MAX(target.set_number)
+                    FROM SessionExercisePerformance target
+                    WHERE target.session_id = sep.session_id AND target.exercise_id = $2
+                ), 0) + ROW_NUMBER() OVER (
+                    PARTITION BY sep.session_id ORDER BY sep.created_at, sep.set_number
+                ))::smallint AS to_set_number
+            FROM SessionExercisePerformance sep
+            WHERE sep.exercise_id = ANY($1)
+        )
+        UPDATE SessionExercisePerformance sep
+        SET exercise_id = $2, set_number = moved.to_set_number
+        FROM moved
+        WHERE sep.performance_id = moved.performance_id
+        RETURNING moved.performance_id, moved.session_id, moved.from_set_number,
+            moved.to_set_number
+        "#,
+        )
+        .bind(source_ids)
+        .bind(target_id)
+        .fetch_all(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?;
+
+        // A day keeps one link to the target, the one it had or else its oldest source link.
+        // The notes of the links it drops are appended to the one it keeps.
+        let duplicate_link = r#"
+        l.exercise_id = ANY($1)
+            AND EXISTS (
+                SELECT 1
+                FROM ExerciseTrainingDayLink other
+                WHERE other.day_id = l.day_id
+                    AND (other.exercise_id = $2 OR (other.exercise_id = ANY($1)
+                        AND (other.created_at, other.link_id) < (l.created_at, l.link_id)))
+            )
+        "#;
+        sqlx::query(&format!(
+            r#"
+        WITH dropped AS (
+            SELECT l.day_id, string_agg(l.notes, E'\n' ORDER BY l.created_at, l.link_id) AS notes
+            FROM ExerciseTrainingDayLink l
+            WHERE {duplicate_link} AND l.notes IS NOT NULL
+            GROUP BY l.day_id
+        ),
+        kept AS (
+            SELECT DISTINCT ON (l.day_id) l.link_id, dropped.notes
+            FROM ExerciseTrainingDayLink l
+            JOIN dropped ON dropped.day_id = l.day_id
+            WHERE l.exercise_id = $2 OR l.exercise_id = ANY($1)
+            ORDER BY l.day_id, l.exercise_id = $2 DESC, l.created_at, l.link_id
+        )
+        UPDATE ExerciseTrainingDayLink l
+        SET notes = concat_ws(E'\n', l.notes, kept.notes)
+        FROM kept
+        WHERE l.link_id = kept.link_id
+        "#,
+        ))
+        .bind(source_ids)
+        .bind(target_id)
+        .execute(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?;
+        let duplicate_links = sqlx::query(&format!(
+            "DELETE FROM ExerciseTrainingDayLink l WHERE {duplicate_link}"
+        ))
+        .bind(source_ids)
+        .bind(target_id)
+        .execute(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?
+        .rows_affected();
+
+        let mut repointed = [0; 3];
+        for (count, table) in
+            repointed
+                .iter_mut()
+                .zip(["ExerciseTrainingDayLink", "PersonalRecords", "Comments"])
+        {
+            *count = sqlx::query(&format!(
+                "UPDATE {table} SET exercise_id = $2 WHERE exercise_id = ANY($1)"
+            ))
+            .bind(source_ids)
+            .bind(target_id)
+            .execute(transaction.as_mut())
+            .await
+            .map_err(|e| e.to_string())?
+            .rows_affected();
+        }
+        let [links, personal_records, comments] = repointed;
+
+        // The moved sets compete with the target's own for its PRs
+        sqlx::query("SELECT recompute_personal_records($1)")
+            .bind(target_id)
+            .execute(transaction.as_mut())
+            .await
+            .map_err(|e| e.to_string())?;
 
-            let result = sqlx::query_as::<_, Exercise>(&query)
-                .bind(&exercise.exercise_name)
-                .bind(&exercise.exercise_description)
-                .fetch_one(&self.pool)
+        if !aliases.is_empty() {
+            sqlx::query("UPDATE Exercises SET aliases = aliases || $2 WHERE exercise_id = $1")
+                .bind(target_id)
+                .bind(aliases)
+                .execute(transaction.as_mut())
                 .await
                 .map_err(|e| e.to_string())?;
+        }
+        sqlx::query("DELETE FROM Exercises WHERE exercise_id = ANY($1)")
+            .bind(source_ids)
+            .execute(transaction.as_mut())
+            .await
+            .map_err(|e| e.to_string())?;
 
-            results.push(result);
+        if dry_run {
+            transaction.rollback().await.map_err(|e| e.to_string())?;
+        } else {
+            transaction.commit().await.map_err(|e| e.to_string())?;
         }
 
-        Ok(results)
+        Ok(MergeReport {
+            target_id: *target_id,
+            dry_run,
+            links,
+            duplicate_links,
+            sets: moved.len() as u64,
+            renumbered_sets: moved
+                .into_iter()
+                .filter(|set| set.from_set_number != set.to_set_number)
+                .collect(),
+            personal_records,
+            comments,
+            ..Default::default()
+        })
     }
 
     async fn add_exercise_to_training_day(
         &self,
+        user_id: &Uuid,
         exercise_id: &uuid::Uuid,
         day_id: &uuid::Uuid,
     ) -> ExerciseToTrainingDayResult<ExerciseToTrainingDay> {
         sqlx::query_as::<_, ExerciseToTrainingDay>(
             r#"
       INSERT INTO ExerciseTrainingDayLink (exercise_id, day_id)
-      VALUES ($1, $2)
-      RETURNING link_id, exercise_id, day_id, created_at, updated_at
+      SELECT e.exercise_id, $2
+      FROM Exercises e
+      WHERE e.exercise_id = $1
+        AND has_access($3, day_owner($2), 'edit_routines')
+        -- a library exercise, or one of the editor's or the athlete's own
+        AND (e.builtin OR e.owner_id = $3 OR e.owner_id = day_owner($2))
+      RETURNING link_id, exercise_id, day_id, notes, created_at, updated_at
       "#,
         )
         .bind(&exercise_id)
         .bind(&day_id)
-        .fetch_one(&self.pool)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| {
+            format!(
+                "Training day {} or exercise {} not found",
+                day_id, exercise_id
+            )
+        })
     }
 
     async fn get_exercises_for_training_day(
         &self,
+        user_id: &Uuid,
         day_id: &uuid::Uuid,
     ) -> SelectedExercisesWithLinkIdResult<Vec<ExerciseWithLinkId>> {
         sqlx::query_as(
@@ -379,15 +1036,20 @@ impl RoutinesRepository for PostgresRoutinesRepository {
             e.exercise_id,
             e.exercise_name,
             e.exercise_description,
+            e.load_type,
+            e.tracked_metrics,
+            l.notes,
             e.created_at,
             e.updated_at,
             l.link_id AS "link_id"
         FROM exercises e
         JOIN ExerciseTrainingDayLink l
         ON e.exercise_id = l.exercise_id
-        WHERE l.day_id = $1"#,
+        WHERE l.day_id = $1
+            AND has_access($2, day_owner(l.day_id), 'read_routines')"#,
         )
         .bind(&day_id)
+        .bind(user_id)
         .fetch_all(&self.pool)
         .await
         .map_err(|e| e.to_string())
@@ -395,16 +1057,19 @@ impl RoutinesRepository for PostgresRoutinesRepository {
 
     async fn remove_exercise_from_training_day(
         &self,
+        user_id: &Uuid,
         link_id: &uuid::Uuid,
     ) -> ExerciseToTrainingDayResult<Uuid> {
         sqlx::query_scalar::<_, Uuid>(
             r#"
       DELETE FROM ExerciseTrainingDayLink
       WHERE link_id = $1
+        AND has_access($2, day_owner(day_id), 'edit_routines')
       RETURNING link_id
       "#,
         )
         .bind(&link_id)
+        .bind(user_id)
         .fetch_one(&self.pool)
         .await
         .map_err(|e| e.to_string())
@@ -413,7 +1078,7 @@ impl RoutinesRepository for PostgresRoutinesRepository {
     async fn get_link_table_data(&self) -> ExerciseToTrainingDayResult<Vec<ExerciseToTrainingDay>> {
         sqlx::query_as::<_, ExerciseToTrainingDay>(
             r#"
-      SELECT link_id, exercise_id, day_id, created_at, updated_at
+      SELECT link_id, exercise_id, day_id, notes, created_at, updated_at
       FROM ExerciseTrainingDayLink
       "#,
         )
@@ -424,6 +1089,7 @@ impl RoutinesRepository for PostgresRoutinesRepository {
 
     async fn get_training_days_with_exercises(
         &self,
+        user_id: &Uuid,
         routine_id: &Uuid,
     ) -> Result<Vec<TrainingDayWithExercises>, sqlx::Error> {
         let query = sqlx::query_as::<_, TrainingDayWithExercisesQuery>(
@@ -437,6 +1103,9 @@ impl RoutinesRepository for PostgresRoutinesRepository {
                 e.exercise_id AS exercise_id,
                 e.exercise_name AS exercise_name,
                 e.exercise_description AS exercise_description,
+                COALESCE(e.load_type, 'external') AS load_type,
+                COALESCE(e.tracked_metrics, ARRAY['reps', 'weight']) AS tracked_metrics,
+                etdl.notes AS notes,
                 etdl.link_id AS link_id
             FROM
                 TrainingDays td
@@ -446,9 +1115,11 @@ impl RoutinesRepository for PostgresRoutinesRepository {
                 Exercises e ON etdl.exercise_id = e.exercise_id
             WHERE
                 td.routine_id = $1
+                AND has_access($2, routine_owner(td.routine_id), 'read_routines')
             "#,
         )
-        .bind(routine_id);
+        .bind(routine_id)
+        .bind(user_id);
 
         let rows = query.fetch_all(&self.pool).await?;
 
@@ -477,6 +1148,9 @@ impl RoutinesRepository for PostgresRoutinesRepository {
                     exercise_id,
                     exercise_name,
                     exercise_description,
+                    load_type: row.load_type,
+                    tracked_metrics: row.tracked_metrics,
+                    notes: row.notes,
                     link_id,
                     created_at,
                     updated_at,
@@ -512,17 +1186,23 @@ impl RoutinesRepository for PostgresRoutinesRepository {
         Ok(result)
     }
 
-    async fn is_previous_session_in_progress(&self, day_id: &Uuid) -> SessionResult<bool> {
+    async fn is_previous_session_in_progress(
+        &self,
+        user_id: &Uuid,
+        day_id: &Uuid,
+    ) -> SessionResult<bool> {
         let query = sqlx::query_as::<_, (bool,)>(
             r#"
         SELECT EXISTS (
             SELECT 1
             FROM Sessions
             WHERE day_id = $1 AND in_progress = true
+            AND has_access($2, user_id, 'read_history')
         ) AS previous_session_in_progress
         "#,
         )
         .bind(day_id)
+        .bind(user_id)
         .fetch_one(&self.pool)
         .await
         .map_err(|e| e.to_string());
@@ -538,6 +1218,7 @@ impl RoutinesRepository for PostgresRoutinesRepository {
 
     async fn get_session_in_progress(
         &self,
+        user_id: &Uuid,
         routine_id: &Uuid,
     ) -> SessionResult<Option<SessionWithExercisePerformance>> {
         let active_session_query = sqlx::query_as::<_, Session>(
@@ -546,121 +1227,59 @@ impl RoutinesRepository for PostgresRoutinesRepository {
         FROM Sessions s
         LEFT JOIN TrainingDays td ON s.day_id = td.day_id
         WHERE td.routine_id = $1 AND s.in_progress = true
+        AND has_access($2, s.user_id, 'read_history')
         LIMIT 1
         "#,
         )
         .bind(routine_id)
+        .bind(user_id)
         .fetch_optional(&self.pool)
         .await
         .map_err(|e| SessionError::Error(e.to_string()))?;
 
-        if let Some(active_session) = active_session_query {
-            let exercises_query = sqlx::query_as::<_, ExerciseWithLinkId>(
-                r#"
-            SELECT
-                e.exercise_id,
-                etdl.link_id,
-                e.exercise_name,
-                e.exercise_description,
-                e.created_at,
-                e.updated_at
-            FROM
-                ExerciseTrainingDayLink etdl
-            LEFT JOIN
-                Exercises e ON etdl.exercise_id = e.exercise_id
-            WHERE
-                etdl.day_id = $1
-            "#,
-            )
-            .bind(active_session.day_id)
-            .fetch_all(&self.pool)
-            .await
-            .map_err(|e| SessionError::Error(e.to_string()))?;
-
-            // Initialize a vector to hold SessionPerformance objects
-            let mut session_performance_vec = Vec::new();
-
-            // Iterate over fetched exercises
-            for exercise in exercises_query.iter() {
-                // Query SessionExercisePerformance table to get set data for this exercise within the active session
-                let sets_query = sqlx::query_as::<_, SetPerformance>(
-                    r#"
-                SELECT
-                    performance_id,
-                    set_number,
-                    weight,
-                    reps,
-                    rir,
-                    created_at,
-                    updated_at
-                FROM
-                    SessionExercisePerformance
-                WHERE
-                    session_id = $1 AND
-                    exercise_id = $2
-                ORDER BY
-                    set_number
-                "#,
-                )
-                .bind(active_session.session_id)
-                .bind(exercise.exercise_id)
-                .fetch_all(&self.pool)
-                .await
-                .map_err(|e| SessionError::Error(e.to_string()))?;
-
-                // Create SessionPerformance object for this exercise, populating it with fetched set data
-                let session_performance = SessionPerformance {
-                    session_id: active_session.session_id,
-                    exercise_id: exercise.exercise_id,
-                    exercise_name: exercise.exercise_name.clone(),
-                    sets: sets_query,
-                    created_at: None, // Modify as needed
-                    updated_at: None, // Modify as needed
-                };
-
-                // Add the SessionPerformance object to the vector
-                session_performance_vec.push(session_performance);
-            }
-
-            // Construct the SessionWithExercisePerformance object
-            let session_with_exercises = SessionWithExercisePerformance {
-                session_id: active_session.session_id,
-                day_id: active_session.day_id,
-                day_name: active_session.day_name,
-                in_progress: active_session.in_progress,
-                exercises: exercises_query,
-                performance: session_performance_vec,
-                created_at: active_session.created_at,
-                updated_at: active_session.updated_at,
-            };
-
-            Ok(Some(session_with_exercises))
-        } else {
-            Ok(None) // No active session found
+        match active_session_query {
+            Some(active_session) => Ok(Some(self.load_session_performance(active_session).await?)),
+            None => Ok(None), // No active session found
         }
     }
 
-    async fn create_session(&self, day_id: &Uuid) -> SessionResult<SessionWithExercisePerformance> {
-        if self.is_previous_session_in_progress(day_id).await? {
+    async fn create_session(
+        &self,
+        user_id: &Uuid,
+        day_id: &Uuid,
+    ) -> SessionResult<SessionWithExercisePerformance> {
+        if self
+            .is_previous_session_in_progress(user_id, day_id)
+            .await?
+        {
             return Err(SessionError::PreviousSessionInProgress);
         }
 
-        // Fetch the day_name associated with the provided day_id
-        let day_name_query =
-            sqlx::query_scalar::<_, String>("SELECT day_name FROM TrainingDays WHERE day_id = $1")
-                .bind(day_id)
-                .fetch_one(&self.pool)
-                .await
-                .map_err(|e| SessionError::Error(e.to_string()))?;
+        // Fetch the day_name associated with the provided day_id, only the owner trains on it
+        let day_name_query = sqlx::query_scalar::<_, String>(
+            r#"
+        SELECT day_name
+        FROM TrainingDays
+        WHERE day_id = $1 AND has_access($2, day_owner(day_id), 'log_sessions')
+        "#,
+        )
+        .bind(day_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| SessionError::Error(e.to_string()))?
+        .ok_or_else(|| SessionError::Error(format!("Training day {} not found", day_id)))?;
 
         // Insert a new session into the database
         let session_query = sqlx::query_as::<_, Session>(
             r#"
-        INSERT INTO Sessions (day_id, day_name)
-        VALUES ($1, $2)
-        RETURNING session_id, day_id, day_name, in_progress, created_at, updated_at
+        INSERT INTO Sessions (user_id, day_id, day_name)
+        VALUES ($1, $2, $3)
+        RETURNING session_id, day_id, day_name, COALESCE(in_progress, false) AS in_progress, notes,
+            created_at, updated_at
         "#,
         )
+        .bind(user_id)
         .bind(day_id)
         .bind(&day_name_query)
         .fetch_one(&self.pool)
@@ -675,6 +1294,9 @@ impl RoutinesRepository for PostgresRoutinesRepository {
             etdl.link_id,
             e.exercise_name,
             e.exercise_description,
+            e.load_type,
+            e.tracked_metrics,
+            etdl.notes,
             e.created_at,
             e.updated_at
         FROM
@@ -690,16 +1312,22 @@ impl RoutinesRepository for PostgresRoutinesRepository {
         .await
         .map_err(|e| SessionError::Error(e.to_string()))?;
 
-        // Initialize a vector to hold SessionPerformance objects
+        let mut previous_notes = self
+            .load_previous_notes(&session_query.session_id, &exercises_query)
+            .await?;
+
+        // Initialize a vector to hold SessionPerformance objects
         let mut session_performance_vec = Vec::new();
 
         // Iterate over fetched exercises and initialize SessionPerformance for each
         for exercise in exercises_query.iter() {
-            let session_performance = SessionPerformance::new(
+            let mut session_performance = SessionPerformance::new(
                 session_query.session_id,
                 exercise.exercise_id,
                 exercise.exercise_name.clone(),
             );
+            session_performance.tracked_metrics = exercise.tracked_metrics.clone();
+            session_performance.previous_note = previous_notes.remove(&exercise.exercise_id);
             session_performance_vec.push(session_performance);
         }
 
@@ -709,8 +1337,10 @@ impl RoutinesRepository for PostgresRoutinesRepository {
             day_id: session_query.day_id,
             day_name: session_query.day_name,
             in_progress: session_query.in_progress,
+            notes: session_query.notes,
             exercises: exercises_query,
             performance: session_performance_vec, // Set the performance vector with initialized SessionPerformance objects
+            comments: Vec::new(),
             created_at: session_query.created_at,
             updated_at: session_query.updated_at,
         };
@@ -718,15 +1348,51 @@ impl RoutinesRepository for PostgresRoutinesRepository {
         Ok(session_with_exercises)
     }
 
-    async fn get_all_sessions_by_day_id(&self, day_id: &Uuid) -> SessionResult<Vec<Session>> {
+    async fn get_session(
+        &self,
+        user_id: &Uuid,
+        session_id: &Uuid,
+    ) -> SessionResult<Option<Session>> {
+        sqlx::query_as::<_, Session>(
+            r#"
+            SELECT session_id, day_id, day_name, in_progress, notes, created_at, updated_at
+            FROM Sessions
+            WHERE session_id = $1 AND has_access($2, user_id, 'read_history')
+            "#,
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| SessionError::Error(e.to_string()))
+    }
+
+    async fn get_session_with_performance(
+        &self,
+        user_id: &Uuid,
+        session_id: &Uuid,
+    ) -> SessionResult<Option<SessionWithExercisePerformance>> {
+        match self.get_session(user_id, session_id).await? {
+            Some(session) => Ok(Some(self.load_session_performance(session).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_all_sessions_by_day_id(
+        &self,
+        user_id: &Uuid,
+        day_id: &Uuid,
+    ) -> SessionResult<Vec<Session>> {
         sqlx::query_as::<_, Session>(
             r#"
             SELECT * 
             FROM Sessions
         WHERE Sessions.day_id = $1
+            AND has_access($2, Sessions.user_id, 'read_history')
             "#,
         )
         .bind(&day_id)
+        .bind(user_id)
         .fetch_all(&self.pool)
         .await
         .map_err(|e| SessionError::Error(e.to_string()))
@@ -734,17 +1400,21 @@ impl RoutinesRepository for PostgresRoutinesRepository {
 
     async fn get_all_sessions_by_routine_id(
         &self,
+        user_id: &Uuid,
         routine_id: &Uuid,
     ) -> SessionResult<Vec<Session>> {
         sqlx::query_as::<_, Session>(
             r#"
-            SELECT s.session_id, s.day_id, s.day_name, s.in_progress, s.created_at, s.updated_at
+            SELECT s.session_id, s.day_id, s.day_name, s.in_progress, s.notes, s.created_at,
+                s.updated_at
             FROM Sessions s
             LEFT JOIN TrainingDays td ON s.day_id = td.day_id
             WHERE td.routine_id = $1
+            AND has_access($2, s.user_id, 'read_history')
             "#,
         )
         .bind(&routine_id)
+        .bind(user_id)
         .fetch_all(&self.pool)
         .await
         .map_err(|e| SessionError::Error(e.to_string()))
@@ -752,6 +1422,7 @@ impl RoutinesRepository for PostgresRoutinesRepository {
 
     async fn get_sessions_with_exercises(
         &self,
+        user_id: &Uuid,
         day_id: &Uuid,
     ) -> SessionResult<Vec<SessionWithExercises>> {
         let query = sqlx::query_as::<_, SessionsWithExercisesQuery>(
@@ -761,9 +1432,13 @@ impl RoutinesRepository for PostgresRoutinesRepository {
             s.day_id,
             s.day_name,
             s.in_progress,
+            s.notes,
             e.exercise_id,
             e.exercise_name,
             e.exercise_description,
+            COALESCE(e.load_type, 'external') AS load_type,
+            COALESCE(e.tracked_metrics, ARRAY['reps', 'weight']) AS tracked_metrics,
+            etdl.notes AS link_notes,
             etdl.link_id,
             s.created_at,
             s.updated_at
@@ -777,9 +1452,11 @@ impl RoutinesRepository for PostgresRoutinesRepository {
             Exercises e ON etdl.exercise_id = e.exercise_id
         WHERE 
             s.day_id = $1
+            AND has_access($2, s.user_id, 'read_history')
         "#,
         )
-        .bind(&day_id);
+        .bind(&day_id)
+        .bind(user_id);
 
         let rows = match query.fetch_all(&self.pool).await {
             Ok(rows) => rows,
@@ -793,6 +1470,7 @@ impl RoutinesRepository for PostgresRoutinesRepository {
             let day_id = row.day_id;
             let day_name = row.day_name;
             let in_progress = row.in_progress;
+            let notes = row.notes;
             let exercise_id = row.exercise_id;
             let exercise_name = row.exercise_name;
             let exercise_description = row.exercise_description;
@@ -807,6 +1485,7 @@ impl RoutinesRepository for PostgresRoutinesRepository {
                     day_id,
                     day_name,
                     in_progress,
+                    notes,
                     exercises: Vec::new(),
                     created_at,
                     updated_at,
@@ -817,6 +1496,9 @@ impl RoutinesRepository for PostgresRoutinesRepository {
                 link_id,
                 exercise_name,
                 exercise_description,
+                load_type: row.load_type,
+                tracked_metrics: row.tracked_metrics,
+                notes: row.link_notes,
                 created_at,
                 updated_at,
             });
@@ -833,17 +1515,18 @@ impl RoutinesRepository for PostgresRoutinesRepository {
         Ok(sessions)
     }
 
-    async fn end_session(&self, session_id: &Uuid) -> SessionResult<Uuid> {
+    async fn end_session(&self, user_id: &Uuid, session_id: &Uuid) -> SessionResult<Uuid> {
         // Update the `in_progress` field of the session to false in the database
         let end_session_query = sqlx::query(
             r#"
         UPDATE Sessions
         SET in_progress = FALSE
-        WHERE session_id = $1
+        WHERE session_id = $1 AND has_access($2, user_id, 'log_sessions')
         RETURNING session_id
         "#,
         )
         .bind(session_id)
+        .bind(user_id)
         .execute(&self.pool)
         .await
         .map_err(|e| SessionError::Error(e.to_string()))?;
@@ -854,50 +1537,110 @@ impl RoutinesRepository for PostgresRoutinesRepository {
         }
     }
 
+    async fn get_session_owner(&self, session_id: &Uuid) -> RoutineResult<Option<Uuid>> {
+        sqlx::query_scalar::<_, Option<Uuid>>("SELECT session_owner($1)")
+            .bind(session_id)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn get_routine_owner(&self, routine_id: &Uuid) -> RoutineResult<Option<Uuid>> {
+        sqlx::query_scalar::<_, Option<Uuid>>("SELECT routine_owner($1)")
+            .bind(routine_id)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn get_tracked_metrics(
+        &self,
+        exercise_id: &Uuid,
+    ) -> RoutineResult<Option<TrackedMetrics>> {
+        let metrics: Option<Vec<String>> =
+            sqlx::query_scalar("SELECT tracked_metrics FROM Exercises WHERE exercise_id = $1")
+                .bind(exercise_id)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(|e| e.to_string())?;
+        metrics.map(TrackedMetrics::try_from).transpose()
+    }
+
     async fn add_set_performance_to_session(
         &self,
+        user_id: &Uuid,
         session_id: &Uuid,
         exercise_id: &Uuid,
         set_performance: &SetPerformancePayload,
     ) -> SessionResult<SetPerformance> {
-        let query = sqlx::query_as::<_, SetPerformance>(
+        let query = sqlx::query_as::<_, SetPerformance>(&format!(
            r#"
-        INSERT INTO SessionExercisePerformance (session_id, exercise_id, set_number, weight, reps, rir)
-        VALUES ($1, $2, $3, $4, $5, $6)
+        INSERT INTO SessionExercisePerformance (session_id, exercise_id, set_number, weight, reps, rir, notes,
+            duration_seconds, distance_meters, calories, heart_rate)
+        SELECT $1, e.exercise_id, $3, $4, $5, $6, NULLIF($8, ''), $9, $10, $11, $12
+        FROM Exercises e
+        WHERE e.exercise_id = $2
+            AND has_access($7, session_owner($1), 'log_sessions')
+            -- a library exercise, one of the lifter's own, or one their training day has
+            AND (e.builtin OR e.owner_id = $7 OR EXISTS (
+                SELECT 1
+                FROM ExerciseTrainingDayLink l
+                JOIN Sessions s ON s.day_id = l.day_id
+                WHERE s.session_id = $1 AND l.exercise_id = e.exercise_id
+            ))
         ON CONFLICT (session_id, exercise_id, set_number) -- Conflict resolution
         DO UPDATE SET
             weight = EXCLUDED.weight,
             reps = EXCLUDED.reps,
             rir = EXCLUDED.rir,
+            notes = EXCLUDED.notes,
+            duration_seconds = EXCLUDED.duration_seconds,
+            distance_meters = EXCLUDED.distance_meters,
+            calories = EXCLUDED.calories,
+            heart_rate = EXCLUDED.heart_rate,
             updated_at = CURRENT_TIMESTAMP
-        RETURNING performance_id, set_number, weight, reps, rir, created_at, updated_at
+        RETURNING {SET_COLUMNS}
         "#,
-        )
+        ))
         .bind(&session_id)
         .bind(&exercise_id)
         .bind(&set_performance.set_number)
         .bind(&set_performance.weight)
         .bind(&set_performance.reps)
         .bind(&set_performance.rir)
-        .fetch_one(&self.pool)
+        .bind(user_id)
+        .bind(&set_performance.notes)
+        .bind(set_performance.duration_seconds)
+        .bind(set_performance.distance_meters)
+        .bind(set_performance.calories)
+        .bind(set_performance.heart_rate)
+        .fetch_optional(&self.pool)
         .await
-        .map_err(|e| SessionError::Error(e.to_string()))?;
+        .map_err(|e| SessionError::Error(e.to_string()))?
+        .ok_or_else(|| {
+            SessionError::Error(format!(
+                "Session {} or exercise {} not found",
+                session_id, exercise_id
+            ))
+        })?;
 
         Ok(query)
     }
 
     async fn remove_set_performance_from_session(
         &self,
+        user_id: &Uuid,
         performance_id: &Uuid,
-    ) -> SessionResult<Uuid> {
-        let query = sqlx::query_scalar::<_, Uuid>(
+    ) -> SessionResult<RemovedSetPerformance> {
+        let query = sqlx::query_as::<_, RemovedSetPerformance>(
             r#"
         DELETE FROM SessionExercisePerformance
-        WHERE performance_id = $1
-        RETURNING performance_id
+        WHERE performance_id = $1 AND has_access($2, session_owner(session_id), 'log_sessions')
+        RETURNING performance_id, session_id, exercise_id
         "#,
         )
         .bind(&performance_id)
+        .bind(user_id)
         .fetch_one(&self.pool)
         .await
         .map_err(|e| SessionError::Error(e.to_string()))?;
@@ -905,40 +1648,2283 @@ impl RoutinesRepository for PostgresRoutinesRepository {
         Ok(query)
     }
 
-    async fn clear_data(&self) -> Result<(), sqlx::Error> {
-        let mut transaction = self.pool.begin().await?;
+    async fn close_stale_sessions(
+        &self,
+        user_id: &Uuid,
+        started_before: DateTime<Utc>,
+    ) -> SessionResult<Vec<Session>> {
+        sqlx::query_as::<_, Session>(
+            r#"
+        UPDATE Sessions
+        SET in_progress = FALSE
+        WHERE in_progress = TRUE AND created_at < $1 AND user_id = $2
+        RETURNING session_id, day_id, day_name, in_progress, notes, created_at, updated_at
+        "#,
+        )
+        .bind(started_before)
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| SessionError::Error(e.to_string()))
+    }
 
-        // Delete all rows from the ExerciseTrainingDayLink table
-        sqlx::query("DELETE FROM ExerciseTrainingDayLink")
-            .execute(transaction.as_mut())
-            .await?;
+    async fn record_personal_record(
+        &self,
+        session_id: &Uuid,
+        exercise_id: &Uuid,
+        set_performance: &SetPerformance,
+    ) -> RoutineResult<Option<PersonalRecord>> {
+        if set_performance.effective_load <= Decimal::ZERO || set_performance.reps <= 0 {
+            return self
+                .remove_personal_record(&set_performance.performance_id)
+                .await
+                .map(|_| None);
+        }
+        let estimated_one_rep_max =
+            estimated_one_rep_max(set_performance.effective_load, set_performance.reps);
 
-        // Delete all rows from the Sessions table
-        sqlx::query("DELETE FROM Sessions")
-            .execute(transaction.as_mut())
-            .await?;
+        // Compared against every set the session's owner logged, not just earlier PRs, so history
+        // from before PRs were tracked still counts. The CASE mirrors `estimated_one_rep_max`.
+        // Records are on the effective load so bodyweight movements count the lifter.
+        let record = sqlx::query_as::<_, PersonalRecord>(
+            r#"
+        INSERT INTO PersonalRecords (exercise_id, session_id, performance_id, weight, reps, estimated_one_rep_max)
+        SELECT $1, $2, $3, $4, $5, $6
+        WHERE NOT EXISTS (
+            SELECT 1
+            FROM SessionExercisePerformance
+            WHERE exercise_id = $1
+            AND session_owner(session_id) IS NOT DISTINCT FROM session_owner($2)
+            AND performance_id <> $3
+            AND reps > 0
+            AND ROUND(CASE WHEN reps <= 1 THEN effective_load ELSE effective_load * (30 + reps) / 30 END, 3) >= $6
+        )
+        ON CONFLICT (performance_id) DO UPDATE SET
+            exercise_id = EXCLUDED.exercise_id,
+            session_id = EXCLUDED.session_id,
+            weight = EXCLUDED.weight,
+            reps = EXCLUDED.reps,
+            estimated_one_rep_max = EXCLUDED.estimated_one_rep_max
+        RETURNING record_id, exercise_id, session_id, performance_id, weight, reps, estimated_one_rep_max, created_at
+        "#,
+        )
+        .bind(exercise_id)
+        .bind(session_id)
+        .bind(set_performance.performance_id)
+        .bind(set_performance.effective_load)
+        .bind(set_performance.reps)
+        .bind(estimated_one_rep_max)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
 
-        // Delete all rows from the TrainingDays table
-        sqlx::query("DELETE FROM TrainingDays")
-            .execute(transaction.as_mut())
-            .await?;
+        // A set edited below another one isn't a PR anymore
+        if record.is_none() {
+            self.remove_personal_record(&set_performance.performance_id)
+                .await?;
+        }
+        Ok(record)
+    }
 
-        // Delete all rows from the Exercises table
-        sqlx::query("DELETE FROM Exercises")
-            .execute(transaction.as_mut())
-            .await?;
+    async fn remove_set_performance_from_session_exercise(
+        &self,
+        user_id: &Uuid,
+        session_id: &Uuid,
+        exercise_id: &Uuid,
+        performance_id: &Uuid,
+    ) -> SessionResult<Option<Uuid>> {
+        sqlx::query_scalar::<_, Uuid>(
+            r#"
+        DELETE FROM SessionExercisePerformance
+        WHERE performance_id = $1 AND session_id = $2 AND exercise_id = $3
+            AND has_access($4, session_owner(session_id), 'log_sessions')
+        RETURNING performance_id
+        "#,
+        )
+        .bind(performance_id)
+        .bind(session_id)
+        .bind(exercise_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| SessionError::Error(e.to_string()))
+    }
 
-        // Delete all rows from the Routines table
-        sqlx::query("DELETE FROM Routines")
-            .execute(transaction.as_mut())
-            .await?;
+    async fn patch_routine(
+        &self,
+        user_id: &Uuid,
+        routine_id: &Uuid,
+        patch: &PatchRoutine,
+        expected_version: Option<DateTime<Utc>>,
+    ) -> PatchResult<Routine> {
+        let routine = sqlx::query_as::<_, Routine>(
+            r#"
+      UPDATE routines
+      SET name = COALESCE($2, name),
+          description = COALESCE($3, description),
+          is_active = COALESCE($4, is_active)
+      WHERE routine_id = $1
+        AND ($5::timestamptz IS NULL OR COALESCE(updated_at, created_at) = $5)
+        AND has_access($6, user_id, 'edit_routines')
+      RETURNING routine_id, name, description, is_active, created_at, updated_at
+      "#,
+        )
+        .bind(routine_id)
+        .bind(&patch.name)
+        .bind(&patch.description)
+        .bind(patch.is_active)
+        .bind(expected_version)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
 
-        sqlx::query("DELETE FROM SessionExercisePerformance")
-            .execute(transaction.as_mut())
-            .await?;
+        match routine {
+            Some(routine) => Ok(routine),
+            None => Err(self
+                .patch_miss(
+                    r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM routines
+            WHERE routine_id = $1 AND has_access($2, user_id, 'edit_routines')
+        )
+        "#,
+                    &[routine_id, user_id],
+                )
+                .await),
+        }
+    }
 
-        transaction.commit().await?;
+    async fn patch_training_day(
+        &self,
+        user_id: &Uuid,
+        day_id: &Uuid,
+        patch: &PatchTrainingDay,
+        expected_version: Option<DateTime<Utc>>,
+    ) -> PatchResult<TrainingDay> {
+        let day = sqlx::query_as::<_, TrainingDay>(
+            r#"
+      UPDATE trainingdays
+      SET day_name = COALESCE($2, day_name)
+      WHERE day_id = $1
+        AND ($3::timestamptz IS NULL OR COALESCE(updated_at, created_at) = $3)
+        AND has_access($4, routine_owner(routine_id), 'edit_routines')
+      RETURNING day_id, day_name, routine_id, created_at, updated_at
+      "#,
+        )
+        .bind(day_id)
+        .bind(&patch.day_name)
+        .bind(expected_version)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
 
-        Ok(())
+        match day {
+            Some(day) => Ok(day),
+            None => Err(self
+                .patch_miss(
+                    r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM trainingdays
+            WHERE day_id = $1 AND has_access($2, routine_owner(routine_id), 'edit_routines')
+        )
+        "#,
+                    &[day_id, user_id],
+                )
+                .await),
+        }
+    }
+
+    async fn patch_exercise(
+        &self,
+        user_id: &Uuid,
+        exercise_id: &Uuid,
+        patch: &PatchExercise,
+        expected_version: Option<DateTime<Utc>>,
+    ) -> PatchResult<Exercise> {
+        let exercise = sqlx::query_as::<_, Exercise>(&format!(
+            r#"
+      UPDATE Exercises
+      SET exercise_name = COALESCE($2, exercise_name),
+          exercise_description = COALESCE($3, exercise_description),
+          load_type = COALESCE($5, load_type),
+          tracked_metrics = COALESCE($6, tracked_metrics),
+          equipment = COALESCE($7, equipment),
+          movement_pattern = COALESCE($8, movement_pattern),
+          unilateral = COALESCE($9, unilateral),
+          primary_muscles = COALESCE($10, primary_muscles),
+          secondary_muscles = COALESCE($11, secondary_muscles),
+          aliases = COALESCE($12, aliases)
+      WHERE exercise_id = $1 AND owner_id = $13
+        AND ($4::timestamptz IS NULL OR COALESCE(updated_at, created_at) = $4)
+      RETURNING {EXERCISE_COLUMNS}
+      "#,
+        ))
+        .bind(exercise_id)
+        .bind(&patch.exercise_name)
+        .bind(&patch.exercise_description)
+        .bind(expected_version)
+        .bind(patch.load_type.map(|load_type| load_type.as_str()))
+        .bind(patch.tracked_metrics.as_ref().map(TrackedMetrics::as_strs))
+        .bind(patch.equipment.map(|equipment| equipment.as_str()))
+        .bind(patch.movement_pattern.map(|pattern| pattern.as_str()))
+        .bind(patch.unilateral)
+        .bind(patch.primary_muscles.as_ref().map(Muscles::as_strs))
+        .bind(patch.secondary_muscles.as_ref().map(Muscles::as_strs))
+        .bind(&patch.aliases)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        match exercise {
+            Some(exercise) => Ok(exercise),
+            None => Err(self
+                .patch_miss(
+                    "SELECT EXISTS (SELECT 1 FROM Exercises WHERE exercise_id = $1 AND owner_id = $2)",
+                    &[exercise_id, user_id],
+                )
+                .await),
+        }
+    }
+
+    async fn patch_exercise_link(
+        &self,
+        user_id: &Uuid,
+        link_id: &Uuid,
+        patch: &PatchExerciseToTrainingDay,
+        expected_version: Option<DateTime<Utc>>,
+    ) -> PatchResult<ExerciseToTrainingDay> {
+        let link = sqlx::query_as::<_, ExerciseToTrainingDay>(
+            r#"
+      UPDATE ExerciseTrainingDayLink
+      SET exercise_id = COALESCE($2, exercise_id),
+          day_id = COALESCE($3, day_id),
+          notes = CASE WHEN $6::text IS NULL THEN notes ELSE NULLIF($6, '') END
+      WHERE link_id = $1
+        AND ($4::timestamptz IS NULL OR COALESCE(updated_at, created_at) = $4)
+        AND has_access($5, day_owner(day_id), 'edit_routines')
+        -- moving the exercise needs edit access to the day it lands on as well
+        AND ($3::uuid IS NULL OR has_access($5, day_owner($3), 'edit_routines'))
+        -- the same exercises adding a link allows
+        AND ($2::uuid IS NULL OR EXISTS (
+            SELECT 1 FROM Exercises e
+            WHERE e.exercise_id = $2
+                AND (e.builtin OR e.owner_id = $5 OR e.owner_id = day_owner(COALESCE($3, day_id)))
+        ))
+      RETURNING link_id, exercise_id, day_id, notes, created_at, updated_at
+      "#,
+        )
+        .bind(link_id)
+        .bind(patch.exercise_id)
+        .bind(patch.day_id)
+        .bind(expected_version)
+        .bind(user_id)
+        .bind(&patch.notes)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        // an exercise the editor may not link reads as missing, not as a stale version
+        if link.is_none() && patch.exercise_id.is_some() {
+            let visible: bool = sqlx::query_scalar(
+                r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM Exercises e, ExerciseTrainingDayLink l
+            WHERE l.link_id = $1 AND e.exercise_id = $2
+                AND (e.builtin OR e.owner_id = $3 OR e.owner_id = day_owner(COALESCE($4, l.day_id)))
+        )
+        "#,
+            )
+            .bind(link_id)
+            .bind(patch.exercise_id)
+            .bind(user_id)
+            .bind(patch.day_id)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+            if !visible {
+                return Err(PatchError::NotFound);
+            }
+        }
+
+        match link {
+            Some(link) => Ok(link),
+            None => Err(self
+                .patch_miss(
+                    r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM ExerciseTrainingDayLink
+            WHERE link_id = $1 AND has_access($2, day_owner(day_id), 'edit_routines')
+        )
+        "#,
+                    &[link_id, user_id],
+                )
+                .await),
+        }
+    }
+
+    async fn patch_set_performance(
+        &self,
+        user_id: &Uuid,
+        session_id: &Uuid,
+        exercise_id: &Uuid,
+        performance_id: &Uuid,
+        patch: &PatchSetPerformance,
+        expected_version: Option<DateTime<Utc>>,
+    ) -> PatchResult<SetPerformance> {
+        let set_performance = sqlx::query_as::<_, SetPerformance>(&format!(
+            r#"
+        UPDATE SessionExercisePerformance
+        SET weight = COALESCE($4, weight),
+            reps = COALESCE($5, reps),
+            set_number = COALESCE($6, set_number),
+            rir = COALESCE($7, rir),
+            notes = CASE WHEN $10::text IS NULL THEN notes ELSE NULLIF($10, '') END,
+            duration_seconds = COALESCE($11, duration_seconds),
+            distance_meters = COALESCE($12, distance_meters),
+            calories = COALESCE($13, calories),
+            heart_rate = COALESCE($14, heart_rate)
+        WHERE performance_id = $1 AND session_id = $2 AND exercise_id = $3
+          AND ($8::timestamptz IS NULL OR COALESCE(updated_at, created_at) = $8)
+          AND has_access($9, session_owner(session_id), 'log_sessions')
+        RETURNING {SET_COLUMNS}
+        "#,
+        ))
+        .bind(performance_id)
+        .bind(session_id)
+        .bind(exercise_id)
+        .bind(patch.weight)
+        .bind(patch.reps)
+        .bind(patch.set_number)
+        .bind(patch.rir)
+        .bind(expected_version)
+        .bind(user_id)
+        .bind(&patch.notes)
+        .bind(patch.duration_seconds)
+        .bind(patch.distance_meters)
+        .bind(patch.calories)
+        .bind(patch.heart_rate)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db) if db.constraint() == Some("unique_set_number") => {
+                PatchError::Conflict(format!(
+                    "Set {} of this exercise is already logged in the session",
+                    patch.set_number.unwrap_or_default()
+                ))
+            }
+            _ => PatchError::Error(e.to_string()),
+        })?;
+
+        match set_performance {
+            Some(set_performance) => Ok(set_performance),
+            None => Err(self
+                .patch_miss(
+                    r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM SessionExercisePerformance
+            WHERE performance_id = $1 AND session_id = $2 AND exercise_id = $3
+            AND has_access($4, session_owner(session_id), 'log_sessions')
+        )
+        "#,
+                    &[performance_id, session_id, exercise_id, user_id],
+                )
+                .await),
+        }
+    }
+
+    async fn reserve_idempotency_key(
+        &self,
+        user_id: &Uuid,
+        key: &str,
+        method: &str,
+        path: &str,
+        request_hash: &str,
+        ttl_seconds: i64,
+    ) -> RoutineResult<IdempotencyReservation> {
+        // Claim the key, taking it over if the stored response is older than the replay window
+        let reserved = sqlx::query_scalar::<_, String>(
+            r#"
+        INSERT INTO IdempotencyKeys (user_id, idempotency_key, request_method, request_path, request_hash)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (user_id, idempotency_key) DO UPDATE
+        SET request_method = EXCLUDED.request_method,
+            request_path = EXCLUDED.request_path,
+            request_hash = EXCLUDED.request_hash,
+            status_code = NULL,
+            content_type = NULL,
+            response_body = NULL,
+            created_at = CURRENT_TIMESTAMP
+        WHERE IdempotencyKeys.created_at < CURRENT_TIMESTAMP - make_interval(secs => $6)
+        RETURNING idempotency_key
+        "#,
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(method)
+        .bind(path)
+        .bind(request_hash)
+        .bind(ttl_seconds as f64)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        if reserved.is_some() {
+            return Ok(IdempotencyReservation::Reserved);
+        }
+
+        sqlx::query_as::<_, IdempotencyRecord>(
+            r#"
+        SELECT request_hash, status_code, content_type, response_body
+        FROM IdempotencyKeys
+        WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        )
+        .bind(user_id)
+        .bind(key)
+        .fetch_one(&self.pool)
+        .await
+        .map(IdempotencyReservation::Existing)
+        .map_err(|e| e.to_string())
+    }
+
+    async fn complete_idempotency_key<'a>(
+        &self,
+        user_id: &Uuid,
+        key: &str,
+        status_code: i16,
+        content_type: Option<&'a str>,
+        response_body: &[u8],
+    ) -> RoutineResult<()> {
+        sqlx::query(
+            r#"
+        UPDATE IdempotencyKeys
+        SET status_code = $3, content_type = $4, response_body = $5
+        WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(status_code)
+        .bind(content_type)
+        .bind(response_body)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    }
+
+    async fn release_idempotency_key(&self, user_id: &Uuid, key: &str) -> RoutineResult<()> {
+        sqlx::query("DELETE FROM IdempotencyKeys WHERE user_id = $1 AND idempotency_key = $2")
+            .bind(user_id)
+            .bind(key)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn push_sync_mutations(
+        &self,
+        user_id: &Uuid,
+        mutations: &[SyncMutation],
+    ) -> RoutineResult<SyncPushResponse> {
+        let mut transaction = self.pool.begin().await.map_err(|e| e.to_string())?;
+        let mut response = SyncPushResponse::default();
+
+        for mutation in mutations {
+            // Savepoint per mutation so one bad mutation doesn't sink the whole batch
+            let mut savepoint = transaction.begin().await.map_err(|e| e.to_string())?;
+            // The change log triggers version what this mutation writes by its own time and id
+            sqlx::query(
+                "SELECT set_config('sync.changed_at', $1, true), set_config('sync.mutation_id', $2, true)",
+            )
+            .bind(mutation.client_timestamp.to_rfc3339())
+            .bind(mutation.mutation_id.to_string())
+            .execute(savepoint.as_mut())
+            .await
+            .map_err(|e| e.to_string())?;
+            match apply_sync_mutation(&mut savepoint, user_id, mutation).await {
+                Ok(conflicts) => {
+                    savepoint.commit().await.map_err(|e| e.to_string())?;
+                    if conflicts.is_empty() {
+                        response.applied.push(mutation.mutation_id);
+                    }
+                    response.conflicts.extend(conflicts);
+                }
+                Err(message) => {
+                    savepoint.rollback().await.map_err(|e| e.to_string())?;
+                    response.rejected.push(SyncRejection {
+                        mutation_id: mutation.mutation_id,
+                        message,
+                    });
+                }
+            }
+        }
+
+        response.cursor = sqlx::query_scalar::<_, i64>(
+            "SELECT COALESCE(MAX(position), 0) FROM ChangeLog WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_one(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?;
+
+        transaction.commit().await.map_err(|e| e.to_string())?;
+
+        Ok(response)
+    }
+
+    async fn get_logged_sets(&self, performance_ids: &[Uuid]) -> RoutineResult<Vec<LoggedSet>> {
+        sqlx::query_as::<_, LoggedSet>(&format!(
+            r#"
+        SELECT session_id, exercise_id, {SET_COLUMNS}
+        FROM SessionExercisePerformance
+        WHERE performance_id = ANY($1)
+        "#,
+        ))
+        .bind(performance_ids)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn pull_sync_changes(
+        &self,
+        user_id: &Uuid,
+        cursor: i64,
+        limit: i64,
+    ) -> RoutineResult<SyncPullResponse> {
+        let mut transaction = self.pool.begin().await.map_err(|e| e.to_string())?;
+        // One pull per user at a time hands out positions, to the changes of transactions that
+        // ended before every one still running. Anything committing later gets a later position.
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1::text))")
+            .bind(user_id)
+            .execute(transaction.as_mut())
+            .await
+            .map_err(|e| e.to_string())?;
+        sqlx::query(
+            r#"
+        UPDATE ChangeLog c SET position = numbered.position
+        FROM (
+            SELECT change_id, nextval('changelog_position_seq') AS position
+            FROM (
+                SELECT change_id FROM ChangeLog
+                WHERE user_id = $1 AND position IS NULL
+                    AND txid < pg_snapshot_xmin(pg_current_snapshot())
+                ORDER BY txid, change_id
+            ) settled
+        ) numbered
+        WHERE c.change_id = numbered.change_id
+        "#,
+        )
+        .bind(user_id)
+        .execute(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let mut changes = sqlx::query_as::<_, SyncChange>(
+            r#"
+        SELECT position AS change_id, entity_type AS entity, entity_id, operation, data, changed_at
+        FROM ChangeLog
+        WHERE user_id = $1 AND position > $2
+        ORDER BY position
+        LIMIT $3
+        "#,
+        )
+        .bind(user_id)
+        .bind(cursor)
+        .bind(limit + 1)
+        .fetch_all(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?;
+
+        transaction.commit().await.map_err(|e| e.to_string())?;
+
+        let has_more = changes.len() as i64 > limit;
+        changes.truncate(limit as usize);
+        let cursor = changes.last().map_or(cursor, |change| change.change_id);
+
+        Ok(SyncPullResponse {
+            changes,
+            cursor,
+            has_more,
+        })
+    }
+
+    fn export_history(
+        &self,
+        user_id: &Uuid,
+        query: &HistoryExportQuery,
+    ) -> BoxStream<'static, RoutineResult<HistoryExportRow>> {
+        let pool = self.pool.clone();
+        let query = query.clone();
+        let user_id = *user_id;
+        // A coach passes the athlete's id, everyone else exports their own history
+        let owner_id = query.user_id.unwrap_or(user_id);
+
+        async_stream::try_stream! {
+            // Sets don't have a type yet, every logged set is a working set
+            let mut rows = sqlx::query_as::<_, HistoryExportRow>(
+                r#"
+            SELECT
+                s.created_at AS session_date,
+                COALESCE(r.name, '') AS routine,
+                s.day_name AS training_day,
+                e.exercise_name AS exercise,
+                COALESCE(sep.set_number, 0) AS set_number,
+                'working' AS set_type,
+                COALESCE(sep.weight, 0) AS weight,
+                COALESCE(sep.effective_load, sep.weight, 0) AS effective_load,
+                COALESCE(sep.reps, 0) AS reps,
+                sep.rir,
+                sep.duration_seconds,
+                sep.distance_meters,
+                sep.calories,
+                sep.heart_rate
+            FROM SessionExercisePerformance sep
+            JOIN Sessions s ON s.session_id = sep.session_id
+            JOIN Exercises e ON e.exercise_id = sep.exercise_id
+            LEFT JOIN TrainingDays td ON td.day_id = s.day_id
+            LEFT JOIN Routines r ON r.routine_id = td.routine_id
+            WHERE ($1::date IS NULL OR s.created_at >= $1::date)
+            AND ($2::date IS NULL OR s.created_at < $2::date + 1)
+            AND ($3::uuid IS NULL OR td.routine_id = $3)
+            AND (s.user_id = $5 OR (s.user_id IS NULL AND $4 = $5))
+            AND has_access($4, s.user_id, 'read_history')
+            ORDER BY s.created_at, s.session_id, e.exercise_name, sep.set_number
+            "#,
+            )
+            .bind(query.from)
+            .bind(query.to)
+            .bind(query.routine_id)
+            .bind(user_id)
+            .bind(owner_id)
+            .fetch(&pool)
+            .map_err(|e| e.to_string());
+
+            while let Some(mut row) = rows.try_next().await? {
+                row.estimated_one_rep_max = estimated_one_rep_max(row.effective_load, row.reps);
+                yield row;
+            }
+        }
+        .boxed()
+    }
+
+    async fn import_sessions(
+        &self,
+        user_id: &Uuid,
+        sessions: &[ImportedSession],
+    ) -> SessionResult<usize> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| SessionError::Error(e.to_string()))?;
+
+        let mut set_count = 0;
+        for session in sessions {
+            // Imported sessions aren't tied to one of our training days
+            let session_id = sqlx::query_scalar::<_, Uuid>(
+                r#"
+            INSERT INTO Sessions (user_id, day_id, day_name, in_progress, created_at)
+            VALUES ($1, NULL, $2, FALSE, $3)
+            RETURNING session_id
+            "#,
+            )
+            .bind(user_id)
+            .bind(&session.day_name)
+            .bind(session.started_at)
+            .fetch_one(transaction.as_mut())
+            .await
+            .map_err(|e| SessionError::Error(e.to_string()))?;
+
+            let exercise_ids: Vec<Uuid> = session.sets.iter().map(|set| set.exercise_id).collect();
+            let set_numbers: Vec<i16> = session.sets.iter().map(|set| set.set_number).collect();
+            let weights: Vec<Decimal> = session.sets.iter().map(|set| set.weight).collect();
+            let reps: Vec<i16> = session.sets.iter().map(|set| set.reps).collect();
+            let rirs: Vec<Option<i16>> = session.sets.iter().map(|set| set.rir).collect();
+            let durations: Vec<Option<i32>> = session
+                .sets
+                .iter()
+                .map(|set| set.duration_seconds)
+                .collect();
+            let distances: Vec<Option<Decimal>> =
+                session.sets.iter().map(|set| set.distance_meters).collect();
+
+            sqlx::query(
+                r#"
+            INSERT INTO SessionExercisePerformance (session_id, exercise_id, set_number, weight, reps, rir,
+                duration_seconds, distance_meters, created_at)
+            SELECT $1, exercise_id, set_number, weight, reps, rir, duration_seconds, distance_meters, $9
+            FROM UNNEST($2::uuid[], $3::smallint[], $4::numeric[], $5::smallint[], $6::smallint[],
+                $7::integer[], $8::numeric[])
+                AS sets(exercise_id, set_number, weight, reps, rir, duration_seconds, distance_meters)
+            "#,
+            )
+            .bind(session_id)
+            .bind(&exercise_ids)
+            .bind(&set_numbers)
+            .bind(&weights)
+            .bind(&reps)
+            .bind(&rirs)
+            .bind(&durations)
+            .bind(&distances)
+            .bind(session.started_at)
+            .execute(transaction.as_mut())
+            .await
+            .map_err(|e| SessionError::Error(e.to_string()))?;
+
+            set_count += session.sets.len();
+        }
+
+        // The imported history makes PRs like any other sets
+        let mut imported_exercises: Vec<Uuid> = sessions
+            .iter()
+            .flat_map(|session| session.sets.iter().map(|set| set.exercise_id))
+            .collect();
+        imported_exercises.sort();
+        imported_exercises.dedup();
+        for exercise_id in imported_exercises {
+            sqlx::query("SELECT recompute_personal_records($1)")
+                .bind(exercise_id)
+                .execute(transaction.as_mut())
+                .await
+                .map_err(|e| SessionError::Error(e.to_string()))?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| SessionError::Error(e.to_string()))?;
+
+        Ok(set_count)
+    }
+
+    async fn create_webhook(
+        &self,
+        user_id: &Uuid,
+        url: &str,
+        events: &[String],
+        secret: &str,
+    ) -> RoutineResult<Webhook> {
+        sqlx::query_as::<_, Webhook>(
+            r#"
+        INSERT INTO Webhooks (user_id, url, events, secret)
+        VALUES ($1, $2, $3, $4)
+        RETURNING webhook_id, url, events, is_active, created_at, updated_at
+        "#,
+        )
+        .bind(user_id)
+        .bind(url)
+        .bind(events)
+        .bind(secret)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn get_webhooks(&self, user_id: &Uuid) -> RoutineResult<Vec<Webhook>> {
+        sqlx::query_as::<_, Webhook>(
+            r#"
+        SELECT webhook_id, url, events, is_active, created_at, updated_at
+        FROM Webhooks
+        WHERE user_id = $1
+        ORDER BY created_at
+        "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn delete_webhook(
+        &self,
+        user_id: &Uuid,
+        webhook_id: &Uuid,
+    ) -> RoutineResult<Option<Uuid>> {
+        sqlx::query_scalar::<_, Uuid>(
+            r#"
+        DELETE FROM Webhooks
+        WHERE webhook_id = $1 AND user_id = $2
+        RETURNING webhook_id
+        "#,
+        )
+        .bind(webhook_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn get_webhook_target(&self, webhook_id: &Uuid) -> RoutineResult<Option<WebhookTarget>> {
+        sqlx::query_as::<_, WebhookTarget>(
+            "SELECT url, secret, is_active FROM Webhooks WHERE webhook_id = $1",
+        )
+        .bind(webhook_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn create_webhook_deliveries(
+        &self,
+        user_id: &Uuid,
+        event_type: &str,
+        payload: &serde_json::Value,
+    ) -> RoutineResult<Vec<WebhookDelivery>> {
+        sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+        INSERT INTO WebhookDeliveries (webhook_id, event_type, payload)
+        SELECT webhook_id, $2, $3
+        FROM Webhooks
+        WHERE user_id = $1 AND is_active = TRUE AND (cardinality(events) = 0 OR $2 = ANY(events))
+        RETURNING *
+        "#,
+        )
+        .bind(user_id)
+        .bind(event_type)
+        .bind(payload)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn get_webhook_deliveries(
+        &self,
+        user_id: &Uuid,
+        webhook_id: &Uuid,
+        limit: i64,
+    ) -> RoutineResult<Vec<WebhookDelivery>> {
+        sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+        SELECT wd.*
+        FROM WebhookDeliveries wd
+        JOIN Webhooks w ON w.webhook_id = wd.webhook_id
+        WHERE wd.webhook_id = $1 AND w.user_id = $2
+        ORDER BY wd.created_at DESC
+        LIMIT $3
+        "#,
+        )
+        .bind(webhook_id)
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn get_pending_webhook_deliveries(&self) -> RoutineResult<Vec<WebhookDelivery>> {
+        sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+        SELECT *
+        FROM WebhookDeliveries
+        WHERE status = 'pending'
+        ORDER BY next_attempt_at
+        "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn redeliver_webhook_delivery(
+        &self,
+        user_id: &Uuid,
+        webhook_id: &Uuid,
+        delivery_id: &Uuid,
+    ) -> RoutineResult<Option<WebhookDelivery>> {
+        sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+        INSERT INTO WebhookDeliveries (webhook_id, event_type, payload)
+        SELECT wd.webhook_id, wd.event_type, wd.payload
+        FROM WebhookDeliveries wd
+        JOIN Webhooks w ON w.webhook_id = wd.webhook_id
+        WHERE wd.delivery_id = $1 AND wd.webhook_id = $2 AND w.user_id = $3
+        RETURNING *
+        "#,
+        )
+        .bind(delivery_id)
+        .bind(webhook_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn record_webhook_attempt<'a>(
+        &self,
+        delivery_id: &Uuid,
+        status: &str,
+        response_status: Option<i16>,
+        last_error: Option<&'a str>,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> RoutineResult<()> {
+        sqlx::query(
+            r#"
+        UPDATE WebhookDeliveries
+        SET
+            status = $2,
+            attempt_count = attempt_count + 1,
+            response_status = $3,
+            last_error = $4,
+            next_attempt_at = $5,
+            delivered_at = CASE WHEN $2 = 'succeeded' THEN CURRENT_TIMESTAMP ELSE delivered_at END
+        WHERE delivery_id = $1
+        "#,
+        )
+        .bind(delivery_id)
+        .bind(status)
+        .bind(response_status)
+        .bind(last_error)
+        .bind(next_attempt_at)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    }
+
+    async fn create_coach_invitation(
+        &self,
+        coach_id: &Uuid,
+        athlete_id: &Uuid,
+        permissions: &CoachPermissions,
+    ) -> RoutineResult<Option<CoachingLink>> {
+        sqlx::query_as::<_, CoachingLink>(&format!(
+            r#"
+        WITH ca AS (
+            INSERT INTO CoachAthletes (coach_id, athlete_id, can_read_history, can_edit_routines, can_comment)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (coach_id, athlete_id) DO NOTHING
+            RETURNING *
+        )
+        SELECT {COACHING_LINK_COLUMNS}
+        FROM ca
+        {COACHING_LINK_JOINS}
+        "#
+        ))
+        .bind(coach_id)
+        .bind(athlete_id)
+        .bind(permissions.can_read_history)
+        .bind(permissions.can_edit_routines)
+        .bind(permissions.can_comment)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn get_coaches(&self, athlete_id: &Uuid) -> RoutineResult<Vec<CoachingLink>> {
+        sqlx::query_as::<_, CoachingLink>(&format!(
+            r#"
+        SELECT {COACHING_LINK_COLUMNS}
+        FROM CoachAthletes ca
+        {COACHING_LINK_JOINS}
+        WHERE ca.athlete_id = $1
+        ORDER BY ca.created_at
+        "#
+        ))
+        .bind(athlete_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn get_athletes(&self, coach_id: &Uuid) -> RoutineResult<Vec<CoachingLink>> {
+        sqlx::query_as::<_, CoachingLink>(&format!(
+            r#"
+        SELECT {COACHING_LINK_COLUMNS}
+        FROM CoachAthletes ca
+        {COACHING_LINK_JOINS}
+        WHERE ca.coach_id = $1
+        ORDER BY athlete.username
+        "#
+        ))
+        .bind(coach_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn accept_coach_invitation(
+        &self,
+        athlete_id: &Uuid,
+        link_id: &Uuid,
+    ) -> RoutineResult<Option<CoachingLink>> {
+        sqlx::query_as::<_, CoachingLink>(&format!(
+            r#"
+        WITH ca AS (
+            UPDATE CoachAthletes
+            SET status = 'accepted', accepted_at = CURRENT_TIMESTAMP
+            WHERE link_id = $1 AND athlete_id = $2 AND status = 'pending'
+            RETURNING *
+        )
+        SELECT {COACHING_LINK_COLUMNS}
+        FROM ca
+        {COACHING_LINK_JOINS}
+        "#
+        ))
+        .bind(link_id)
+        .bind(athlete_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn update_coach_permissions(
+        &self,
+        athlete_id: &Uuid,
+        link_id: &Uuid,
+        permissions: &CoachPermissions,
+    ) -> RoutineResult<Option<CoachingLink>> {
+        sqlx::query_as::<_, CoachingLink>(&format!(
+            r#"
+        WITH ca AS (
+            UPDATE CoachAthletes
+            SET can_read_history = $3, can_edit_routines = $4, can_comment = $5
+            WHERE link_id = $1 AND athlete_id = $2
+            RETURNING *
+        )
+        SELECT {COACHING_LINK_COLUMNS}
+        FROM ca
+        {COACHING_LINK_JOINS}
+        "#
+        ))
+        .bind(link_id)
+        .bind(athlete_id)
+        .bind(permissions.can_read_history)
+        .bind(permissions.can_edit_routines)
+        .bind(permissions.can_comment)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn delete_coaching_link(
+        &self,
+        user_id: &Uuid,
+        link_id: &Uuid,
+    ) -> RoutineResult<Option<Uuid>> {
+        sqlx::query_scalar::<_, Uuid>(
+            r#"
+        DELETE FROM CoachAthletes
+        WHERE link_id = $1 AND (coach_id = $2 OR athlete_id = $2)
+        RETURNING link_id
+        "#,
+        )
+        .bind(link_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn get_coach_dashboard(
+        &self,
+        coach_id: &Uuid,
+    ) -> RoutineResult<Vec<CoachDashboardEntry>> {
+        let links = sqlx::query_as::<_, CoachingLink>(&format!(
+            r#"
+        SELECT {COACHING_LINK_COLUMNS}
+        FROM CoachAthletes ca
+        {COACHING_LINK_JOINS}
+        WHERE ca.coach_id = $1 AND ca.status = 'accepted'
+        ORDER BY athlete.username
+        "#
+        ))
+        .bind(coach_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        // The queries check the permissions themselves, what the coach may not see comes back empty
+        let mut dashboard = Vec::with_capacity(links.len());
+        for link in links {
+            let active_routine = sqlx::query_as::<_, Routine>(
+                r#"
+            SELECT routine_id, name, description, is_active, created_at, updated_at
+            FROM routines
+            WHERE user_id = $1 AND is_active = true
+              AND has_access($2, user_id, 'read_routines')
+            ORDER BY COALESCE(updated_at, created_at) DESC
+            LIMIT 1
+            "#,
+            )
+            .bind(link.athlete_id)
+            .bind(coach_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+
+            let last_session_at = sqlx::query_scalar::<_, Option<DateTime<Utc>>>(
+                r#"
+            SELECT MAX(created_at)
+            FROM Sessions
+            WHERE user_id = $1 AND has_access($2, user_id, 'read_history')
+            "#,
+            )
+            .bind(link.athlete_id)
+            .bind(coach_id)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+
+            let recent_personal_records = sqlx::query_as::<_, PersonalRecord>(
+                r#"
+            SELECT pr.record_id, pr.exercise_id, pr.session_id, pr.performance_id, pr.weight,
+                pr.reps, pr.estimated_one_rep_max, pr.created_at
+            FROM PersonalRecords pr
+            JOIN Sessions s ON s.session_id = pr.session_id
+            WHERE s.user_id = $1 AND has_access($2, s.user_id, 'read_history')
+              AND pr.created_at > CURRENT_TIMESTAMP - INTERVAL '30 days'
+            ORDER BY pr.created_at DESC
+            LIMIT 5
+            "#,
+            )
+            .bind(link.athlete_id)
+            .bind(coach_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+
+            dashboard.push(CoachDashboardEntry {
+                athlete_id: link.athlete_id,
+                athlete_username: link.athlete_username,
+                permissions: CoachPermissions {
+                    can_read_history: link.can_read_history,
+                    can_edit_routines: link.can_edit_routines,
+                    can_comment: link.can_comment,
+                },
+                active_routine,
+                last_session_at,
+                recent_personal_records,
+            });
+        }
+
+        Ok(dashboard)
+    }
+
+    async fn get_comments(&self, user_id: &Uuid, session_id: &Uuid) -> RoutineResult<Vec<Comment>> {
+        sqlx::query_as::<_, Comment>(&format!(
+            r#"
+        SELECT {COMMENT_COLUMNS}
+        FROM Comments c
+        {COMMENT_JOINS}
+        WHERE c.session_id = $1 AND has_access($2, session_owner(c.session_id), 'read_history')
+        ORDER BY c.created_at
+        "#
+        ))
+        .bind(session_id)
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn create_comment(
+        &self,
+        author_id: &Uuid,
+        session_id: &Uuid,
+        create_comment: &CreateComment,
+    ) -> RoutineResult<Option<SavedComment>> {
+        // An exercise has to be logged in the session or be on its training day, a set and a
+        // parent comment have to belong to the session
+        sqlx::query_as::<_, SavedComment>(&format!(
+            r#"
+        WITH c AS (
+            INSERT INTO Comments (session_id, exercise_id, performance_id, parent_id, author_id, body)
+            SELECT
+                s.session_id,
+                COALESCE($2, (SELECT exercise_id FROM SessionExercisePerformance WHERE performance_id = $3)),
+                $3,
+                $4,
+                $5,
+                $6
+            FROM Sessions s
+            WHERE s.session_id = $1
+            AND has_access($5, s.user_id, 'comment')
+            AND ($2::uuid IS NULL OR $3::uuid IS NOT NULL
+                OR EXISTS (
+                    SELECT 1 FROM SessionExercisePerformance
+                    WHERE session_id = s.session_id AND exercise_id = $2
+                )
+                OR EXISTS (
+                    SELECT 1 FROM ExerciseTrainingDayLink
+                    WHERE day_id = s.day_id AND exercise_id = $2
+                ))
+            AND ($3::uuid IS NULL OR EXISTS (
+                SELECT 1 FROM SessionExercisePerformance
+                WHERE performance_id = $3 AND session_id = s.session_id
+                AND ($2::uuid IS NULL OR exercise_id = $2)
+            ))
+            AND ($4::uuid IS NULL OR EXISTS (
+                SELECT 1 FROM Comments WHERE comment_id = $4 AND session_id = s.session_id
+            ))
+            RETURNING *
+        )
+        SELECT {COMMENT_COLUMNS}, session_owner(c.session_id) AS session_owner
+        FROM c
+        {COMMENT_JOINS}
+        "#
+        ))
+        .bind(session_id)
+        .bind(create_comment.exercise_id)
+        .bind(create_comment.performance_id)
+        .bind(create_comment.parent_id)
+        .bind(author_id)
+        .bind(&create_comment.body)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn update_comment(
+        &self,
+        author_id: &Uuid,
+        comment_id: &Uuid,
+        body: &str,
+    ) -> RoutineResult<Option<SavedComment>> {
+        sqlx::query_as::<_, SavedComment>(&format!(
+            r#"
+        WITH c AS (
+            UPDATE Comments
+            SET body = $3
+            WHERE comment_id = $1 AND author_id = $2
+            RETURNING *
+        )
+        SELECT {COMMENT_COLUMNS}, session_owner(c.session_id) AS session_owner
+        FROM c
+        {COMMENT_JOINS}
+        "#
+        ))
+        .bind(comment_id)
+        .bind(author_id)
+        .bind(body)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn delete_comment(
+        &self,
+        user_id: &Uuid,
+        comment_id: &Uuid,
+    ) -> RoutineResult<Option<RemovedComment>> {
+        sqlx::query_as::<_, RemovedComment>(
+            r#"
+        DELETE FROM Comments
+        WHERE comment_id = $1 AND (author_id = $2 OR session_owner(session_id) = $2)
+        RETURNING comment_id, session_id, author_id, session_owner(session_id) AS session_owner
+        "#,
+        )
+        .bind(comment_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn set_session_notes<'a>(
+        &self,
+        user_id: &Uuid,
+        session_id: &Uuid,
+        notes: Option<&'a str>,
+    ) -> SessionResult<Option<Session>> {
+        sqlx::query_as::<_, Session>(
+            r#"
+        UPDATE Sessions
+        SET notes = NULLIF($3, '')
+        WHERE session_id = $1 AND has_access($2, user_id, 'log_sessions')
+        RETURNING session_id, day_id, day_name, COALESCE(in_progress, false) AS in_progress, notes,
+            created_at, updated_at
+        "#,
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .bind(notes)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| SessionError::Error(e.to_string()))
+    }
+
+    async fn search_notes(
+        &self,
+        user_id: &Uuid,
+        query: &str,
+        limit: i64,
+    ) -> RoutineResult<Vec<NoteSearchHit>> {
+        sqlx::query_as::<_, NoteSearchHit>(
+            r#"
+        WITH q AS (SELECT websearch_to_tsquery('english', $2) AS query)
+        SELECT hits.*
+        FROM (
+            SELECT 'session' AS source, s.session_id AS id, s.session_id, s.day_id,
+                NULL::uuid AS exercise_id, s.notes, ts_rank(s.notes_search, q.query) AS rank,
+                s.created_at
+            FROM Sessions s, q
+            WHERE s.user_id = $1 AND s.notes_search @@ q.query
+            UNION ALL
+            SELECT 'set', sep.performance_id, sep.session_id, s.day_id, sep.exercise_id, sep.notes,
+                ts_rank(sep.notes_search, q.query), sep.created_at
+            FROM SessionExercisePerformance sep
+            JOIN Sessions s ON s.session_id = sep.session_id, q
+            WHERE s.user_id = $1 AND sep.notes_search @@ q.query
+            UNION ALL
+            SELECT 'exercise_link', etdl.link_id, NULL::uuid, etdl.day_id, etdl.exercise_id,
+                etdl.notes, ts_rank(etdl.notes_search, q.query), etdl.created_at
+            FROM ExerciseTrainingDayLink etdl, q
+            WHERE day_owner(etdl.day_id) = $1 AND etdl.notes_search @@ q.query
+        ) hits
+        ORDER BY hits.rank DESC, hits.created_at DESC
+        LIMIT $3
+        "#,
+        )
+        .bind(user_id)
+        .bind(query)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn log_body_measurement(
+        &self,
+        user_id: &Uuid,
+        measurement: &CreateBodyMeasurement,
+    ) -> RoutineResult<BodyMeasurement> {
+        sqlx::query_as::<_, BodyMeasurement>(&format!(
+            r#"
+        INSERT INTO BodyMeasurements (user_id, measured_on, bodyweight, body_fat_percent,
+            waist_cm, chest_cm, arms_cm, thighs_cm)
+        VALUES ($1, COALESCE($2, CURRENT_DATE), $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (user_id, measured_on) DO UPDATE SET
+            bodyweight = COALESCE(EXCLUDED.bodyweight, BodyMeasurements.bodyweight),
+            body_fat_percent = COALESCE(EXCLUDED.body_fat_percent, BodyMeasurements.body_fat_percent),
+            waist_cm = COALESCE(EXCLUDED.waist_cm, BodyMeasurements.waist_cm),
+            chest_cm = COALESCE(EXCLUDED.chest_cm, BodyMeasurements.chest_cm),
+            arms_cm = COALESCE(EXCLUDED.arms_cm, BodyMeasurements.arms_cm),
+            thighs_cm = COALESCE(EXCLUDED.thighs_cm, BodyMeasurements.thighs_cm)
+        RETURNING {BODY_MEASUREMENT_COLUMNS}
+        "#
+        ))
+        .bind(user_id)
+        .bind(measurement.measured_on)
+        .bind(measurement.bodyweight)
+        .bind(measurement.body_fat_percent)
+        .bind(measurement.waist_cm)
+        .bind(measurement.chest_cm)
+        .bind(measurement.arms_cm)
+        .bind(measurement.thighs_cm)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn get_body_measurements(
+        &self,
+        user_id: &Uuid,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> RoutineResult<Vec<BodyMeasurement>> {
+        sqlx::query_as::<_, BodyMeasurement>(&format!(
+            r#"
+        SELECT {BODY_MEASUREMENT_COLUMNS}
+        FROM BodyMeasurements
+        WHERE user_id = $1
+        AND ($2::date IS NULL OR measured_on >= $2)
+        AND ($3::date IS NULL OR measured_on <= $3)
+        ORDER BY measured_on DESC
+        "#
+        ))
+        .bind(user_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn get_body_measurement(
+        &self,
+        user_id: &Uuid,
+        measurement_id: &Uuid,
+    ) -> RoutineResult<Option<BodyMeasurement>> {
+        sqlx::query_as::<_, BodyMeasurement>(&format!(
+            r#"
+        SELECT {BODY_MEASUREMENT_COLUMNS}
+        FROM BodyMeasurements
+        WHERE measurement_id = $1 AND user_id = $2
+        "#
+        ))
+        .bind(measurement_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn patch_body_measurement(
+        &self,
+        user_id: &Uuid,
+        measurement_id: &Uuid,
+        patch: &PatchBodyMeasurement,
+    ) -> RoutineResult<Option<BodyMeasurement>> {
+        sqlx::query_as::<_, BodyMeasurement>(&format!(
+            r#"
+        UPDATE BodyMeasurements
+        SET measured_on = COALESCE($3, measured_on),
+            bodyweight = COALESCE($4, bodyweight),
+            body_fat_percent = COALESCE($5, body_fat_percent),
+            waist_cm = COALESCE($6, waist_cm),
+            chest_cm = COALESCE($7, chest_cm),
+            arms_cm = COALESCE($8, arms_cm),
+            thighs_cm = COALESCE($9, thighs_cm)
+        WHERE measurement_id = $1 AND user_id = $2
+        RETURNING {BODY_MEASUREMENT_COLUMNS}
+        "#
+        ))
+        .bind(measurement_id)
+        .bind(user_id)
+        .bind(patch.measured_on)
+        .bind(patch.bodyweight)
+        .bind(patch.body_fat_percent)
+        .bind(patch.waist_cm)
+        .bind(patch.chest_cm)
+        .bind(patch.arms_cm)
+        .bind(patch.thighs_cm)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn delete_body_measurement(
+        &self,
+        user_id: &Uuid,
+        measurement_id: &Uuid,
+    ) -> RoutineResult<bool> {
+        sqlx::query("DELETE FROM BodyMeasurements WHERE measurement_id = $1 AND user_id = $2")
+            .bind(measurement_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .map_err(|e| e.to_string())
+    }
+
+    async fn get_body_trend(
+        &self,
+        user_id: &Uuid,
+        metric: BodyMetric,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> RoutineResult<Vec<BodyTrendPoint>> {
+        // the column comes from `BodyMetric`, never from the request
+        let column = metric.column();
+        sqlx::query_as::<_, BodyTrendPoint>(&format!(
+            r#"
+        SELECT measured_on, {column}::numeric AS value
+        FROM BodyMeasurements
+        WHERE user_id = $1 AND {column} IS NOT NULL
+        AND ($2::date IS NULL OR measured_on >= $2)
+        AND ($3::date IS NULL OR measured_on <= $3)
+        ORDER BY measured_on
+        "#
+        ))
+        .bind(user_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn get_bodyweight_as_of(
+        &self,
+        user_id: &Uuid,
+        day: NaiveDate,
+    ) -> RoutineResult<Option<BodyMeasurement>> {
+        sqlx::query_as::<_, BodyMeasurement>(&format!(
+            r#"
+        SELECT {BODY_MEASUREMENT_COLUMNS}
+        FROM BodyMeasurements
+        WHERE user_id = $1 AND measured_on <= $2 AND bodyweight IS NOT NULL
+        ORDER BY measured_on DESC
+        LIMIT 1
+        "#
+        ))
+        .bind(user_id)
+        .bind(day)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    async fn export_account(&self, user_id: &Uuid) -> RoutineResult<Option<AccountExport>> {
+        let mut transaction = self.pool.begin().await.map_err(|e| e.to_string())?;
+        // one snapshot for every query below
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+            .execute(transaction.as_mut())
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let Some(user) = sqlx::query_as::<_, UserNoPassword>(
+            "SELECT user_id, username, role, weight_unit FROM Users WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?
+        else {
+            return Ok(None);
+        };
+
+        let routines = sqlx::query_as::<_, Routine>(&format!(
+            r#"
+        SELECT routine_id, name, COALESCE(description, '') AS description,
+            COALESCE(is_active, false) AS is_active, created_at, updated_at
+        FROM Routines
+        WHERE routine_id IN ({OWNED_ROUTINES})
+        ORDER BY created_at
+        "#
+        ))
+        .bind(user_id)
+        .fetch_all(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let training_days = sqlx::query_as::<_, TrainingDay>(&format!(
+            r#"
+        SELECT day_id, day_name, routine_id, created_at, updated_at
+        FROM TrainingDays
+        WHERE day_id IN ({OWNED_DAYS})
+        ORDER BY created_at
+        "#
+        ))
+        .bind(user_id)
+        .fetch_all(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let exercise_links = sqlx::query_as::<_, ExerciseToTrainingDay>(&format!(
+            r#"
+        SELECT link_id, exercise_id, day_id, notes, created_at, updated_at
+        FROM ExerciseTrainingDayLink
+        WHERE day_id IN ({OWNED_DAYS})
+        ORDER BY created_at
+        "#
+        ))
+        .bind(user_id)
+        .fetch_all(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let sessions = sqlx::query_as::<_, Session>(&format!(
+            r#"
+        SELECT session_id, COALESCE(day_id, '00000000-0000-0000-0000-000000000000') AS day_id,
+            day_name, COALESCE(in_progress, false) AS in_progress, notes, created_at, updated_at
+        FROM Sessions
+        WHERE session_id IN ({OWNED_SESSIONS})
+        ORDER BY created_at
+        "#
+        ))
+        .bind(user_id)
+        .fetch_all(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let sets = sqlx::query_as::<_, AccountSetRow>(&format!(
+            r#"
+        SELECT sep.session_id, sep.exercise_id, e.exercise_name, e.tracked_metrics,
+            sep.performance_id, COALESCE(sep.weight, 0) AS weight,
+            COALESCE(sep.effective_load, sep.weight, 0) AS effective_load, COALESCE(sep.reps, 0) AS reps,
+            COALESCE(sep.set_number, 0) AS set_number, sep.rir, sep.duration_seconds,
+            sep.distance_meters, sep.calories, sep.heart_rate, sep.notes, sep.created_at,
+            sep.updated_at
+        FROM SessionExercisePerformance sep
+        JOIN Sessions s ON s.session_id = sep.session_id
+        JOIN Exercises e ON e.exercise_id = sep.exercise_id
+        WHERE sep.session_id IN ({OWNED_SESSIONS})
+        ORDER BY s.created_at, sep.session_id, e.exercise_name, sep.exercise_id, sep.set_number
+        "#
+        ))
+        .bind(user_id)
+        .fetch_all(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?;
+
+        // rows arrive ordered, so each session and exercise is one consecutive run
+        let mut performance: Vec<SessionPerformance> = Vec::new();
+        for row in sets {
+            match performance.last_mut() {
+                Some(last)
+                    if last.session_id == row.session_id && last.exercise_id == row.exercise_id =>
+                {
+                    last.sets.push(row.set)
+                }
+                _ => {
+                    let mut exercise_performance =
+                        SessionPerformance::new(row.session_id, row.exercise_id, row.exercise_name);
+                    exercise_performance.tracked_metrics = row.tracked_metrics;
+                    exercise_performance.sets.push(row.set);
+                    performance.push(exercise_performance);
+                }
+            }
+        }
+
+        let personal_records = sqlx::query_as::<_, PersonalRecord>(&format!(
+            r#"
+        SELECT record_id, exercise_id, session_id, performance_id, weight, reps,
+            estimated_one_rep_max, created_at
+        FROM PersonalRecords
+        WHERE session_id IN ({OWNED_SESSIONS})
+        ORDER BY created_at
+        "#
+        ))
+        .bind(user_id)
+        .fetch_all(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let webhooks = sqlx::query_as::<_, Webhook>(
+            r#"
+        SELECT webhook_id, url, events, is_active, created_at, updated_at
+        FROM Webhooks
+        WHERE user_id = $1
+        ORDER BY created_at
+        "#,
+        )
+        .bind(user_id)
+        .fetch_all(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let coaching = sqlx::query_as::<_, CoachingLink>(&format!(
+            r#"
+        SELECT {COACHING_LINK_COLUMNS}
+        FROM CoachAthletes ca
+        {COACHING_LINK_JOINS}
+        WHERE ca.coach_id = $1 OR ca.athlete_id = $1
+        ORDER BY ca.created_at
+        "#
+        ))
+        .bind(user_id)
+        .fetch_all(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let comments = sqlx::query_as::<_, Comment>(&format!(
+            r#"
+        SELECT {COMMENT_COLUMNS}
+        FROM Comments c
+        {COMMENT_JOINS}
+        WHERE c.author_id = $1 OR c.session_id IN ({OWNED_SESSIONS})
+        ORDER BY c.created_at
+        "#
+        ))
+        .bind(user_id)
+        .fetch_all(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let body_measurements = sqlx::query_as::<_, BodyMeasurement>(&format!(
+            r#"
+        SELECT {BODY_MEASUREMENT_COLUMNS}
+        FROM BodyMeasurements
+        WHERE user_id = $1
+        ORDER BY measured_on
+        "#
+        ))
+        .bind(user_id)
+        .fetch_all(transaction.as_mut())
+        .await
+        .map_err(|e| e.to_string())?;
+
+        transaction.commit().await.map_err(|e| e.to_string())?;
+
+        Ok(Some(AccountExport {
+            exported_at: Some(Utc::now()),
+            user,
+            routines,
+            training_days,
+            exercise_links,
+            sessions,
+            performance,
+            personal_records,
+            webhooks,
+            coaching,
+            comments,
+            body_measurements,
+        }))
+    }
+
+    async fn delete_account(&self, user_id: &Uuid) -> RoutineResult<bool> {
+        let mut transaction = self.pool.begin().await.map_err(|e| e.to_string())?;
+
+        // Children first, most of the foreign keys don't cascade. Sync versions are keyed by
+        // entity id only, so they have to go while the owned rows can still be found.
+        let statements = [
+            format!(
+                r#"
+        DELETE FROM SyncFieldVersions
+        WHERE (entity_type = 'routine' AND entity_id IN ({OWNED_ROUTINES}))
+            OR (entity_type = 'session' AND entity_id IN ({OWNED_SESSIONS}))
+            OR (entity_type = 'set_performance' AND entity_id IN (
+                SELECT performance_id FROM SessionExercisePerformance
+                WHERE session_id IN ({OWNED_SESSIONS})
+            ))
+        "#
+            ),
+            // PersonalRecords cascade with the sets
+            format!(
+                "DELETE FROM SessionExercisePerformance WHERE session_id IN ({OWNED_SESSIONS})"
+            ),
+            format!("DELETE FROM Sessions WHERE session_id IN ({OWNED_SESSIONS})"),
+            format!("DELETE FROM ExerciseTrainingDayLink WHERE day_id IN ({OWNED_DAYS})"),
+            format!("DELETE FROM TrainingDays WHERE day_id IN ({OWNED_DAYS})"),
+            "DELETE FROM Routines WHERE user_id = $1".to_string(),
+            // Exercises others still use stay behind without an owner
+            r#"
+        DELETE FROM Exercises e
+        WHERE owner_id = $1
+            AND NOT EXISTS (SELECT 1 FROM ExerciseTrainingDayLink l WHERE l.exercise_id = e.exercise_id)
+            AND NOT EXISTS (
+                SELECT 1 FROM SessionExercisePerformance sep WHERE sep.exercise_id = e.exercise_id
+            )
+        "#
+            .to_string(),
+            // Deliveries go with their webhook
+            "DELETE FROM Webhooks WHERE user_id = $1".to_string(),
+            // The deletes above left tombstones, found through the feed they were logged to
+            r#"
+        DELETE FROM SyncFieldVersions v
+        USING ChangeLog c
+        WHERE c.user_id = $1 AND c.operation = 'delete'
+            AND v.entity_type = c.entity_type AND v.entity_id = c.entity_id
+        "#
+            .to_string(),
+            "DELETE FROM ChangeLog WHERE user_id = $1".to_string(),
+            "DELETE FROM IdempotencyKeys WHERE user_id = $1".to_string(),
+        ];
+        for statement in &statements {
+            sqlx::query(statement)
+                .bind(user_id)
+                .execute(transaction.as_mut())
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        let deleted = sqlx::query("DELETE FROM Users WHERE user_id = $1")
+            .bind(user_id)
+            .execute(transaction.as_mut())
+            .await
+            .map_err(|e| e.to_string())?
+            .rows_affected();
+
+        transaction.commit().await.map_err(|e| e.to_string())?;
+
+        Ok(deleted > 0)
+    }
+
+    async fn clear_data(&self) -> Result<(), sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        // Delete all rows from the ExerciseTrainingDayLink table
+        sqlx::query("DELETE FROM ExerciseTrainingDayLink")
+            .execute(transaction.as_mut())
+            .await?;
+
+        // Delete all rows from the Sessions table
+        sqlx::query("DELETE FROM Sessions")
+            .execute(transaction.as_mut())
+            .await?;
+
+        // Delete all rows from the TrainingDays table
+        sqlx::query("DELETE FROM TrainingDays")
+            .execute(transaction.as_mut())
+            .await?;
+
+        // Delete all rows from the Exercises table
+        sqlx::query("DELETE FROM Exercises")
+            .execute(transaction.as_mut())
+            .await?;
+
+        // Delete all rows from the Routines table
+        sqlx::query("DELETE FROM Routines")
+            .execute(transaction.as_mut())
+            .await?;
+
+        sqlx::query("DELETE FROM SessionExercisePerformance")
+            .execute(transaction.as_mut())
+            .await?;
+
+        // Stored responses point at rows that no longer exist
+        sqlx::query("DELETE FROM IdempotencyKeys")
+            .execute(transaction.as_mut())
+            .await?;
+
+        sqlx::query("DELETE FROM SyncFieldVersions")
+            .execute(transaction.as_mut())
+            .await?;
+
+        sqlx::query("DELETE FROM ChangeLog")
+            .execute(transaction.as_mut())
+            .await?;
+
+        // Deliveries go with their webhook
+        sqlx::query("DELETE FROM Webhooks")
+            .execute(transaction.as_mut())
+            .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+}
+
+// Apply one sync mutation inside the caller's savepoint and return the fields that lost
+async fn apply_sync_mutation(
+    conn: &mut PgConnection,
+    user_id: &Uuid,
+    mutation: &SyncMutation,
+) -> Result<Vec<SyncConflict>, String> {
+    let entity_type = mutation.entity.as_str();
+    let incoming = FieldVersion {
+        changed_at: mutation.client_timestamp,
+        mutation_id: mutation.mutation_id,
+    };
+
+    let versions: HashMap<String, FieldVersion> =
+        sqlx::query_as::<_, (String, chrono::DateTime<chrono::Utc>, Uuid)>(
+            r#"
+        SELECT field, changed_at, mutation_id
+        FROM SyncFieldVersions
+        WHERE entity_type = $1 AND entity_id = $2
+        FOR UPDATE
+        "#,
+        )
+        .bind(entity_type)
+        .bind(mutation.entity_id)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|(field, changed_at, mutation_id)| {
+            (
+                field,
+                FieldVersion {
+                    changed_at,
+                    mutation_id,
+                },
+            )
+        })
+        .collect();
+
+    check_sync_access(conn, user_id, mutation).await?;
+
+    let current = load_sync_row(conn, mutation.entity, &mutation.entity_id).await?;
+    let conflict = |field: &str, stored: &FieldVersion| SyncConflict {
+        mutation_id: mutation.mutation_id,
+        entity: mutation.entity,
+        entity_id: mutation.entity_id,
+        field: field.to_string(),
+        client_timestamp: mutation.client_timestamp,
+        server_timestamp: stored.changed_at,
+        server_value: current
+            .as_ref()
+            .and_then(|row| row.get(field).cloned())
+            .unwrap_or(Value::Null),
+    };
+
+    match mutation.operation {
+        SyncOperation::Delete => {
+            // A delete has to be newer than every field write it would throw away
+            let newer_write = versions
+                .iter()
+                .filter(|(field, _)| field.as_str() != sync::DELETED_FIELD)
+                .find(|(_, stored)| sync::resolve(&incoming, Some(stored)) == Resolution::Reject);
+            if let Some((field, stored)) = newer_write {
+                return Ok(vec![conflict(field, stored)]);
+            }
+            match sync::resolve(&incoming, versions.get(sync::DELETED_FIELD)) {
+                Resolution::Accept => {}
+                Resolution::AlreadyApplied => return Ok(vec![]),
+                Resolution::Reject => {
+                    return Ok(vec![conflict(
+                        sync::DELETED_FIELD,
+                        &versions[sync::DELETED_FIELD],
+                    )])
+                }
+            }
+
+            delete_sync_row(conn, mutation.entity, &mutation.entity_id).await?;
+            save_field_versions(conn, mutation, &[sync::DELETED_FIELD]).await?;
+            Ok(vec![])
+        }
+        SyncOperation::Upsert => {
+            if let Some(deleted) = versions.get(sync::DELETED_FIELD) {
+                if sync::resolve(&incoming, Some(deleted)) != Resolution::Accept {
+                    return Ok(vec![conflict(sync::DELETED_FIELD, deleted)]);
+                }
+            }
+
+            let writable = sync::writable_fields(mutation.entity);
+            if let Some(field) = mutation
+                .fields
+                .keys()
+                .find(|field| !writable.contains(&field.as_str()))
+            {
+                return Err(format!(
+                    "Field {} can't be synced for {}",
+                    field, entity_type
+                ));
+            }
+
+            let mut accepted = Vec::new();
+            let mut conflicts = Vec::new();
+            for field in mutation.fields.keys() {
+                match sync::resolve(&incoming, versions.get(field)) {
+                    Resolution::Accept => accepted.push(field.as_str()),
+                    Resolution::AlreadyApplied => {}
+                    Resolution::Reject => conflicts.push(conflict(field, &versions[field])),
+                }
+            }
+            if accepted.is_empty() {
+                return Ok(conflicts);
+            }
+
+            let is_new = current.is_none();
+            let mut row = match current {
+                Some(Value::Object(row)) => row,
+                _ => serde_json::Map::new(),
+            };
+            row.insert(
+                sync::id_field(mutation.entity).to_string(),
+                Value::String(mutation.entity_id.to_string()),
+            );
+            for field in &accepted {
+                row.insert(field.to_string(), mutation.fields[*field].clone());
+            }
+            // rows created offline keep the time they were created on the device
+            if is_new && mutation.entity == SyncEntity::Session {
+                row.entry("created_at")
+                    .or_insert_with(|| Value::String(mutation.client_timestamp.to_rfc3339()));
+            }
+
+            save_sync_row(conn, user_id, mutation.entity, Value::Object(row)).await?;
+            save_field_versions(conn, mutation, &accepted).await?;
+            Ok(conflicts)
+        }
+    }
+}
+
+// Reject the mutation when it touches a row the user may not change, or would move a row
+// under a parent they may not change. Rows that don't exist yet are created for the user.
+async fn check_sync_access(
+    conn: &mut PgConnection,
+    user_id: &Uuid,
+    mutation: &SyncMutation,
+) -> Result<(), String> {
+    let parent = |field: &str| {
+        mutation
+            .fields
+            .get(field)
+            .and_then(Value::as_str)
+            .and_then(|id| Uuid::parse_str(id).ok())
+    };
+    let (query, parent_id) = match mutation.entity {
+        SyncEntity::Routine => (
+            r#"
+        SELECT NOT EXISTS (
+            SELECT 1 FROM Routines
+            WHERE routine_id = $1 AND NOT has_access($2, user_id, 'edit_routines')
+        )
+        "#,
+            None,
+        ),
+        SyncEntity::Session => (
+            r#"
+        SELECT NOT EXISTS (
+            SELECT 1 FROM Sessions
+            WHERE session_id = $1 AND NOT has_access($2, user_id, 'log_sessions')
+        )
+        AND ($3::uuid IS NULL OR has_access($2, day_owner($3), 'log_sessions'))
+        "#,
+            parent("day_id"),
+        ),
+        SyncEntity::SetPerformance => (
+            r#"
+        SELECT NOT EXISTS (
+            SELECT 1 FROM SessionExercisePerformance
+            WHERE performance_id = $1
+            AND NOT has_access($2, session_owner(session_id), 'log_sessions')
+        )
+        AND ($3::uuid IS NULL OR has_access($2, session_owner($3), 'log_sessions'))
+        "#,
+            parent("session_id"),
+        ),
+    };
+
+    let allowed = sqlx::query_scalar::<_, bool>(query)
+        .bind(mutation.entity_id)
+        .bind(user_id)
+        .bind(parent_id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    if !allowed {
+        return Err(format!(
+            "{} {} not found",
+            mutation.entity.as_str(),
+            mutation.entity_id
+        ));
+    }
+    Ok(())
+}
+
+async fn load_sync_row(
+    conn: &mut PgConnection,
+    entity: SyncEntity,
+    entity_id: &Uuid,
+) -> Result<Option<Value>, String> {
+    let row = match entity {
+        SyncEntity::Routine => sqlx::query_as::<_, SyncRoutine>(
+            r#"
+        SELECT routine_id, name, COALESCE(description, '') AS description, COALESCE(is_active, false) AS is_active
+        FROM Routines
+        WHERE routine_id = $1
+        "#,
+        )
+        .bind(entity_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map(|row| row.map(serde_json::to_value)),
+        SyncEntity::Session => sqlx::query_as::<_, SyncSession>(
+            r#"
+        SELECT session_id, day_id, day_name, COALESCE(in_progress, false) AS in_progress, created_at
+        FROM Sessions
+        WHERE session_id = $1
+        "#,
+        )
+        .bind(entity_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map(|row| row.map(serde_json::to_value)),
+        SyncEntity::SetPerformance => sqlx::query_as::<_, SyncSetPerformance>(
+            r#"
+        SELECT performance_id, session_id, exercise_id, COALESCE(set_number, 0) AS set_number,
+            COALESCE(weight, 0) AS weight, COALESCE(reps, 0) AS reps, rir,
+            duration_seconds, distance_meters, calories, heart_rate
+        FROM SessionExercisePerformance
+        WHERE performance_id = $1
+        "#,
+        )
+        .bind(entity_id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map(|row| row.map(serde_json::to_value)),
+    }
+    .map_err(|e| e.to_string())?;
+
+    row.transpose().map_err(|e| e.to_string())
+}
+
+// Write the merged row back, returning it as the server now sees it. Rows created
+// through sync belong to the pushing user, an existing row keeps its owner.
+async fn save_sync_row(
+    conn: &mut PgConnection,
+    user_id: &Uuid,
+    entity: SyncEntity,
+    row: Value,
+) -> Result<Value, String> {
+    let invalid = |e: serde_json::Error| format!("Invalid {}: {}", entity.as_str(), e);
+    let saved = match entity {
+        SyncEntity::Routine => {
+            let routine: SyncRoutine = serde_json::from_value(row).map_err(invalid)?;
+            sqlx::query_as::<_, SyncRoutine>(
+                r#"
+        INSERT INTO Routines (routine_id, name, description, is_active, user_id)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (routine_id) DO UPDATE SET
+            name = EXCLUDED.name,
+            description = EXCLUDED.description,
+            is_active = EXCLUDED.is_active
+        RETURNING routine_id, name, COALESCE(description, '') AS description, COALESCE(is_active, false) AS is_active
+        "#,
+            )
+            .bind(routine.routine_id)
+            .bind(&routine.name)
+            .bind(&routine.description)
+            .bind(routine.is_active)
+            .bind(user_id)
+            .fetch_one(&mut *conn)
+            .await
+            .map(serde_json::to_value)
+        }
+        SyncEntity::Session => {
+            let session: SyncSession = serde_json::from_value(row).map_err(invalid)?;
+            sqlx::query_as::<_, SyncSession>(
+                r#"
+        INSERT INTO Sessions (session_id, day_id, day_name, in_progress, created_at, user_id)
+        VALUES (
+            $1,
+            $2,
+            COALESCE(NULLIF($3, ''), (SELECT day_name FROM TrainingDays WHERE day_id = $2)),
+            $4,
+            COALESCE($5, CURRENT_TIMESTAMP),
+            $6
+        )
+        ON CONFLICT (session_id) DO UPDATE SET
+            day_id = EXCLUDED.day_id,
+            day_name = EXCLUDED.day_name,
+            in_progress = EXCLUDED.in_progress
+        RETURNING session_id, day_id, day_name, COALESCE(in_progress, false) AS in_progress, created_at
+        "#,
+            )
+            .bind(session.session_id)
+            .bind(session.day_id)
+            .bind(&session.day_name)
+            .bind(session.in_progress)
+            .bind(session.created_at)
+            .bind(user_id)
+            .fetch_one(&mut *conn)
+            .await
+            .map(serde_json::to_value)
+        }
+        SyncEntity::SetPerformance => {
+            let set: SyncSetPerformance = serde_json::from_value(row).map_err(invalid)?;
+            check_sync_set_metrics(conn, user_id, &set).await?;
+            sqlx::query_as::<_, SyncSetPerformance>(
+                r#"
+        INSERT INTO SessionExercisePerformance (performance_id, session_id, exercise_id, set_number, weight, reps, rir,
+            duration_seconds, distance_meters, calories, heart_rate)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        ON CONFLICT (performance_id) DO UPDATE SET
+            session_id = EXCLUDED.session_id,
+            exercise_id = EXCLUDED.exercise_id,
+            set_number = EXCLUDED.set_number,
+            weight = EXCLUDED.weight,
+            reps = EXCLUDED.reps,
+            rir = EXCLUDED.rir,
+            duration_seconds = EXCLUDED.duration_seconds,
+            distance_meters = EXCLUDED.distance_meters,
+            calories = EXCLUDED.calories,
+            heart_rate = EXCLUDED.heart_rate
+        RETURNING performance_id, session_id, exercise_id, COALESCE(set_number, 0) AS set_number,
+            COALESCE(weight, 0) AS weight, COALESCE(reps, 0) AS reps, rir,
+            duration_seconds, distance_meters, calories, heart_rate
+        "#,
+            )
+            .bind(set.performance_id)
+            .bind(set.session_id)
+            .bind(set.exercise_id)
+            .bind(set.set_number)
+            .bind(set.weight)
+            .bind(set.reps)
+            .bind(set.rir)
+            .bind(set.duration_seconds)
+            .bind(set.distance_meters)
+            .bind(set.calories)
+            .bind(set.heart_rate)
+            .fetch_one(&mut *conn)
+            .await
+            .map(serde_json::to_value)
+        }
+    }
+    .map_err(|e| e.to_string())?;
+
+    saved.map_err(|e| e.to_string())
+}
+
+async fn delete_sync_row(
+    conn: &mut PgConnection,
+    entity: SyncEntity,
+    entity_id: &Uuid,
+) -> Result<(), String> {
+    let query = match entity {
+        SyncEntity::Routine => "DELETE FROM Routines WHERE routine_id = $1",
+        SyncEntity::Session => "DELETE FROM Sessions WHERE session_id = $1",
+        SyncEntity::SetPerformance => {
+            "DELETE FROM SessionExercisePerformance WHERE performance_id = $1"
+        }
+    };
+    sqlx::query(query)
+        .bind(entity_id)
+        .execute(&mut *conn)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+// A synced set is held to what its exercise tracks, like one logged online
+// Also keeps sets to exercises the pusher may log, as `add_set_performance_to_session` does
+async fn check_sync_set_metrics(
+    conn: &mut PgConnection,
+    user_id: &Uuid,
+    set: &SyncSetPerformance,
+) -> Result<(), String> {
+    let metrics: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT e.tracked_metrics
+        FROM Exercises e
+        WHERE e.exercise_id = $1
+            AND (e.builtin OR e.owner_id = $2 OR EXISTS (
+                SELECT 1
+                FROM ExerciseTrainingDayLink l
+                JOIN Sessions s ON s.day_id = l.day_id
+                WHERE s.session_id = $3 AND l.exercise_id = e.exercise_id
+            ))
+        "#,
+    )
+    .bind(set.exercise_id)
+    .bind(user_id)
+    .bind(set.session_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Exercise {} not found", set.exercise_id))?;
+    TrackedMetrics::try_from(metrics)?.check_set(&SetPerformancePayload {
+        weight: set.weight,
+        reps: set.reps,
+        set_number: set.set_number,
+        rir: set.rir,
+        duration_seconds: set.duration_seconds,
+        distance_meters: set.distance_meters,
+        calories: set.calories,
+        heart_rate: set.heart_rate,
+        ..Default::default()
+    })
+}
+
+async fn save_field_versions(
+    conn: &mut PgConnection,
+    mutation: &SyncMutation,
+    fields: &[&str],
+) -> Result<(), String> {
+    for field in fields {
+        sqlx::query(
+            r#"
+        INSERT INTO SyncFieldVersions (entity_type, entity_id, field, changed_at, mutation_id)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (entity_type, entity_id, field) DO UPDATE SET
+            changed_at = EXCLUDED.changed_at,
+            mutation_id = EXCLUDED.mutation_id
+        "#,
+        )
+        .bind(mutation.entity.as_str())
+        .bind(mutation.entity_id)
+        .bind(field)
+        .bind(mutation.client_timestamp)
+        .bind(mutation.mutation_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
     }
+    Ok(())
 }
@@ -0,0 +1,876 @@
+use actix_web::{
+    guard,
+    web::{
+        delete, get, patch, post, put, resource, scope, Data, Json, JsonConfig, Path, Query,
+        ReqData, ServiceConfig,
+    },
+    HttpRequest, HttpResponse, ResponseError,
+};
+use actix_web_httpauth::middleware::HttpAuthentication;
+use serde::Deserialize;
+use shared::models::{
+    CreateTrainingDay, PatchExercise, PatchExerciseToTrainingDay, PatchRoutine,
+    PatchSetPerformance, PatchTrainingDay, Role, Routine, SessionEvent, SetPerformancePayload,
+    TokenClaims, TrainingDay, UnitQuery,
+};
+use shared::utils::InWeightUnit;
+use uuid::Uuid;
+
+use crate::account;
+use crate::activity::{self, ActivityBus};
+use crate::authorization::RequireRole;
+use crate::body;
+use crate::coaching;
+use crate::comments;
+use crate::etag;
+use crate::export;
+use crate::idempotency::Idempotency;
+use crate::import;
+use crate::live::{self, SessionHub};
+use crate::merge;
+use crate::notes;
+use crate::routines::{self, validator};
+use crate::routines_repository::{PatchError, RoutinesRepository};
+use crate::substitutes;
+use crate::sync;
+use crate::webhooks;
+
+// v2 nests resources under their parents (routines -> days -> exercises,
+// sessions -> exercises -> sets) and checks every path id against the body
+// and the parent it is nested under. v1 stays registered next to it until
+// clients have migrated.
+pub fn service<R: RoutinesRepository>(cfg: &mut ServiceConfig) {
+    let bearer_middleware = HttpAuthentication::bearer(validator);
+    cfg.service(
+        scope("/v2")
+            .service(
+                scope("/users")
+                    .route("/auth", get().to(routines::basic_auth::<R>))
+                    .service(
+                        resource("")
+                            .guard(guard::Get())
+                            .wrap(RequireRole::admin())
+                            .wrap(HttpAuthentication::bearer(validator))
+                            .route(get().to(routines::get_users::<R>)),
+                    )
+                    .route("", post().to(routines::create_user::<R>))
+                    .service(
+                        resource("/{user_id}/role")
+                            .wrap(RequireRole::admin())
+                            .wrap(HttpAuthentication::bearer(validator))
+                            .route(put().to(routines::set_user_role::<R>)),
+                    ),
+            )
+            .service(
+                scope("")
+                    .wrap(Idempotency::<R>::new())
+                    .wrap(bearer_middleware)
+                    .service(
+                        scope("/routines")
+                            .route("", get().to(routines::get_all_routines::<R>))
+                            .route("", post().to(routines::create_routine::<R>))
+                            .route("/active", get().to(routines::get_active_routine::<R>))
+                            .route("/{routine_id}", get().to(get_routine::<R>))
+                            .route("/{routine_id}", put().to(update_routine::<R>))
+                            .route("/{routine_id}", patch().to(patch_routine::<R>))
+                            .route("/{routine_id}", delete().to(routines::delete_routine::<R>))
+                            .route("/{routine_id}/days", get().to(get_training_days::<R>))
+                            .route("/{routine_id}/days", post().to(create_training_day::<R>))
+                            .route(
+                                "/{routine_id}/days/bulk",
+                                post().to(create_training_days::<R>),
+                            )
+                            .route(
+                                "/{routine_id}/days/{day_id}",
+                                delete().to(delete_training_day::<R>),
+                            )
+                            .route(
+                                "/{routine_id}/days/{day_id}",
+                                patch().to(patch_training_day::<R>),
+                            )
+                            .route(
+                                "/{routine_id}/days/{day_id}/exercises",
+                                get().to(get_exercises_for_training_day::<R>),
+                            )
+                            .route(
+                                "/{routine_id}/days/{day_id}/exercises/{exercise_id}",
+                                post().to(add_exercise_to_training_day::<R>),
+                            )
+                            .route(
+                                "/{routine_id}/days/{day_id}/exercises/{exercise_id}",
+                                delete().to(remove_exercise_from_training_day::<R>),
+                            )
+                            .route(
+                                "/{routine_id}/days/{day_id}/exercises/{exercise_id}",
+                                patch().to(patch_exercise_link::<R>),
+                            )
+                            .route(
+                                "/{routine_id}/days/{day_id}/sessions",
+                                post().to(create_session::<R>),
+                            )
+                            .route(
+                                "/{routine_id}/days/{day_id}/sessions",
+                                get().to(get_sessions_for_training_day::<R>),
+                            )
+                            .route(
+                                "/{routine_id}/sessions",
+                                get().to(routines::get_all_sessions_by_routine_id::<R>),
+                            )
+                            .route(
+                                "/{routine_id}/sessions/in_progress",
+                                get().to(routines::get_session_in_progress::<R>),
+                            ),
+                    )
+                    .service(
+                        scope("/exercises")
+                            .route("", get().to(routines::get_exercises::<R>))
+                            .route("", post().to(routines::create_exercise::<R>))
+                            .route("/bulk", post().to(routines::create_exercises::<R>))
+                            .route("/search", get().to(routines::search_exercises::<R>))
+                            .route("/merge", post().to(merge::merge_exercises::<R>))
+                            .route(
+                                "/{exercise_id}/substitutes",
+                                get().to(substitutes::get_substitutes::<R>),
+                            )
+                            .route("/{exercise_id}", patch().to(patch_exercise::<R>)),
+                    )
+                    .service(
+                        scope("/sessions")
+                            .route("/{session_id}", get().to(get_session::<R>))
+                            .route("/{session_id}/end", put().to(routines::end_session::<R>))
+                            .route("/{session_id}/live", get().to(live::session_socket::<R>))
+                            .route(
+                                "/{session_id}/comments",
+                                get().to(comments::get_comments::<R>),
+                            )
+                            .route(
+                                "/{session_id}/comments",
+                                post().to(comments::create_comment::<R>),
+                            )
+                            .route(
+                                "/{session_id}/notes",
+                                put().to(notes::set_session_notes::<R>),
+                            )
+                            .route(
+                                "/{session_id}/exercises/{exercise_id}/sets",
+                                post().to(add_set_performance_to_session::<R>),
+                            )
+                            .route(
+                                "/{session_id}/exercises/{exercise_id}/sets/{performance_id}",
+                                delete().to(remove_set_performance_from_session::<R>),
+                            )
+                            .route(
+                                "/{session_id}/exercises/{exercise_id}/sets/{performance_id}",
+                                patch().to(patch_set_performance::<R>),
+                            ),
+                    )
+                    .route("/activity", get().to(activity::stream))
+                    .route("/notes/search", get().to(notes::search_notes::<R>))
+                    .service(
+                        scope("/body")
+                            .route("/measurements", post().to(body::log_body_measurement::<R>))
+                            .route("/measurements", get().to(body::get_body_measurements::<R>))
+                            .route(
+                                "/measurements/{measurement_id}",
+                                get().to(body::get_body_measurement::<R>),
+                            )
+                            .route(
+                                "/measurements/{measurement_id}",
+                                patch().to(body::patch_body_measurement::<R>),
+                            )
+                            .route(
+                                "/measurements/{measurement_id}",
+                                delete().to(body::delete_body_measurement::<R>),
+                            )
+                            .route("/trend", get().to(body::get_body_trend::<R>))
+                            .route("/bodyweight", get().to(body::get_bodyweight_as_of::<R>)),
+                    )
+                    .service(
+                        scope("/account")
+                            .route("", delete().to(account::delete_account::<R>))
+                            .route("/export", get().to(account::export_account::<R>))
+                            .route("/preferences", get().to(account::get_preferences::<R>))
+                            .route("/preferences", put().to(account::update_preferences::<R>)),
+                    )
+                    .service(
+                        scope("/comments")
+                            .route("/{comment_id}", put().to(comments::update_comment::<R>))
+                            .route("/{comment_id}", delete().to(comments::delete_comment::<R>)),
+                    )
+                    .service(
+                        scope("/coaching")
+                            .service(
+                                resource("/invitations")
+                                    .wrap(RequireRole::new(&[Role::Coach, Role::Admin]))
+                                    .route(post().to(coaching::create_invitation::<R>)),
+                            )
+                            .route(
+                                "/invitations/{link_id}/accept",
+                                put().to(coaching::accept_invitation::<R>),
+                            )
+                            .route("/coaches", get().to(coaching::get_coaches::<R>))
+                            .route(
+                                "/coaches/{link_id}/permissions",
+                                put().to(coaching::update_permissions::<R>),
+                            )
+                            .route("/athletes", get().to(coaching::get_athletes::<R>))
+                            .route("/links/{link_id}", delete().to(coaching::delete_link::<R>))
+                            .route("/dashboard", get().to(coaching::get_dashboard::<R>)),
+                    )
+                    .route("/export/history", get().to(export::export_history::<R>))
+                    .service(
+                        resource("/import")
+                            .app_data(JsonConfig::default().limit(import::IMPORT_BODY_LIMIT))
+                            .route(post().to(import::import_history::<R>)),
+                    )
+                    .service(
+                        scope("/webhooks")
+                            .route("", get().to(webhooks::get_webhooks::<R>))
+                            .route("", post().to(webhooks::create_webhook::<R>))
+                            .route("/{webhook_id}", delete().to(webhooks::delete_webhook::<R>))
+                            .route(
+                                "/{webhook_id}/deliveries",
+                                get().to(webhooks::get_webhook_deliveries::<R>),
+                            )
+                            .route(
+                                "/{webhook_id}/deliveries/{delivery_id}/redeliver",
+                                post().to(webhooks::redeliver::<R>),
+                            ),
+                    )
+                    .service(
+                        scope("/sync")
+                            .route("/push", post().to(sync::push::<R>))
+                            .route("/pull", get().to(sync::pull::<R>)),
+                    )
+                    .service(
+                        scope("/debug")
+                            .wrap(RequireRole::admin())
+                            .route("/link_table", get().to(routines::get_link_table_data::<R>))
+                            .route("/clear_data", delete().to(routines::clear_data::<R>)),
+                    ),
+            ),
+    );
+}
+
+#[derive(Deserialize)]
+struct DepthQuery {
+    with_exercises: Option<bool>,
+}
+
+fn path_mismatch(name: &str, body_id: &Uuid, path_id: &Uuid) -> HttpResponse {
+    HttpResponse::BadRequest().body(format!(
+        "{} in body ({}) does not match path ({})",
+        name, body_id, path_id
+    ))
+}
+
+// Resolve a training day and make sure it belongs to the routine in the path
+async fn training_day_in_routine<R: RoutinesRepository>(
+    repo: &R,
+    user_id: &Uuid,
+    routine_id: &Uuid,
+    day_id: &Uuid,
+) -> Result<TrainingDay, HttpResponse> {
+    match repo.get_training_day(user_id, day_id).await {
+        Ok(Some(day)) if day.routine_id == *routine_id => Ok(day),
+        Ok(_) => Err(HttpResponse::NotFound().body(format!(
+            "Training day {} not found in routine {}",
+            day_id, routine_id
+        ))),
+        Err(e) => {
+            Err(HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e)))
+        }
+    }
+}
+
+// Find the link that puts an exercise on a training day
+async fn link_in_training_day<R: RoutinesRepository>(
+    repo: &R,
+    user_id: &Uuid,
+    day_id: &Uuid,
+    exercise_id: &Uuid,
+) -> Result<Uuid, HttpResponse> {
+    match repo.get_exercises_for_training_day(user_id, day_id).await {
+        Ok(exercises) => exercises
+            .into_iter()
+            .find(|exercise| exercise.exercise_id == *exercise_id)
+            .map(|exercise| exercise.link_id)
+            .ok_or_else(|| {
+                HttpResponse::NotFound().body(format!(
+                    "Exercise {} is not part of training day {}",
+                    exercise_id, day_id
+                ))
+            }),
+        Err(e) => {
+            Err(HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e)))
+        }
+    }
+}
+
+fn patch_error(error: PatchError) -> HttpResponse {
+    match error {
+        PatchError::NotFound => HttpResponse::NotFound().finish(),
+        PatchError::PreconditionFailed => HttpResponse::PreconditionFailed()
+            .body("Resource was modified since the version in If-Match"),
+        PatchError::Conflict(e) => HttpResponse::Conflict().body(e),
+        PatchError::Error(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+// ROUTINES
+async fn get_routine<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let routine_id = path.into_inner();
+    match repo.get_routine(&claims.token_id, &routine_id).await {
+        Ok(Some(routine)) => {
+            etag::ok_with_etag(routine.created_at, routine.updated_at).json(routine)
+        }
+        Ok(None) => HttpResponse::NotFound().body(format!("Routine {} not found", routine_id)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+async fn patch_routine<R: RoutinesRepository>(
+    req: HttpRequest,
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    patch: Json<PatchRoutine>,
+    repo: Data<R>,
+    bus: Data<ActivityBus>,
+) -> HttpResponse {
+    let routine_id = path.into_inner();
+    let expected_version = match etag::if_match(&req) {
+        Ok(version) => version,
+        Err(e) => return e.error_response(),
+    };
+    match repo
+        .patch_routine(&claims.token_id, &routine_id, &patch, expected_version)
+        .await
+    {
+        Ok(routine) => {
+            if patch.is_active == Some(true) {
+                activity::owner_routine_activated(repo.get_ref(), &bus, &routine).await;
+            }
+            etag::ok_with_etag(routine.created_at, routine.updated_at).json(routine)
+        }
+        Err(e) => patch_error(e),
+    }
+}
+
+async fn update_routine<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    routine: Json<Routine>,
+    repo: Data<R>,
+    bus: Data<ActivityBus>,
+) -> HttpResponse {
+    let routine_id = path.into_inner();
+    if routine.routine_id != routine_id {
+        return path_mismatch("routine_id", &routine.routine_id, &routine_id);
+    }
+    match repo.update_routine(&claims.token_id, &routine).await {
+        Ok(routine) => {
+            if routine.is_active {
+                activity::owner_routine_activated(repo.get_ref(), &bus, &routine).await;
+            }
+            HttpResponse::Ok().json(routine)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+// TRAINING DAYS
+async fn get_training_days<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    query: Query<DepthQuery>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let routine_id = path.into_inner();
+    if query.with_exercises.unwrap_or(false) {
+        match repo
+            .get_training_days_with_exercises(&claims.token_id, &routine_id)
+            .await
+        {
+            Ok(training_days) => HttpResponse::Ok().json(training_days),
+            Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
+        }
+    } else {
+        match repo.get_training_days(&claims.token_id, &routine_id).await {
+            Ok(training_days) => HttpResponse::Ok().json(training_days),
+            Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
+        }
+    }
+}
+
+async fn create_training_day<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    create_training_day: Json<CreateTrainingDay>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let routine_id = path.into_inner();
+    if create_training_day.routine_id != routine_id {
+        return path_mismatch("routine_id", &create_training_day.routine_id, &routine_id);
+    }
+    match repo
+        .create_training_day(&claims.token_id, &create_training_day)
+        .await
+    {
+        Ok(day) => HttpResponse::Ok().json(day),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+async fn create_training_days<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    create_training_days: Json<Vec<CreateTrainingDay>>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let routine_id = path.into_inner();
+    if let Some(day) = create_training_days
+        .iter()
+        .find(|day| day.routine_id != routine_id)
+    {
+        return path_mismatch("routine_id", &day.routine_id, &routine_id);
+    }
+    match repo
+        .create_training_days(&claims.token_id, &create_training_days)
+        .await
+    {
+        Ok(days) => HttpResponse::Ok().json(days),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+async fn delete_training_day<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<(Uuid, Uuid)>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let (routine_id, day_id) = path.into_inner();
+    if let Err(response) =
+        training_day_in_routine(repo.get_ref(), &claims.token_id, &routine_id, &day_id).await
+    {
+        return response;
+    }
+    match repo.delete_training_day(&claims.token_id, &day_id).await {
+        Ok(day_id) => HttpResponse::Ok().json(day_id),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+async fn patch_training_day<R: RoutinesRepository>(
+    req: HttpRequest,
+    claims: ReqData<TokenClaims>,
+    path: Path<(Uuid, Uuid)>,
+    patch: Json<PatchTrainingDay>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let (routine_id, day_id) = path.into_inner();
+    let expected_version = match etag::if_match(&req) {
+        Ok(version) => version,
+        Err(e) => return e.error_response(),
+    };
+    if let Err(response) =
+        training_day_in_routine(repo.get_ref(), &claims.token_id, &routine_id, &day_id).await
+    {
+        return response;
+    }
+    match repo
+        .patch_training_day(&claims.token_id, &day_id, &patch, expected_version)
+        .await
+    {
+        Ok(day) => etag::ok_with_etag(day.created_at, day.updated_at).json(day),
+        Err(e) => patch_error(e),
+    }
+}
+
+// EXERCISES
+// Library exercises can't be changed, they're not found here
+async fn patch_exercise<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    req: HttpRequest,
+    path: Path<Uuid>,
+    patch: Json<PatchExercise>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let exercise_id = path.into_inner();
+    let expected_version = match etag::if_match(&req) {
+        Ok(version) => version,
+        Err(e) => return e.error_response(),
+    };
+    match repo
+        .patch_exercise(&claims.token_id, &exercise_id, &patch, expected_version)
+        .await
+    {
+        Ok(exercise) => etag::ok_with_etag(exercise.created_at, exercise.updated_at).json(exercise),
+        Err(e) => patch_error(e),
+    }
+}
+
+async fn get_exercises_for_training_day<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<(Uuid, Uuid)>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let (routine_id, day_id) = path.into_inner();
+    if let Err(response) =
+        training_day_in_routine(repo.get_ref(), &claims.token_id, &routine_id, &day_id).await
+    {
+        return response;
+    }
+    match repo
+        .get_exercises_for_training_day(&claims.token_id, &day_id)
+        .await
+    {
+        Ok(exercises) => HttpResponse::Ok().json(exercises),
+        Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
+    }
+}
+
+async fn add_exercise_to_training_day<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<(Uuid, Uuid, Uuid)>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let (routine_id, day_id, exercise_id) = path.into_inner();
+    if let Err(response) =
+        training_day_in_routine(repo.get_ref(), &claims.token_id, &routine_id, &day_id).await
+    {
+        return response;
+    }
+    match repo
+        .add_exercise_to_training_day(&claims.token_id, &exercise_id, &day_id)
+        .await
+    {
+        Ok(exercise_day_link) => HttpResponse::Ok().json(exercise_day_link),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+async fn remove_exercise_from_training_day<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<(Uuid, Uuid, Uuid)>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let (routine_id, day_id, exercise_id) = path.into_inner();
+    if let Err(response) =
+        training_day_in_routine(repo.get_ref(), &claims.token_id, &routine_id, &day_id).await
+    {
+        return response;
+    }
+    let link_id =
+        match link_in_training_day(repo.get_ref(), &claims.token_id, &day_id, &exercise_id).await {
+            Ok(link_id) => link_id,
+            Err(response) => return response,
+        };
+    match repo
+        .remove_exercise_from_training_day(&claims.token_id, &link_id)
+        .await
+    {
+        Ok(link_id) => HttpResponse::Ok().json(link_id),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+async fn patch_exercise_link<R: RoutinesRepository>(
+    req: HttpRequest,
+    claims: ReqData<TokenClaims>,
+    path: Path<(Uuid, Uuid, Uuid)>,
+    patch: Json<PatchExerciseToTrainingDay>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let (routine_id, day_id, exercise_id) = path.into_inner();
+    let expected_version = match etag::if_match(&req) {
+        Ok(version) => version,
+        Err(e) => return e.error_response(),
+    };
+    if let Err(response) =
+        training_day_in_routine(repo.get_ref(), &claims.token_id, &routine_id, &day_id).await
+    {
+        return response;
+    }
+    // moving the link to another day must stay within the same routine
+    if let Some(target_day_id) = patch.day_id {
+        if let Err(response) = training_day_in_routine(
+            repo.get_ref(),
+            &claims.token_id,
+            &routine_id,
+            &target_day_id,
+        )
+        .await
+        {
+            return response;
+        }
+    }
+    let link_id =
+        match link_in_training_day(repo.get_ref(), &claims.token_id, &day_id, &exercise_id).await {
+            Ok(link_id) => link_id,
+            Err(response) => return response,
+        };
+    match repo
+        .patch_exercise_link(&claims.token_id, &link_id, &patch, expected_version)
+        .await
+    {
+        Ok(link) => etag::ok_with_etag(link.created_at, link.updated_at).json(link),
+        Err(e) => patch_error(e),
+    }
+}
+
+// SESSIONS
+async fn create_session<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<(Uuid, Uuid)>,
+    query: Query<UnitQuery>,
+    repo: Data<R>,
+    bus: Data<ActivityBus>,
+    hub: Data<SessionHub>,
+) -> HttpResponse {
+    let (routine_id, day_id) = path.into_inner();
+    if let Err(response) =
+        training_day_in_routine(repo.get_ref(), &claims.token_id, &routine_id, &day_id).await
+    {
+        return response;
+    }
+    activity::close_stale_sessions(repo.get_ref(), &bus, &hub, claims.token_id).await;
+    match repo.create_session(&claims.token_id, &day_id).await {
+        Ok(session_with_exercises) => {
+            activity::session_started(&bus, claims.token_id, &session_with_exercises);
+            let unit = account::preferred_unit(repo.get_ref(), &claims.token_id, query.unit).await;
+            HttpResponse::Ok().json(session_with_exercises.in_unit(unit))
+        }
+        Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
+    }
+}
+
+async fn get_sessions_for_training_day<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<(Uuid, Uuid)>,
+    query: Query<DepthQuery>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let (routine_id, day_id) = path.into_inner();
+    if let Err(response) =
+        training_day_in_routine(repo.get_ref(), &claims.token_id, &routine_id, &day_id).await
+    {
+        return response;
+    }
+    if query.with_exercises.unwrap_or(false) {
+        match repo
+            .get_sessions_with_exercises(&claims.token_id, &day_id)
+            .await
+        {
+            Ok(sessions_with_exercises) => HttpResponse::Ok().json(sessions_with_exercises),
+            Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
+        }
+    } else {
+        match repo
+            .get_all_sessions_by_day_id(&claims.token_id, &day_id)
+            .await
+        {
+            Ok(sessions) => HttpResponse::Ok().json(sessions),
+            Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
+        }
+    }
+}
+
+async fn get_session<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    query: Query<UnitQuery>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let session_id = path.into_inner();
+    match repo
+        .get_session_with_performance(&claims.token_id, &session_id)
+        .await
+    {
+        Ok(Some(session)) => {
+            let unit = account::preferred_unit(repo.get_ref(), &claims.token_id, query.unit).await;
+            HttpResponse::Ok().json(session.in_unit(unit))
+        }
+        Ok(None) => HttpResponse::NotFound().body(format!("Session {} not found", session_id)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+async fn add_set_performance_to_session<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<(Uuid, Uuid)>,
+    set_performance: Json<SetPerformancePayload>,
+    repo: Data<R>,
+    hub: Data<SessionHub>,
+    bus: Data<ActivityBus>,
+) -> HttpResponse {
+    let (session_id, exercise_id) = path.into_inner();
+    let unit =
+        account::preferred_unit(repo.get_ref(), &claims.token_id, set_performance.unit).await;
+    let set_performance = set_performance.into_inner().into_storage_unit(unit);
+    match repo.get_session(&claims.token_id, &session_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return HttpResponse::NotFound().body(format!("Session {} not found", session_id))
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Internal server error: {:?}", e))
+        }
+    }
+    if let Err(response) = routines::check_set_metrics(repo.get_ref(), &exercise_id, |metrics| {
+        metrics.check_set(&set_performance)
+    })
+    .await
+    {
+        return response;
+    }
+    match repo
+        .add_set_performance_to_session(
+            &claims.token_id,
+            &session_id,
+            &exercise_id,
+            &set_performance,
+        )
+        .await
+    {
+        Ok(set_performance) => {
+            activity::check_personal_record(
+                repo.get_ref(),
+                &bus,
+                session_id,
+                exercise_id,
+                &set_performance,
+            )
+            .await;
+            hub.publish(
+                session_id,
+                SessionEvent::SetAdded {
+                    session_id,
+                    exercise_id,
+                    set: set_performance.clone(),
+                },
+            );
+            HttpResponse::Ok().json(set_performance.in_unit(unit))
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+async fn remove_set_performance_from_session<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<(Uuid, Uuid, Uuid)>,
+    repo: Data<R>,
+    hub: Data<SessionHub>,
+) -> HttpResponse {
+    let (session_id, exercise_id, performance_id) = path.into_inner();
+    match repo
+        .remove_set_performance_from_session_exercise(
+            &claims.token_id,
+            &session_id,
+            &exercise_id,
+            &performance_id,
+        )
+        .await
+    {
+        Ok(Some(performance_id)) => {
+            hub.publish(
+                session_id,
+                SessionEvent::SetRemoved {
+                    session_id,
+                    exercise_id,
+                    performance_id,
+                },
+            );
+            HttpResponse::Ok().json(performance_id)
+        }
+        Ok(None) => HttpResponse::NotFound().body(format!(
+            "Set {} not found for exercise {} in session {}",
+            performance_id, exercise_id, session_id
+        )),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+async fn patch_set_performance<R: RoutinesRepository>(
+    req: HttpRequest,
+    claims: ReqData<TokenClaims>,
+    path: Path<(Uuid, Uuid, Uuid)>,
+    patch: Json<PatchSetPerformance>,
+    repo: Data<R>,
+    hub: Data<SessionHub>,
+    bus: Data<ActivityBus>,
+) -> HttpResponse {
+    let (session_id, exercise_id, performance_id) = path.into_inner();
+    let expected_version = match etag::if_match(&req) {
+        Ok(version) => version,
+        Err(e) => return e.error_response(),
+    };
+    let unit = account::preferred_unit(repo.get_ref(), &claims.token_id, patch.unit).await;
+    let patch = patch.into_inner().into_storage_unit(unit);
+    if let Err(response) = routines::check_set_metrics(repo.get_ref(), &exercise_id, |metrics| {
+        metrics.check_patch(&patch)
+    })
+    .await
+    {
+        return response;
+    }
+    match repo
+        .patch_set_performance(
+            &claims.token_id,
+            &session_id,
+            &exercise_id,
+            &performance_id,
+            &patch,
+            expected_version,
+        )
+        .await
+    {
+        Ok(set_performance) => {
+            activity::check_personal_record(
+                repo.get_ref(),
+                &bus,
+                session_id,
+                exercise_id,
+                &set_performance,
+            )
+            .await;
+            hub.publish(
+                session_id,
+                SessionEvent::SetUpdated {
+                    session_id,
+                    exercise_id,
+                    set: set_performance.clone(),
+                },
+            );
+            etag::ok_with_etag(set_performance.created_at, set_performance.updated_at)
+                .json(set_performance.in_unit(unit))
+        }
+        Err(e) => patch_error(e),
+    }
+}
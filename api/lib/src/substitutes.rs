@@ -0,0 +1,67 @@
+use actix_web::{
+    web::{Data, Path, Query, ReqData},
+    HttpResponse,
+};
+use shared::models::{Equipment, Substitute, SubstituteQuery, TokenClaims};
+use shared::substitutes::rank_substitutes;
+use uuid::Uuid;
+
+use crate::routines_repository::RoutinesRepository;
+
+pub(crate) const DEFAULT_SUBSTITUTE_LIMIT: usize = 10;
+const MAX_SUBSTITUTE_LIMIT: usize = 50;
+
+// Ranks the exercises `user_id` can see as substitutes for `exercise_id`. None when the exercise
+// doesn't exist or the user can't see it. Shared by the endpoint and the session socket.
+pub(crate) async fn suggest<R: RoutinesRepository>(
+    repo: &R,
+    user_id: &Uuid,
+    exercise_id: &Uuid,
+    available: &[Equipment],
+    limit: usize,
+) -> Result<Option<Vec<Substitute>>, String> {
+    // Not necessarily in the user's own list, like a coach's exercise in their routine
+    let Some(exercise) = repo.get_visible_exercise(user_id, exercise_id).await? else {
+        return Ok(None);
+    };
+    let candidates = repo.get_exercises(user_id).await?;
+    Ok(Some(rank_substitutes(
+        &exercise,
+        &candidates,
+        available,
+        limit.clamp(1, MAX_SUBSTITUTE_LIMIT),
+    )))
+}
+
+// Comparable exercises for when the equipment is taken, by movement pattern and muscles,
+// optionally only ones using the equipment at hand
+pub(crate) async fn get_substitutes<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    query: Query<SubstituteQuery>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let exercise_id = path.into_inner();
+    let available = match query.available_equipment() {
+        Ok(available) => available,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let limit = query
+        .limit
+        .map_or(DEFAULT_SUBSTITUTE_LIMIT, |limit| limit.max(1) as usize);
+    match suggest(
+        repo.get_ref(),
+        &claims.token_id,
+        &exercise_id,
+        &available,
+        limit,
+    )
+    .await
+    {
+        Ok(Some(substitutes)) => HttpResponse::Ok().json(substitutes),
+        Ok(None) => HttpResponse::NotFound().body(format!("Exercise {} not found", exercise_id)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
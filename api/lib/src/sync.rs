@@ -0,0 +1,211 @@
+use actix_web::{
+    web::{Data, Json, Query, ReqData},
+    HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use shared::models::{SyncEntity, SyncOperation, SyncPullQuery, SyncPushRequest, TokenClaims};
+use uuid::Uuid;
+
+use crate::activity::{self, ActivityBus};
+use crate::routines_repository::RoutinesRepository;
+
+// Pseudo field that versions deletes, so a stale edit can't resurrect a deleted row
+pub const DELETED_FIELD: &str = "_deleted";
+
+const DEFAULT_PULL_LIMIT: i64 = 500;
+const MAX_PULL_LIMIT: i64 = 5000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldVersion {
+    pub changed_at: DateTime<Utc>,
+    pub mutation_id: Uuid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Accept,
+    // the same mutation was pushed before, e.g. a retried push
+    AlreadyApplied,
+    Reject,
+}
+
+// Last writer wins per field. Equal timestamps are broken by the larger mutation id,
+// so every server resolves the same pair of writes the same way whatever order they arrive in.
+pub fn resolve(incoming: &FieldVersion, stored: Option<&FieldVersion>) -> Resolution {
+    match stored {
+        None => Resolution::Accept,
+        Some(stored) if stored.mutation_id == incoming.mutation_id => Resolution::AlreadyApplied,
+        Some(stored) => {
+            if (incoming.changed_at, incoming.mutation_id) > (stored.changed_at, stored.mutation_id)
+            {
+                Resolution::Accept
+            } else {
+                Resolution::Reject
+            }
+        }
+    }
+}
+
+// Fields a client may write through sync, ids are taken from the mutation itself
+pub fn writable_fields(entity: SyncEntity) -> &'static [&'static str] {
+    match entity {
+        SyncEntity::Routine => &["name", "description", "is_active"],
+        SyncEntity::Session => &["day_id", "day_name", "in_progress", "created_at"],
+        SyncEntity::SetPerformance => &[
+            "session_id",
+            "exercise_id",
+            "set_number",
+            "weight",
+            "reps",
+            "rir",
+            "duration_seconds",
+            "distance_meters",
+            "calories",
+            "heart_rate",
+        ],
+    }
+}
+
+pub fn id_field(entity: SyncEntity) -> &'static str {
+    match entity {
+        SyncEntity::Routine => "routine_id",
+        SyncEntity::Session => "session_id",
+        SyncEntity::SetPerformance => "performance_id",
+    }
+}
+
+pub(crate) async fn push<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    push_request: Json<SyncPushRequest>,
+    repo: Data<R>,
+    bus: Data<ActivityBus>,
+) -> HttpResponse {
+    match repo
+        .push_sync_mutations(&claims.token_id, &push_request.mutations)
+        .await
+    {
+        Ok(response) => {
+            // Sets logged offline make PRs the same as sets logged live
+            let written: Vec<Uuid> = push_request
+                .mutations
+                .iter()
+                .filter(|mutation| {
+                    mutation.entity == SyncEntity::SetPerformance
+                        && mutation.operation == SyncOperation::Upsert
+                        && !response
+                            .rejected
+                            .iter()
+                            .any(|rejection| rejection.mutation_id == mutation.mutation_id)
+                })
+                .map(|mutation| mutation.entity_id)
+                .collect();
+            if !written.is_empty() {
+                match repo.get_logged_sets(&written).await {
+                    Ok(sets) => {
+                        for logged in sets {
+                            activity::check_personal_record(
+                                repo.get_ref(),
+                                &bus,
+                                logged.session_id,
+                                logged.exercise_id,
+                                &logged.set,
+                            )
+                            .await;
+                        }
+                    }
+                    Err(e) => log::error!("Failed to load synced sets: {}", e),
+                }
+            }
+            HttpResponse::Ok().json(response)
+        }
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+pub(crate) async fn pull<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    query: Query<SyncPullQuery>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let cursor = query.cursor.unwrap_or(0);
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PULL_LIMIT)
+        .clamp(1, MAX_PULL_LIMIT);
+    match repo
+        .pull_sync_changes(&claims.token_id, cursor, limit)
+        .await
+    {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn version(seconds: i64, mutation_id: u128) -> FieldVersion {
+        FieldVersion {
+            changed_at: Utc.timestamp_opt(1_700_000_000 + seconds, 0).unwrap(),
+            mutation_id: Uuid::from_u128(mutation_id),
+        }
+    }
+
+    #[test]
+    fn first_write_is_accepted() {
+        assert_eq!(resolve(&version(0, 1), None), Resolution::Accept);
+    }
+
+    #[test]
+    fn later_write_wins() {
+        assert_eq!(
+            resolve(&version(10, 1), Some(&version(5, 2))),
+            Resolution::Accept
+        );
+        assert_eq!(
+            resolve(&version(5, 2), Some(&version(10, 1))),
+            Resolution::Reject
+        );
+    }
+
+    #[test]
+    fn equal_timestamps_go_to_the_larger_mutation_id_in_either_order() {
+        let smaller = version(5, 1);
+        let larger = version(5, 2);
+        assert_eq!(resolve(&larger, Some(&smaller)), Resolution::Accept);
+        assert_eq!(resolve(&smaller, Some(&larger)), Resolution::Reject);
+    }
+
+    #[test]
+    fn replayed_mutation_is_already_applied() {
+        let pushed = version(5, 1);
+        assert_eq!(resolve(&pushed, Some(&pushed)), Resolution::AlreadyApplied);
+        // a retry is recognized by its id even when the stored time was written differently
+        assert_eq!(
+            resolve(&version(5, 1), Some(&version(7, 1))),
+            Resolution::AlreadyApplied
+        );
+    }
+
+    #[test]
+    fn stale_delete_loses_to_a_newer_field_write() {
+        let edited = version(10, 1);
+        let delete = version(5, 2);
+        assert_eq!(resolve(&delete, Some(&edited)), Resolution::Reject);
+        assert_eq!(resolve(&version(15, 2), Some(&edited)), Resolution::Accept);
+    }
+
+    #[test]
+    fn stale_edit_cannot_resurrect_a_deleted_row() {
+        let deleted = version(10, 2);
+        assert_eq!(resolve(&version(5, 1), Some(&deleted)), Resolution::Reject);
+        assert_eq!(resolve(&version(15, 3), Some(&deleted)), Resolution::Accept);
+    }
+}
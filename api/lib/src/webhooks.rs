@@ -0,0 +1,764 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::{
+    web::{Data, Json, Path, Query, ReqData},
+    HttpResponse,
+};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use shared::models::{ActivityEvent, CreateWebhook, CreatedWebhook, TokenClaims, WebhookDelivery};
+use tokio::{runtime::Handle, sync::broadcast::error::RecvError};
+use uuid::Uuid;
+
+use crate::activity::{ActivityBus, ActivityEnvelope};
+use crate::routines_repository::{RoutinesRepository, WebhookTarget};
+
+const DEFAULT_MAX_ATTEMPTS: i32 = 6;
+const DEFAULT_RETRY_BASE_SECONDS: u64 = 10;
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+const DEFAULT_DELIVERIES_LIMIT: i64 = 50;
+const MAX_DELIVERIES_LIMIT: i64 = 500;
+
+/// Sends activity events to the webhooks users registered for them.
+///
+/// Each delivery is POSTed as JSON with an `X-Webhook-Signature: sha256=<hex>` header,
+/// the HMAC-SHA256 of the body keyed with the webhook's secret. Failed attempts are retried
+/// with exponential backoff (`WEBHOOK_RETRY_BASE_SECONDS`, default 10s, doubling per attempt
+/// up to an hour) until `WEBHOOK_MAX_ATTEMPTS` (default 6), and every attempt is written
+/// to the delivery log.
+///
+/// Only public addresses are ever sent to: hosts are resolved again at every attempt and
+/// redirects are not followed, so neither a re-pointed host nor a `302` reaches the server's
+/// own network.
+pub struct WebhookDispatcher<R> {
+    http: reqwest::Client,
+    // false only in tests, whose receiver listens on loopback
+    public_only: bool,
+    repo: Data<R>,
+    // deliveries always run on the runtime the dispatcher was created on,
+    // not on whichever worker handled a redeliver request
+    runtime: Handle,
+    max_attempts: i32,
+    retry_base: Duration,
+}
+
+impl<R> Clone for WebhookDispatcher<R> {
+    fn clone(&self) -> Self {
+        Self {
+            http: self.http.clone(),
+            public_only: self.public_only,
+            repo: self.repo.clone(),
+            runtime: self.runtime.clone(),
+            max_attempts: self.max_attempts,
+            retry_base: self.retry_base,
+        }
+    }
+}
+
+impl<R: RoutinesRepository> WebhookDispatcher<R> {
+    /// Must be called from within a tokio runtime.
+    pub fn new(repo: Data<R>) -> Self {
+        let max_attempts = std::env::var("WEBHOOK_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|attempts| attempts.parse().ok())
+            .unwrap_or(DEFAULT_MAX_ATTEMPTS)
+            .max(1);
+        let retry_base = std::env::var("WEBHOOK_RETRY_BASE_SECONDS")
+            .ok()
+            .and_then(|seconds| seconds.parse().ok())
+            .unwrap_or(DEFAULT_RETRY_BASE_SECONDS);
+        Self {
+            http: http_client(),
+            public_only: true,
+            repo,
+            runtime: Handle::current(),
+            max_attempts,
+            retry_base: Duration::from_secs(retry_base),
+        }
+    }
+
+    /// Start delivering webhooks for events published on `bus`.
+    /// Deliveries still pending from before a restart are picked up again first.
+    pub fn start(&self, bus: &ActivityBus) {
+        let dispatcher = self.clone();
+        let mut events = bus.subscribe_all();
+        self.runtime.spawn(async move {
+            match dispatcher.repo.get_pending_webhook_deliveries().await {
+                Ok(pending) => {
+                    for delivery in pending {
+                        dispatcher.deliver(delivery);
+                    }
+                }
+                Err(e) => log::error!("Failed to load pending webhook deliveries: {}", e),
+            }
+
+            loop {
+                match events.recv().await {
+                    Ok(envelope) => dispatcher.enqueue(envelope).await,
+                    Err(RecvError::Lagged(skipped)) => {
+                        log::warn!("Webhook dispatcher fell behind, {} events skipped", skipped)
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn enqueue(&self, envelope: ActivityEnvelope) {
+        let event_type = envelope.event.kind();
+        let payload = serde_json::json!({
+            "event": event_type,
+            "user_id": envelope.user_id,
+            "occurred_at": Utc::now(),
+            "data": envelope.event,
+        });
+        match self
+            .repo
+            .create_webhook_deliveries(&envelope.user_id, event_type, &payload)
+            .await
+        {
+            Ok(deliveries) => {
+                for delivery in deliveries {
+                    self.deliver(delivery);
+                }
+            }
+            Err(e) => log::error!("Failed to queue {} webhooks: {}", event_type, e),
+        }
+    }
+
+    pub(crate) fn deliver(&self, delivery: WebhookDelivery) {
+        let dispatcher = self.clone();
+        self.runtime
+            .spawn(async move { dispatcher.run_delivery(delivery).await });
+    }
+
+    async fn run_delivery(&self, delivery: WebhookDelivery) {
+        // A delivery resumed after a restart keeps the backoff it was scheduled with
+        if let Some(wait) = delivery
+            .next_attempt_at
+            .and_then(|next_attempt_at| (next_attempt_at - Utc::now()).to_std().ok())
+        {
+            tokio::time::sleep(wait).await;
+        }
+
+        let body = match serde_json::to_vec(&delivery.payload) {
+            Ok(body) => body,
+            Err(e) => {
+                log::error!("Failed to serialize webhook payload: {}", e);
+                return;
+            }
+        };
+
+        let mut attempt = delivery.attempt_count;
+        loop {
+            let target = match self.repo.get_webhook_target(&delivery.webhook_id).await {
+                Ok(Some(target)) if target.is_active => target,
+                // deleted or disabled since the delivery was queued
+                Ok(_) => return,
+                Err(e) => {
+                    log::error!("Failed to load webhook {}: {}", delivery.webhook_id, e);
+                    return;
+                }
+            };
+
+            attempt += 1;
+            let (response_status, error) = match self.send(&target, &delivery, &body).await {
+                Ok(status) if status.is_success() => {
+                    self.record(&delivery, "succeeded", Some(status.as_u16()), None, None)
+                        .await;
+                    return;
+                }
+                Ok(status) => (
+                    Some(status.as_u16()),
+                    format!("Receiver answered {}", status),
+                ),
+                Err(e) => (None, e),
+            };
+
+            if attempt >= self.max_attempts {
+                self.record(&delivery, "failed", response_status, Some(&error), None)
+                    .await;
+                return;
+            }
+
+            let delay = self.retry_delay(attempt);
+            let next_attempt_at =
+                Utc::now() + chrono::Duration::from_std(delay).unwrap_or_default();
+            self.record(
+                &delivery,
+                "pending",
+                response_status,
+                Some(&error),
+                Some(next_attempt_at),
+            )
+            .await;
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn send(
+        &self,
+        target: &WebhookTarget,
+        delivery: &WebhookDelivery,
+        body: &[u8],
+    ) -> Result<reqwest::StatusCode, String> {
+        let url = reqwest::Url::parse(&target.url).map_err(|e| e.to_string())?;
+        // Hosts given by name go through `PublicResolver`, addresses are never looked up
+        if let Some(ip) = host(&url).and_then(|host| host.parse::<IpAddr>().ok()) {
+            if self.public_only && !is_public(ip) {
+                return Err(format!("{} is not a public host", ip));
+            }
+        }
+        self.http
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Webhook-Event", &delivery.event_type)
+            .header("X-Webhook-Delivery", delivery.delivery_id.to_string())
+            .header(
+                "X-Webhook-Signature",
+                format!("sha256={}", sign(&target.secret, body)),
+            )
+            .body(body.to_vec())
+            .send()
+            .await
+            .map(|response| response.status())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn record(
+        &self,
+        delivery: &WebhookDelivery,
+        status: &str,
+        response_status: Option<u16>,
+        error: Option<&str>,
+        next_attempt_at: Option<chrono::DateTime<Utc>>,
+    ) {
+        if let Err(e) = self
+            .repo
+            .record_webhook_attempt(
+                &delivery.delivery_id,
+                status,
+                response_status.map(|status| status as i16),
+                error,
+                next_attempt_at,
+            )
+            .await
+        {
+            log::error!(
+                "Failed to log webhook delivery {}: {}",
+                delivery.delivery_id,
+                e
+            );
+        }
+    }
+
+    // base, 2 * base, 4 * base, ... capped at an hour
+    fn retry_delay(&self, attempt: i32) -> Duration {
+        let factor = 2u32.saturating_pow((attempt - 1).max(0) as u32);
+        self.retry_base
+            .checked_mul(factor)
+            .unwrap_or(MAX_RETRY_DELAY)
+            .min(MAX_RETRY_DELAY)
+    }
+}
+
+/// Hex HMAC-SHA256 of `body`, what receivers compare `X-Webhook-Signature` against.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+// Addresses on the internet at large. Webhooks may not reach the server's own network, like
+// loopback, private, link-local (cloud metadata) or carrier-grade NAT ranges.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_unspecified()
+                    || ip.is_loopback()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .dns_resolver(Arc::new(PublicResolver))
+        .build()
+        .expect("webhook http client")
+}
+
+// The url's host without the brackets IPv6 hosts come in
+fn host(url: &reqwest::Url) -> Option<&str> {
+    url.host_str()
+        .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+}
+
+// What `host` resolves to, as long as every address is public
+async fn public_addresses(host: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| format!("Couldn't resolve {}: {}", host, e))?
+        .collect();
+    if addresses.is_empty() || !addresses.iter().all(|address| is_public(address.ip())) {
+        return Err(format!("{} is not a public host", host));
+    }
+    Ok(addresses)
+}
+
+// Every address the url's host resolves to has to be public
+async fn check_public_url(url: &reqwest::Url) -> Result<(), String> {
+    let Some(host) = host(url) else {
+        return Err(format!("{} has no host", url));
+    };
+    public_addresses(host, url.port_or_known_default().unwrap_or(443))
+        .await
+        .map(|_| ())
+}
+
+// The dispatcher's resolver. Looking up hosts at connect time, rather than trusting the check
+// made when the webhook was created, keeps a host re-pointed since from reaching private
+// addresses.
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            // reqwest fills in the url's port
+            let addresses = public_addresses(name.as_str(), 0).await?;
+            Ok(Box::new(addresses.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct DeliveriesQuery {
+    limit: Option<i64>,
+}
+
+pub(crate) async fn create_webhook<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    create_webhook: Json<CreateWebhook>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let url = match reqwest::Url::parse(&create_webhook.url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => url,
+        _ => {
+            return HttpResponse::BadRequest()
+                .body(format!("{} is not an http(s) url", create_webhook.url))
+        }
+    };
+    if let Err(e) = check_public_url(&url).await {
+        return HttpResponse::BadRequest().body(e);
+    }
+    if let Some(event) = create_webhook
+        .events
+        .iter()
+        .find(|event| !ActivityEvent::KINDS.contains(&event.as_str()))
+    {
+        return HttpResponse::BadRequest().body(format!(
+            "Unknown event {}, expected one of {}",
+            event,
+            ActivityEvent::KINDS.join(", ")
+        ));
+    }
+
+    let secret = create_webhook
+        .secret
+        .clone()
+        .filter(|secret| !secret.is_empty())
+        .unwrap_or_else(|| {
+            format!(
+                "whsec_{}{}",
+                Uuid::new_v4().simple(),
+                Uuid::new_v4().simple()
+            )
+        });
+    match repo
+        .create_webhook(
+            &claims.token_id,
+            &create_webhook.url,
+            &create_webhook.events,
+            &secret,
+        )
+        .await
+    {
+        Ok(webhook) => HttpResponse::Ok().json(CreatedWebhook { webhook, secret }),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+pub(crate) async fn get_webhooks<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    repo: Data<R>,
+) -> HttpResponse {
+    match repo.get_webhooks(&claims.token_id).await {
+        Ok(webhooks) => HttpResponse::Ok().json(webhooks),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+pub(crate) async fn delete_webhook<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let webhook_id = path.into_inner();
+    match repo.delete_webhook(&claims.token_id, &webhook_id).await {
+        Ok(Some(webhook_id)) => HttpResponse::Ok().json(webhook_id),
+        Ok(None) => HttpResponse::NotFound().body(format!("Webhook {} not found", webhook_id)),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+pub(crate) async fn get_webhook_deliveries<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<Uuid>,
+    query: Query<DeliveriesQuery>,
+    repo: Data<R>,
+) -> HttpResponse {
+    let webhook_id = path.into_inner();
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_DELIVERIES_LIMIT)
+        .clamp(1, MAX_DELIVERIES_LIMIT);
+    match repo
+        .get_webhook_deliveries(&claims.token_id, &webhook_id, limit)
+        .await
+    {
+        Ok(deliveries) => HttpResponse::Ok().json(deliveries),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+pub(crate) async fn redeliver<R: RoutinesRepository>(
+    claims: ReqData<TokenClaims>,
+    path: Path<(Uuid, Uuid)>,
+    repo: Data<R>,
+    dispatcher: Data<WebhookDispatcher<R>>,
+) -> HttpResponse {
+    let (webhook_id, delivery_id) = path.into_inner();
+    match repo
+        .redeliver_webhook_delivery(&claims.token_id, &webhook_id, &delivery_id)
+        .await
+    {
+        Ok(Some(delivery)) => {
+            dispatcher.deliver(delivery.clone());
+            HttpResponse::Accepted().json(delivery)
+        }
+        Ok(None) => HttpResponse::NotFound().body(format!(
+            "Delivery {} not found for webhook {}",
+            delivery_id, webhook_id
+        )),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    use actix_web::{web, App, HttpRequest, HttpServer};
+
+    use super::*;
+    use crate::routines_repository::MockRoutinesRepository;
+
+    // What the stand-in receiver was sent: signature header and body
+    type Received = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
+
+    // A receiver on a free local port answering with `statuses` in turn, 200 once they run out
+    fn start_receiver(statuses: Vec<u16>) -> (String, Received) {
+        let statuses = Arc::new(Mutex::new(VecDeque::from(statuses)));
+        let received: Received = Arc::default();
+        let log = received.clone();
+        let server = HttpServer::new(move || {
+            let statuses = statuses.clone();
+            let log = log.clone();
+            App::new().default_service(web::to(move |req: HttpRequest, body: web::Bytes| {
+                let statuses = statuses.clone();
+                let log = log.clone();
+                async move {
+                    let signature = req
+                        .headers()
+                        .get("X-Webhook-Signature")
+                        .and_then(|signature| signature.to_str().ok())
+                        .unwrap_or_default()
+                        .to_string();
+                    log.lock().unwrap().push((signature, body.to_vec()));
+                    let status = statuses.lock().unwrap().pop_front().unwrap_or(200);
+                    HttpResponse::build(actix_web::http::StatusCode::from_u16(status).unwrap())
+                        .finish()
+                }
+            }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://{}/hook", server.addrs()[0]);
+        actix_rt::spawn(server.run());
+        (url, received)
+    }
+
+    fn dispatcher(
+        repo: MockRoutinesRepository,
+        max_attempts: i32,
+    ) -> WebhookDispatcher<MockRoutinesRepository> {
+        WebhookDispatcher {
+            http: http_client(),
+            public_only: false,
+            repo: Data::new(repo),
+            runtime: Handle::current(),
+            max_attempts,
+            retry_base: Duration::ZERO,
+        }
+    }
+
+    fn repo_for(url: &str, secret: &str) -> MockRoutinesRepository {
+        let mut repo = MockRoutinesRepository::new();
+        let target = WebhookTarget {
+            url: url.to_string(),
+            secret: secret.to_string(),
+            is_active: true,
+        };
+        repo.expect_get_webhook_target()
+            .returning(move |_| Ok(Some(target.clone())));
+        repo
+    }
+
+    fn delivery() -> WebhookDelivery {
+        WebhookDelivery {
+            delivery_id: Uuid::new_v4(),
+            webhook_id: Uuid::new_v4(),
+            event_type: "session_ended".to_string(),
+            payload: serde_json::json!({ "event": "session_ended" }),
+            status: "pending".to_string(),
+            attempt_count: 0,
+            response_status: None,
+            last_error: None,
+            next_attempt_at: None,
+            delivered_at: None,
+            created_at: None,
+        }
+    }
+
+    // (status, response_status) of every attempt recorded
+    type Recorded = Arc<Mutex<Vec<(String, Option<i16>)>>>;
+
+    fn record_attempts(repo: &mut MockRoutinesRepository) -> Recorded {
+        let recorded: Recorded = Arc::default();
+        let log = recorded.clone();
+        repo.expect_record_webhook_attempt()
+            .returning(move |_, status, response_status, _, _| {
+                log.lock()
+                    .unwrap()
+                    .push((status.to_string(), response_status));
+                Ok(())
+            });
+        recorded
+    }
+
+    #[test]
+    fn sign_is_hex_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn retry_delay_doubles_up_to_an_hour() {
+        let mut dispatcher = dispatcher(MockRoutinesRepository::new(), 6);
+        dispatcher.retry_base = Duration::from_secs(10);
+        assert_eq!(dispatcher.retry_delay(0), Duration::from_secs(10));
+        assert_eq!(dispatcher.retry_delay(1), Duration::from_secs(10));
+        assert_eq!(dispatcher.retry_delay(2), Duration::from_secs(20));
+        assert_eq!(dispatcher.retry_delay(3), Duration::from_secs(40));
+        assert_eq!(dispatcher.retry_delay(10), MAX_RETRY_DELAY);
+        assert_eq!(dispatcher.retry_delay(100), MAX_RETRY_DELAY);
+    }
+
+    #[actix_rt::test]
+    async fn run_delivery_retries_until_the_receiver_accepts() {
+        let (url, received) = start_receiver(vec![500, 503]);
+        let mut repo = repo_for(&url, "secret");
+        let recorded = record_attempts(&mut repo);
+        let delivery = delivery();
+
+        dispatcher(repo, 6).run_delivery(delivery.clone()).await;
+
+        assert_eq!(
+            *recorded.lock().unwrap(),
+            vec![
+                ("pending".to_string(), Some(500)),
+                ("pending".to_string(), Some(503)),
+                ("succeeded".to_string(), Some(200)),
+            ]
+        );
+        let body = serde_json::to_vec(&delivery.payload).unwrap();
+        let expected = (format!("sha256={}", sign("secret", &body)), body);
+        assert_eq!(*received.lock().unwrap(), vec![expected; 3]);
+    }
+
+    #[actix_rt::test]
+    async fn run_delivery_gives_up_after_max_attempts() {
+        let (url, received) = start_receiver(vec![500; 5]);
+        let mut repo = repo_for(&url, "secret");
+        let recorded = record_attempts(&mut repo);
+
+        dispatcher(repo, 3).run_delivery(delivery()).await;
+
+        assert_eq!(
+            *recorded.lock().unwrap(),
+            vec![
+                ("pending".to_string(), Some(500)),
+                ("pending".to_string(), Some(500)),
+                ("failed".to_string(), Some(500)),
+            ]
+        );
+        assert_eq!(received.lock().unwrap().len(), 3);
+    }
+
+    #[actix_rt::test]
+    async fn run_delivery_stops_when_the_webhook_is_gone() {
+        let mut repo = MockRoutinesRepository::new();
+        repo.expect_get_webhook_target().returning(|_| Ok(None));
+        repo.expect_record_webhook_attempt().never();
+
+        dispatcher(repo, 3).run_delivery(delivery()).await;
+    }
+
+    #[actix_rt::test]
+    async fn run_delivery_refuses_non_public_addresses() {
+        let (url, received) = start_receiver(vec![]);
+        let mut repo = repo_for(&url, "secret");
+        let recorded = record_attempts(&mut repo);
+        let mut dispatcher = dispatcher(repo, 1);
+        dispatcher.public_only = true;
+
+        dispatcher.run_delivery(delivery()).await;
+
+        assert_eq!(
+            *recorded.lock().unwrap(),
+            vec![("failed".to_string(), None)]
+        );
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn run_delivery_does_not_follow_redirects() {
+        let (target, received) = start_receiver(vec![]);
+        let server = HttpServer::new(move || {
+            let target = target.clone();
+            App::new().default_service(web::to(move || {
+                let target = target.clone();
+                async move {
+                    HttpResponse::Found()
+                        .insert_header((actix_web::http::header::LOCATION, target))
+                        .finish()
+                }
+            }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://{}/hook", server.addrs()[0]);
+        actix_rt::spawn(server.run());
+        let mut repo = repo_for(&url, "secret");
+        let recorded = record_attempts(&mut repo);
+
+        dispatcher(repo, 1).run_delivery(delivery()).await;
+
+        assert_eq!(
+            *recorded.lock().unwrap(),
+            vec![("failed".to_string(), Some(302))]
+        );
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn resolver_refuses_local_hosts() {
+        use reqwest::dns::Resolve;
+
+        let name = "localhost".parse().unwrap();
+        assert!(PublicResolver.resolve(name).await.is_err());
+    }
+
+    #[test]
+    fn only_public_addresses_are_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fe80::1",
+            "fd00::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{} is not public", ip);
+        }
+        for ip in ["93.184.216.34", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{} is public", ip);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn local_urls_are_rejected() {
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://localhost/hook",
+            "http://[::1]/hook",
+            "http://169.254.169.254/latest/meta-data",
+        ] {
+            let url = reqwest::Url::parse(url).unwrap();
+            assert!(
+                check_public_url(&url).await.is_err(),
+                "{} was accepted",
+                url
+            );
+        }
+        let url = reqwest::Url::parse("https://93.184.216.34/hook").unwrap();
+        assert_eq!(check_public_url(&url).await, Ok(()));
+    }
+}
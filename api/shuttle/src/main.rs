@@ -35,12 +35,30 @@ async fn actix_web(
         .map_err(CustomError::new)?;
 
     let routines_repository = api_lib::routines_repository::PostgresRoutinesRepository::new(pool);
+    api_lib::library::seed(&routines_repository)
+        .await
+        .map_err(CustomError::msg)?;
     let routines_repository = actix_web::web::Data::new(routines_repository);
+    // shared by all workers so sockets see writes handled on any of them
+    let session_hub = actix_web::web::Data::new(api_lib::live::SessionHub::new());
+    let activity_bus = actix_web::web::Data::new(api_lib::activity::ActivityBus::new());
+    let webhook_dispatcher = api_lib::webhooks::WebhookDispatcher::new(routines_repository.clone());
+    webhook_dispatcher.start(&activity_bus);
+    let webhook_dispatcher = actix_web::web::Data::new(webhook_dispatcher);
 
     let config = move |cfg: &mut ServiceConfig| {
-        cfg.service(web::scope("/api").app_data(routines_repository).configure(
-            api_lib::routines::service::<api_lib::routines_repository::PostgresRoutinesRepository>,
-        ));
+        cfg.service(
+            web::scope("/api")
+                .app_data(routines_repository)
+                .app_data(session_hub)
+                .app_data(activity_bus)
+                .app_data(webhook_dispatcher)
+                .configure(
+                    api_lib::routines::service::<
+                        api_lib::routines_repository::PostgresRoutinesRepository,
+                    >,
+                ),
+        );
     };
 
     Ok(config.into())
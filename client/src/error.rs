@@ -0,0 +1,66 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+
+#[derive(Debug)]
+pub enum ClientError {
+    // transport or (de)serialisation failure inside reqwest
+    Http(reqwest::Error),
+    // a request that needs a token was made before `login` and no credentials are stored
+    MissingCredentials,
+    Unauthorized(String),
+    NotFound(String),
+    PreviousSessionInProgress,
+    NoExercisesFound,
+    Api { status: u16, message: String },
+}
+
+impl ClientError {
+    // Map a non-success response onto the error the server meant to send
+    pub(crate) fn from_response(status: StatusCode, body: String) -> Self {
+        // most handlers answer with `.json(format!(..))`, so unwrap the JSON string if there is one
+        let message = serde_json::from_str::<String>(&body).unwrap_or(body);
+
+        if message.contains("PreviousSessionInProgress") {
+            return ClientError::PreviousSessionInProgress;
+        }
+        if message.contains("NoExercisesFound") {
+            return ClientError::NoExercisesFound;
+        }
+
+        match status {
+            StatusCode::UNAUTHORIZED => ClientError::Unauthorized(message),
+            StatusCode::NOT_FOUND => ClientError::NotFound(message),
+            _ => ClientError::Api {
+                status: status.as_u16(),
+                message,
+            },
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Http(e) => write!(f, "http error: {}", e),
+            ClientError::MissingCredentials => write!(f, "no token or credentials available"),
+            ClientError::Unauthorized(message) => write!(f, "unauthorized: {}", message),
+            ClientError::NotFound(message) => write!(f, "not found: {}", message),
+            ClientError::PreviousSessionInProgress => {
+                write!(f, "a previous session for this day is still in progress")
+            }
+            ClientError::NoExercisesFound => write!(f, "no exercises found for session"),
+            ClientError::Api { status, message } => write!(f, "api error {}: {}", status, message),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Http(e)
+    }
+}
+
+pub type ClientResult<T> = Result<T, ClientError>;
@@ -0,0 +1,445 @@
+use std::sync::RwLock;
+
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::{de::DeserializeOwned, Serialize};
+use shared::models::{
+    CreateExercise, CreateRoutine, CreateTrainingDay, CreateUser, Equipment, Exercise,
+    ExerciseSearchHit, ExerciseToTrainingDay, ExerciseWithLinkId, Role, Routine, SearchQuery,
+    Session, SessionWithExercisePerformance, SessionWithExercises, SetPerformance,
+    SetPerformancePayload, Substitute, TrainingDay, TrainingDayWithExercises, UpdateRole, User,
+    UserNoPassword,
+};
+use uuid::Uuid;
+
+pub use error::{ClientError, ClientResult};
+
+mod error;
+
+#[derive(Clone)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+/// Typed client for the routes registered by `api_lib::routines::service`.
+///
+/// `base_url` is everything up to and including the `/api` scope,
+/// e.g. `http://localhost:8000/api`.
+pub struct ApiClient {
+    http: reqwest::Client,
+    base_url: String,
+    credentials: RwLock<Option<Credentials>>,
+    token: RwLock<Option<String>>,
+}
+
+impl ApiClient {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            credentials: RwLock::new(None),
+            token: RwLock::new(None),
+        }
+    }
+
+    // Use a token obtained elsewhere, e.g. restored from local storage
+    pub fn with_token(self, token: impl Into<String>) -> Self {
+        *self.token.write().unwrap() = Some(token.into());
+        self
+    }
+
+    pub fn token(&self) -> Option<String> {
+        self.token.read().unwrap().clone()
+    }
+
+    // AUTH
+    /// Exchange basic auth credentials for a bearer token.
+    ///
+    /// The credentials are kept so the token can be refreshed transparently
+    /// when the server answers a request with 401.
+    pub async fn login(&self, username: &str, password: &str) -> ClientResult<String> {
+        *self.credentials.write().unwrap() = Some(Credentials {
+            username: username.to_string(),
+            password: password.to_string(),
+        });
+        self.refresh_token().await
+    }
+
+    pub fn logout(&self) {
+        *self.credentials.write().unwrap() = None;
+        *self.token.write().unwrap() = None;
+    }
+
+    pub async fn refresh_token(&self) -> ClientResult<String> {
+        let credentials = self
+            .credentials
+            .read()
+            .unwrap()
+            .clone()
+            .ok_or(ClientError::MissingCredentials)?;
+
+        let response = self
+            .http
+            .get(self.url("/v1/users/auth"))
+            .basic_auth(credentials.username, Some(credentials.password))
+            .send()
+            .await?;
+        let token: String = Self::decode(response).await?;
+
+        *self.token.write().unwrap() = Some(token.clone());
+        Ok(token)
+    }
+
+    // USERS
+    pub async fn create_user(&self, create_user: &CreateUser) -> ClientResult<User> {
+        let request = self
+            .http
+            .post(self.url("/v1/users/create"))
+            .json(create_user);
+        Self::decode(request.send().await?).await
+    }
+
+    pub async fn get_users(&self) -> ClientResult<Vec<UserNoPassword>> {
+        self.send(Method::GET, "/v1/users/all", None::<&()>).await
+    }
+
+    pub async fn set_user_role(&self, user_id: &Uuid, role: Role) -> ClientResult<UserNoPassword> {
+        self.send(
+            Method::PUT,
+            &format!("/v1/users/{}/role", user_id),
+            Some(&UpdateRole { role }),
+        )
+        .await
+    }
+
+    // ROUTINES
+    pub async fn get_all_routines(&self) -> ClientResult<Vec<Routine>> {
+        self.send(Method::GET, "/v1/routines", None::<&()>).await
+    }
+
+    pub async fn get_active_routines(&self) -> ClientResult<Vec<Routine>> {
+        self.send(Method::GET, "/v1/routines/active", None::<&()>)
+            .await
+    }
+
+    pub async fn create_routine(&self, create_routine: &CreateRoutine) -> ClientResult<Routine> {
+        self.send(Method::POST, "/v1/routines", Some(create_routine))
+            .await
+    }
+
+    pub async fn update_routine(&self, routine: &Routine) -> ClientResult<Routine> {
+        self.send(Method::PUT, "/v1/routines", Some(routine)).await
+    }
+
+    pub async fn delete_routine(&self, routine_id: &Uuid) -> ClientResult<Uuid> {
+        self.send(
+            Method::DELETE,
+            &format!("/v1/routines/{}", routine_id),
+            None::<&()>,
+        )
+        .await
+    }
+
+    // TRAINING DAYS
+    pub async fn get_training_days(&self, routine_id: &Uuid) -> ClientResult<Vec<TrainingDay>> {
+        self.send(
+            Method::GET,
+            &format!("/v1/training_days/{}", routine_id),
+            None::<&()>,
+        )
+        .await
+    }
+
+    pub async fn get_training_days_with_exercises(
+        &self,
+        routine_id: &Uuid,
+    ) -> ClientResult<Vec<TrainingDayWithExercises>> {
+        self.send(
+            Method::GET,
+            &format!("/v1/training_days/with_exercises/{}", routine_id),
+            None::<&()>,
+        )
+        .await
+    }
+
+    pub async fn create_training_day(
+        &self,
+        create_training_day: &CreateTrainingDay,
+    ) -> ClientResult<TrainingDay> {
+        self.send(
+            Method::POST,
+            &format!("/v1/training_days/{}", create_training_day.routine_id),
+            Some(create_training_day),
+        )
+        .await
+    }
+
+    pub async fn create_training_days(
+        &self,
+        create_training_days: &[CreateTrainingDay],
+    ) -> ClientResult<Vec<TrainingDay>> {
+        self.send(
+            Method::POST,
+            "/v1/training_days",
+            Some(create_training_days),
+        )
+        .await
+    }
+
+    pub async fn delete_training_day(&self, day_id: &Uuid) -> ClientResult<Option<Uuid>> {
+        self.send(
+            Method::DELETE,
+            &format!("/v1/training_days/{}", day_id),
+            None::<&()>,
+        )
+        .await
+    }
+
+    // EXERCISES
+    pub async fn get_exercises(&self) -> ClientResult<Vec<Exercise>> {
+        self.send(Method::GET, "/v1/exercises", None::<&()>).await
+    }
+
+    // Ranked best match first, typos and aliases included
+    pub async fn search_exercises(&self, name: &str) -> ClientResult<Vec<ExerciseSearchHit>> {
+        let query = SearchQuery {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        self.filter_exercises(&query).await
+    }
+
+    // Search on equipment, movement pattern, muscle or laterality as well as the name
+    pub async fn filter_exercises(
+        &self,
+        query: &SearchQuery,
+    ) -> ClientResult<Vec<ExerciseSearchHit>> {
+        let request = self.http.get(self.url("/v1/exercises/search")).query(query);
+        self.execute(request).await
+    }
+
+    // Comparable exercises best first, only ones using `equipment` unless it's empty
+    pub async fn get_substitutes(
+        &self,
+        exercise_id: &Uuid,
+        equipment: &[Equipment],
+    ) -> ClientResult<Vec<Substitute>> {
+        let equipment: Vec<&str> = equipment.iter().map(Equipment::as_str).collect();
+        let request = self
+            .http
+            .get(self.url(&format!("/v1/exercises/{}/substitutes", exercise_id)))
+            .query(&[("equipment", equipment.join(","))]);
+        self.execute(request).await
+    }
+
+    pub async fn create_exercise(
+        &self,
+        create_exercise: &CreateExercise,
+    ) -> ClientResult<Exercise> {
+        self.send(Method::POST, "/v1/exercises", Some(create_exercise))
+            .await
+    }
+
+    pub async fn create_exercises(
+        &self,
+        create_exercises: &[CreateExercise],
+    ) -> ClientResult<Vec<Exercise>> {
+        self.send(Method::POST, "/v1/exercises/bulk", Some(create_exercises))
+            .await
+    }
+
+    pub async fn add_exercise_to_training_day(
+        &self,
+        exercise_id: &Uuid,
+        day_id: &Uuid,
+    ) -> ClientResult<ExerciseToTrainingDay> {
+        self.send(
+            Method::POST,
+            &format!("/v1/exercises/{}/{}", exercise_id, day_id),
+            None::<&()>,
+        )
+        .await
+    }
+
+    pub async fn get_exercises_for_training_day(
+        &self,
+        day_id: &Uuid,
+    ) -> ClientResult<Vec<ExerciseWithLinkId>> {
+        self.send(
+            Method::GET,
+            &format!("/v1/exercises/{}", day_id),
+            None::<&()>,
+        )
+        .await
+    }
+
+    pub async fn delete_exercise_from_training_day(&self, link_id: &Uuid) -> ClientResult<Uuid> {
+        self.send(
+            Method::DELETE,
+            &format!("/v1/exercises/{}", link_id),
+            None::<&()>,
+        )
+        .await
+    }
+
+    // SESSIONS
+    pub async fn create_session(
+        &self,
+        day_id: &Uuid,
+    ) -> ClientResult<SessionWithExercisePerformance> {
+        self.send(
+            Method::POST,
+            &format!("/v1/session/{}", day_id),
+            None::<&()>,
+        )
+        .await
+    }
+
+    pub async fn get_sessions_by_day_id(&self, day_id: &Uuid) -> ClientResult<Vec<Session>> {
+        self.send(
+            Method::GET,
+            &format!("/v1/session/{}/all", day_id),
+            None::<&()>,
+        )
+        .await
+    }
+
+    pub async fn get_sessions_with_exercises_by_day_id(
+        &self,
+        day_id: &Uuid,
+    ) -> ClientResult<Vec<SessionWithExercises>> {
+        self.send(Method::GET, &format!("/v1/session/{}", day_id), None::<&()>)
+            .await
+    }
+
+    pub async fn get_session_in_progress(
+        &self,
+        routine_id: &Uuid,
+    ) -> ClientResult<Option<SessionWithExercisePerformance>> {
+        self.send(
+            Method::GET,
+            &format!("/v1/session/in_progress/{}", routine_id),
+            None::<&()>,
+        )
+        .await
+    }
+
+    pub async fn get_all_sessions_by_routine_id(
+        &self,
+        routine_id: &Uuid,
+    ) -> ClientResult<Vec<Session>> {
+        self.send(
+            Method::GET,
+            &format!("/v1/session/all/{}", routine_id),
+            None::<&()>,
+        )
+        .await
+    }
+
+    pub async fn add_set_performance_to_session(
+        &self,
+        session_id: &Uuid,
+        exercise_id: &Uuid,
+        set_performance: &SetPerformancePayload,
+    ) -> ClientResult<SetPerformance> {
+        self.send(
+            Method::POST,
+            &format!("/v1/session/{}/{}", session_id, exercise_id),
+            Some(set_performance),
+        )
+        .await
+    }
+
+    pub async fn remove_set_performance_from_session(
+        &self,
+        performance_id: &Uuid,
+    ) -> ClientResult<Uuid> {
+        self.send(
+            Method::DELETE,
+            &format!("/v1/session/{}", performance_id),
+            None::<&()>,
+        )
+        .await
+    }
+
+    pub async fn end_session(&self, session_id: &Uuid) -> ClientResult<Uuid> {
+        self.send(
+            Method::PUT,
+            &format!("/v1/session/end/{}", session_id),
+            None::<&()>,
+        )
+        .await
+    }
+
+    // DEBUG
+    pub async fn get_link_table_data(&self) -> ClientResult<Vec<ExerciseToTrainingDay>> {
+        self.send(Method::GET, "/v1/debug/link_table", None::<&()>)
+            .await
+    }
+
+    pub async fn clear_data(&self) -> ClientResult<()> {
+        let request = self.http.delete(self.url("/v1/debug/clear_data"));
+        let response = self.execute_raw(request).await?;
+        Self::check(response).await.map(|_| ())
+    }
+
+    // helpers
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    async fn send<T: DeserializeOwned, B: Serialize + ?Sized>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> ClientResult<T> {
+        let mut request = self.http.request(method, self.url(path));
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        self.execute(request).await
+    }
+
+    async fn execute<T: DeserializeOwned>(&self, request: RequestBuilder) -> ClientResult<T> {
+        let response = self.execute_raw(request).await?;
+        Self::decode(response).await
+    }
+
+    // Send with the bearer token, logging in again and retrying once if the token was rejected
+    async fn execute_raw(&self, request: RequestBuilder) -> ClientResult<Response> {
+        let retry = request.try_clone();
+        let response = self.authorize(request).await?.send().await?;
+
+        let has_credentials = self.credentials.read().unwrap().is_some();
+        match (response.status(), retry) {
+            (StatusCode::UNAUTHORIZED, Some(retry)) if has_credentials => {
+                self.refresh_token().await?;
+                Ok(self.authorize(retry).await?.send().await?)
+            }
+            _ => Ok(response),
+        }
+    }
+
+    async fn authorize(&self, request: RequestBuilder) -> ClientResult<RequestBuilder> {
+        let token = match self.token() {
+            Some(token) => token,
+            None => self.refresh_token().await?,
+        };
+        Ok(request.bearer_auth(token))
+    }
+
+    async fn check(response: Response) -> ClientResult<Response> {
+        let status = response.status();
+        if status.is_success() {
+            Ok(response)
+        } else {
+            let body = response.text().await.unwrap_or_default();
+            Err(ClientError::from_response(status, body))
+        }
+    }
+
+    async fn decode<T: DeserializeOwned>(response: Response) -> ClientResult<T> {
+        Ok(Self::check(response).await?.json::<T>().await?)
+    }
+}
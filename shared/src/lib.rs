@@ -1 +1,4 @@
 pub mod models;
+pub mod search;
+pub mod substitutes;
+pub mod utils;
@@ -1,5 +1,4 @@
-use std::cmp::Ordering;
-
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 // use sqlx::{Decode, Postgres};
 use uuid::Uuid;
@@ -7,6 +6,96 @@ use uuid::Uuid;
 #[derive(Serialize, Deserialize, Clone)]
 pub struct TokenClaims {
     pub token_id: uuid::Uuid,
+    // tokens issued before roles existed are athletes
+    #[serde(default)]
+    pub role: Role,
+    // unix seconds, tokens issued without one are already expired
+    #[serde(default)]
+    pub exp: i64,
+}
+
+// Stored as text on Users, new users are athletes
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Admin,
+    Coach,
+    #[default]
+    Athlete,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Coach => "coach",
+            Role::Athlete => "athlete",
+        }
+    }
+}
+
+impl TryFrom<String> for Role {
+    type Error = String;
+
+    fn try_from(role: String) -> Result<Self, Self::Error> {
+        match role.as_str() {
+            "admin" => Ok(Role::Admin),
+            "coach" => Ok(Role::Coach),
+            "athlete" => Ok(Role::Athlete),
+            _ => Err(format!("Unknown role {}", role)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct UpdateRole {
+    pub role: Role,
+}
+
+// Weights are stored in kilograms, this is what a user sees and types them in. Set weights are
+// answered rounded to the unit's plate increment, 0.25 kg or 0.5 lb.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum WeightUnit {
+    #[default]
+    Kg,
+    Lb,
+}
+
+impl WeightUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WeightUnit::Kg => "kg",
+            WeightUnit::Lb => "lb",
+        }
+    }
+}
+
+impl TryFrom<String> for WeightUnit {
+    type Error = String;
+
+    fn try_from(unit: String) -> Result<Self, Self::Error> {
+        match unit.as_str() {
+            "kg" => Ok(WeightUnit::Kg),
+            "lb" => Ok(WeightUnit::Lb),
+            _ => Err(format!("Unknown weight unit {}", unit)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct Preferences {
+    pub weight_unit: WeightUnit,
+}
+
+// Overrides the caller's preferred unit for one request
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct UnitQuery {
+    pub unit: Option<WeightUnit>,
 }
 
 #[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
@@ -19,8 +108,13 @@ pub struct CreateUser {
 #[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub struct UserNoPassword {
-    user_id: uuid::Uuid,
-    username: String,
+    pub user_id: uuid::Uuid,
+    pub username: String,
+    #[cfg_attr(feature = "backend", sqlx(try_from = "String"))]
+    pub role: Role,
+    #[cfg_attr(feature = "backend", sqlx(try_from = "String"))]
+    #[serde(default)]
+    pub weight_unit: WeightUnit,
 }
 
 #[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
@@ -37,6 +131,12 @@ pub struct User {
     pub user_id: uuid::Uuid,
     pub username: String,
     pub password: String,
+    #[cfg_attr(feature = "backend", sqlx(try_from = "String"))]
+    #[serde(default)]
+    pub role: Role,
+    #[cfg_attr(feature = "backend", sqlx(try_from = "String"))]
+    #[serde(default)]
+    pub weight_unit: WeightUnit,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
@@ -57,8 +157,19 @@ pub struct Routine {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub struct CreateRoutine {
     pub name: String,
+    #[serde(default)]
     pub description: String,
+    #[serde(default)]
     pub is_active: bool,
+    // owner, the caller when not given; coaches pass an athlete's id
+    #[serde(default)]
+    pub user_id: Option<Uuid>,
+}
+
+// Whose data a listing is for, the caller when not given; coaches pass an athlete's id
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct OwnerQuery {
+    pub user_id: Option<Uuid>,
 }
 
 #[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
@@ -78,20 +189,428 @@ pub struct CreateTrainingDay {
     pub day_name: String,
 }
 
+// How a set's weight turns into the load lifted. Stored as text on Exercises.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadType {
+    // the weight is the load, a barbell or a machine stack
+    #[default]
+    External,
+    // the lifter's bodyweight, the weight is ignored
+    Bodyweight,
+    // bodyweight plus the weight, e.g. a dip belt
+    BodyweightPlusLoad,
+    // bodyweight minus the weight, e.g. an assisted pull-up machine
+    Assisted,
+}
+
+impl LoadType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoadType::External => "external",
+            LoadType::Bodyweight => "bodyweight",
+            LoadType::BodyweightPlusLoad => "bodyweight_plus_load",
+            LoadType::Assisted => "assisted",
+        }
+    }
+}
+
+impl TryFrom<String> for LoadType {
+    type Error = String;
+
+    fn try_from(load_type: String) -> Result<Self, Self::Error> {
+        match load_type.as_str() {
+            "external" => Ok(LoadType::External),
+            "bodyweight" => Ok(LoadType::Bodyweight),
+            "bodyweight_plus_load" => Ok(LoadType::BodyweightPlusLoad),
+            "assisted" => Ok(LoadType::Assisted),
+            _ => Err(format!("Unknown load type {}", load_type)),
+        }
+    }
+}
+
+// What a set of an exercise records
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Reps,
+    Weight,
+    // seconds
+    Duration,
+    // meters
+    Distance,
+    Calories,
+    // average beats per minute
+    HeartRate,
+}
+
+impl Metric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Metric::Reps => "reps",
+            Metric::Weight => "weight",
+            Metric::Duration => "duration",
+            Metric::Distance => "distance",
+            Metric::Calories => "calories",
+            Metric::HeartRate => "heart_rate",
+        }
+    }
+}
+
+impl TryFrom<String> for Metric {
+    type Error = String;
+
+    fn try_from(metric: String) -> Result<Self, Self::Error> {
+        match metric.as_str() {
+            "reps" => Ok(Metric::Reps),
+            "weight" => Ok(Metric::Weight),
+            "duration" => Ok(Metric::Duration),
+            "distance" => Ok(Metric::Distance),
+            "calories" => Ok(Metric::Calories),
+            "heart_rate" => Ok(Metric::HeartRate),
+            _ => Err(format!("Unknown metric {}", metric)),
+        }
+    }
+}
+
+// The metrics an exercise's sets record. Stored as a text array on Exercises, lifts track reps
+// and weight.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
+pub struct TrackedMetrics(pub Vec<Metric>);
+
+impl TrackedMetrics {
+    pub fn tracks(&self, metric: Metric) -> bool {
+        self.0.contains(&metric)
+    }
+
+    pub fn as_strs(&self) -> Vec<&'static str> {
+        self.0.iter().map(Metric::as_str).collect()
+    }
+}
+
+impl Default for TrackedMetrics {
+    fn default() -> Self {
+        TrackedMetrics(vec![Metric::Reps, Metric::Weight])
+    }
+}
+
+impl TryFrom<Vec<String>> for TrackedMetrics {
+    type Error = String;
+
+    fn try_from(metrics: Vec<String>) -> Result<Self, Self::Error> {
+        metrics
+            .into_iter()
+            .map(Metric::try_from)
+            .collect::<Result<_, _>>()
+            .map(TrackedMetrics)
+    }
+}
+
+// What an exercise is done with. Stored as text on Exercises.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Equipment {
+    Barbell,
+    Dumbbell,
+    Kettlebell,
+    Cable,
+    Machine,
+    Band,
+    Bodyweight,
+    #[default]
+    Other,
+}
+
+impl Equipment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Equipment::Barbell => "barbell",
+            Equipment::Dumbbell => "dumbbell",
+            Equipment::Kettlebell => "kettlebell",
+            Equipment::Cable => "cable",
+            Equipment::Machine => "machine",
+            Equipment::Band => "band",
+            Equipment::Bodyweight => "bodyweight",
+            Equipment::Other => "other",
+        }
+    }
+}
+
+impl TryFrom<String> for Equipment {
+    type Error = String;
+
+    fn try_from(equipment: String) -> Result<Self, Self::Error> {
+        match equipment.as_str() {
+            "barbell" => Ok(Equipment::Barbell),
+            "dumbbell" => Ok(Equipment::Dumbbell),
+            "kettlebell" => Ok(Equipment::Kettlebell),
+            "cable" => Ok(Equipment::Cable),
+            "machine" => Ok(Equipment::Machine),
+            "band" => Ok(Equipment::Band),
+            "bodyweight" => Ok(Equipment::Bodyweight),
+            "other" => Ok(Equipment::Other),
+            _ => Err(format!("Unknown equipment {}", equipment)),
+        }
+    }
+}
+
+// The movement an exercise trains, for grouping and substitutes
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum MovementPattern {
+    Squat,
+    Hinge,
+    Lunge,
+    HorizontalPush,
+    VerticalPush,
+    HorizontalPull,
+    VerticalPull,
+    Carry,
+    Core,
+    Isolation,
+    Conditioning,
+    #[default]
+    Other,
+}
+
+impl MovementPattern {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MovementPattern::Squat => "squat",
+            MovementPattern::Hinge => "hinge",
+            MovementPattern::Lunge => "lunge",
+            MovementPattern::HorizontalPush => "horizontal_push",
+            MovementPattern::VerticalPush => "vertical_push",
+            MovementPattern::HorizontalPull => "horizontal_pull",
+            MovementPattern::VerticalPull => "vertical_pull",
+            MovementPattern::Carry => "carry",
+            MovementPattern::Core => "core",
+            MovementPattern::Isolation => "isolation",
+            MovementPattern::Conditioning => "conditioning",
+            MovementPattern::Other => "other",
+        }
+    }
+}
+
+impl TryFrom<String> for MovementPattern {
+    type Error = String;
+
+    fn try_from(movement_pattern: String) -> Result<Self, Self::Error> {
+        match movement_pattern.as_str() {
+            "squat" => Ok(MovementPattern::Squat),
+            "hinge" => Ok(MovementPattern::Hinge),
+            "lunge" => Ok(MovementPattern::Lunge),
+            "horizontal_push" => Ok(MovementPattern::HorizontalPush),
+            "vertical_push" => Ok(MovementPattern::VerticalPush),
+            "horizontal_pull" => Ok(MovementPattern::HorizontalPull),
+            "vertical_pull" => Ok(MovementPattern::VerticalPull),
+            "carry" => Ok(MovementPattern::Carry),
+            "core" => Ok(MovementPattern::Core),
+            "isolation" => Ok(MovementPattern::Isolation),
+            "conditioning" => Ok(MovementPattern::Conditioning),
+            "other" => Ok(MovementPattern::Other),
+            _ => Err(format!("Unknown movement pattern {}", movement_pattern)),
+        }
+    }
+}
+
+// Muscles an exercise targets
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Muscle {
+    Chest,
+    FrontDelts,
+    SideDelts,
+    RearDelts,
+    Biceps,
+    Triceps,
+    Forearms,
+    Lats,
+    UpperBack,
+    Traps,
+    LowerBack,
+    Abs,
+    Obliques,
+    Glutes,
+    Quads,
+    Hamstrings,
+    Adductors,
+    Abductors,
+    Calves,
+    Neck,
+}
+
+impl Muscle {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Muscle::Chest => "chest",
+            Muscle::FrontDelts => "front_delts",
+            Muscle::SideDelts => "side_delts",
+            Muscle::RearDelts => "rear_delts",
+            Muscle::Biceps => "biceps",
+            Muscle::Triceps => "triceps",
+            Muscle::Forearms => "forearms",
+            Muscle::Lats => "lats",
+            Muscle::UpperBack => "upper_back",
+            Muscle::Traps => "traps",
+            Muscle::LowerBack => "lower_back",
+            Muscle::Abs => "abs",
+            Muscle::Obliques => "obliques",
+            Muscle::Glutes => "glutes",
+            Muscle::Quads => "quads",
+            Muscle::Hamstrings => "hamstrings",
+            Muscle::Adductors => "adductors",
+            Muscle::Abductors => "abductors",
+            Muscle::Calves => "calves",
+            Muscle::Neck => "neck",
+        }
+    }
+}
+
+impl TryFrom<String> for Muscle {
+    type Error = String;
+
+    fn try_from(muscle: String) -> Result<Self, Self::Error> {
+        match muscle.as_str() {
+            "chest" => Ok(Muscle::Chest),
+            "front_delts" => Ok(Muscle::FrontDelts),
+            "side_delts" => Ok(Muscle::SideDelts),
+            "rear_delts" => Ok(Muscle::RearDelts),
+            "biceps" => Ok(Muscle::Biceps),
+            "triceps" => Ok(Muscle::Triceps),
+            "forearms" => Ok(Muscle::Forearms),
+            "lats" => Ok(Muscle::Lats),
+            "upper_back" => Ok(Muscle::UpperBack),
+            "traps" => Ok(Muscle::Traps),
+            "lower_back" => Ok(Muscle::LowerBack),
+            "abs" => Ok(Muscle::Abs),
+            "obliques" => Ok(Muscle::Obliques),
+            "glutes" => Ok(Muscle::Glutes),
+            "quads" => Ok(Muscle::Quads),
+            "hamstrings" => Ok(Muscle::Hamstrings),
+            "adductors" => Ok(Muscle::Adductors),
+            "abductors" => Ok(Muscle::Abductors),
+            "calves" => Ok(Muscle::Calves),
+            "neck" => Ok(Muscle::Neck),
+            _ => Err(format!("Unknown muscle {}", muscle)),
+        }
+    }
+}
+
+// Muscles stored as a text array on Exercises
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[serde(transparent)]
+pub struct Muscles(pub Vec<Muscle>);
+
+impl Muscles {
+    pub fn as_strs(&self) -> Vec<&'static str> {
+        self.0.iter().map(Muscle::as_str).collect()
+    }
+}
+
+impl TryFrom<Vec<String>> for Muscles {
+    type Error = String;
+
+    fn try_from(muscles: Vec<String>) -> Result<Self, Self::Error> {
+        muscles
+            .into_iter()
+            .map(Muscle::try_from)
+            .collect::<Result<_, _>>()
+            .map(Muscles)
+    }
+}
+
 #[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub struct Exercise {
     pub exercise_id: uuid::Uuid, // we will be using uuids as ids
     pub exercise_name: String,
     pub exercise_description: String,
+    #[cfg_attr(feature = "backend", sqlx(try_from = "String"))]
+    #[serde(default)]
+    pub load_type: LoadType,
+    #[cfg_attr(feature = "backend", sqlx(try_from = "Vec<String>"))]
+    #[serde(default)]
+    pub tracked_metrics: TrackedMetrics,
+    #[cfg_attr(feature = "backend", sqlx(try_from = "String"))]
+    #[serde(default)]
+    pub equipment: Equipment,
+    #[cfg_attr(feature = "backend", sqlx(try_from = "String"))]
+    #[serde(default)]
+    pub movement_pattern: MovementPattern,
+    // one side at a time, like a split squat or a one-arm row
+    #[serde(default)]
+    pub unilateral: bool,
+    #[cfg_attr(feature = "backend", sqlx(try_from = "Vec<String>"))]
+    #[serde(default)]
+    pub primary_muscles: Muscles,
+    #[cfg_attr(feature = "backend", sqlx(try_from = "Vec<String>"))]
+    #[serde(default)]
+    pub secondary_muscles: Muscles,
+    // other names it goes by, "RDL" for a Romanian deadlift
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    // part of the library that ships with the server, read-only
+    #[serde(default)]
+    pub builtin: bool,
+    // the user who added it, none for library exercises
+    #[serde(default)]
+    pub owner_id: Option<uuid::Uuid>,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
+// Every filter given has to match. The name is matched fuzzily, an empty one matches every
+// exercise.
 #[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub struct SearchQuery {
+    // matched against the name and the aliases
+    #[serde(default)]
     pub name: String,
+    pub equipment: Option<Equipment>,
+    pub movement_pattern: Option<MovementPattern>,
+    // primary or secondary
+    pub muscle: Option<Muscle>,
+    pub unilateral: Option<bool>,
+    pub limit: Option<i64>,
+}
+
+// An exercise matching a search, best first. See `crate::search` for how scores are computed.
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ExerciseSearchHit {
+    #[cfg_attr(feature = "backend", sqlx(flatten))]
+    #[serde(flatten)]
+    pub exercise: Exercise,
+    pub score: f32,
+    // logged by the searching user lately, these rank first
+    pub recently_used: bool,
+}
+
+// `equipment` is what's at hand, comma-separated like "dumbbell,cable". Every kind when left out.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct SubstituteQuery {
+    pub equipment: Option<String>,
+    pub limit: Option<i64>,
+}
+
+// A comparable exercise to do instead, best first. See `shared::substitutes`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Substitute {
+    #[serde(flatten)]
+    pub exercise: Exercise,
+    pub score: f32,
+    pub same_pattern: bool,
+    // the primary muscles both work
+    pub shared_muscles: Muscles,
 }
 
 #[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
@@ -99,6 +618,62 @@ pub struct SearchQuery {
 pub struct CreateExercise {
     pub exercise_name: String,
     pub exercise_description: String,
+    #[serde(default)]
+    pub load_type: LoadType,
+    #[serde(default)]
+    pub tracked_metrics: TrackedMetrics,
+    #[serde(default)]
+    pub equipment: Equipment,
+    #[serde(default)]
+    pub movement_pattern: MovementPattern,
+    #[serde(default)]
+    pub unilateral: bool,
+    #[serde(default)]
+    pub primary_muscles: Muscles,
+    #[serde(default)]
+    pub secondary_muscles: Muscles,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+// Folds duplicates into one exercise: their routine links, sets, records and comments move to the
+// target, their names become its aliases and they are deleted
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct MergeExercises {
+    pub source_ids: Vec<Uuid>,
+    pub target_id: Uuid,
+    // report what would change without writing it
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+// A moved set that had to take a new number, the target already used its number in the session
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct RenumberedSet {
+    pub performance_id: Uuid,
+    pub session_id: Uuid,
+    pub from_set_number: i16,
+    pub to_set_number: i16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct MergeReport {
+    pub target_id: Uuid,
+    pub dry_run: bool,
+    // the names of the merged exercises
+    pub merged: Vec<String>,
+    pub aliases_added: Vec<String>,
+    // the names that weren't kept as aliases, since the caller can't edit the target (a library
+    // exercise). Search won't find the target by them.
+    pub aliases_dropped: Vec<String>,
+    pub links: u64,
+    // links of days that already had the target, their notes appended to the link kept
+    pub duplicate_links: u64,
+    pub sets: u64,
+    pub renumbered_sets: Vec<RenumberedSet>,
+    pub personal_records: u64,
+    pub comments: u64,
 }
 
 #[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
@@ -107,6 +682,8 @@ pub struct ExerciseToTrainingDay {
     pub link_id: uuid::Uuid,
     pub exercise_id: uuid::Uuid,
     pub day_id: uuid::Uuid,
+    // standing note for the exercise on this day, e.g. "use the narrow bar"
+    pub notes: Option<String>,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
@@ -118,6 +695,14 @@ pub struct ExerciseWithLinkId {
     pub link_id: uuid::Uuid,
     pub exercise_name: String,
     pub exercise_description: String,
+    #[cfg_attr(feature = "backend", sqlx(try_from = "String"))]
+    #[serde(default)]
+    pub load_type: LoadType,
+    #[cfg_attr(feature = "backend", sqlx(try_from = "Vec<String>"))]
+    #[serde(default)]
+    pub tracked_metrics: TrackedMetrics,
+    // the link's notes
+    pub notes: Option<String>,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
@@ -143,6 +728,11 @@ pub struct TrainingDayWithExercisesQuery {
     pub day_name: String,
     pub exercise_name: Option<String>, // Make exercise_name optional
     pub exercise_description: Option<String>, // Make exercise_description optional
+    #[cfg_attr(feature = "backend", sqlx(try_from = "String"))]
+    pub load_type: LoadType,
+    #[cfg_attr(feature = "backend", sqlx(try_from = "Vec<String>"))]
+    pub tracked_metrics: TrackedMetrics,
+    pub notes: Option<String>,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
@@ -154,6 +744,7 @@ pub struct Session {
     pub day_id: uuid::Uuid,
     pub day_name: String,
     pub in_progress: bool,
+    pub notes: Option<String>,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
@@ -165,6 +756,7 @@ pub struct SessionWithExercises {
     pub day_id: uuid::Uuid,
     pub day_name: String,
     pub in_progress: bool,
+    pub notes: Option<String>,
     pub exercises: Vec<ExerciseWithLinkId>,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
@@ -176,7 +768,13 @@ pub struct SessionPerformance {
     pub session_id: uuid::Uuid,
     pub exercise_id: uuid::Uuid,
     pub exercise_name: String,
+    // which of the sets' fields are filled in
+    #[serde(default)]
+    pub tracked_metrics: TrackedMetrics,
     pub sets: Vec<SetPerformance>,
+    // the latest set note on this exercise from an earlier session, as a reminder
+    #[serde(default)]
+    pub previous_note: Option<String>,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
@@ -188,7 +786,9 @@ impl SessionPerformance {
             session_id,
             exercise_id,
             exercise_name,
+            tracked_metrics: TrackedMetrics::default(),
             sets: Vec::new(), // Initialize sets vector as empty
+            previous_note: None,
             created_at: None,
             updated_at: None,
         }
@@ -196,40 +796,57 @@ impl SessionPerformance {
 }
 
 #[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, PartialOrd, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub struct SetPerformance {
     pub performance_id: uuid::Uuid,
-    pub weight: f32,
+    // the bar, or what is added to or taken off bodyweight, see `LoadType`
+    pub weight: Decimal,
+    // what was lifted, the load e1RM, PRs and volume use
+    #[serde(default)]
+    pub effective_load: Decimal,
     pub reps: i16,
     pub set_number: i16,
     pub rir: Option<i16>,
+    // only the metrics the exercise tracks, see `TrackedMetrics`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_seconds: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distance_meters: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calories: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heart_rate: Option<i16>,
+    pub notes: Option<String>,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
-impl Eq for SetPerformance {}
-
-impl Ord for SetPerformance {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.set_number.cmp(&other.set_number)
-    }
-}
-
 #[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, PartialOrd, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub struct SetPerformancePayload {
-    pub weight: f32,
+    // the bar, or what is added to or taken off bodyweight, see `LoadType`. Left out, like reps,
+    // for exercises that don't track it.
+    #[serde(default)]
+    pub weight: Decimal,
+    #[serde(default)]
     pub reps: i16,
     pub set_number: i16,
     pub rir: Option<i16>,
-}
-
-impl Eq for SetPerformancePayload {}
-
-impl Ord for SetPerformancePayload {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.set_number.cmp(&other.set_number)
-    }
+    // required for the exercises that track them, refused for the others
+    #[serde(default)]
+    pub duration_seconds: Option<i32>,
+    #[serde(default)]
+    pub distance_meters: Option<Decimal>,
+    #[serde(default)]
+    pub calories: Option<i32>,
+    #[serde(default)]
+    pub heart_rate: Option<i16>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    // the unit `weight` is in, the user's preference when not given
+    #[cfg_attr(feature = "backend", sqlx(skip))]
+    #[serde(default)]
+    pub unit: Option<WeightUnit>,
 }
 
 #[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
@@ -239,8 +856,12 @@ pub struct SessionWithExercisePerformance {
     pub day_id: Uuid,
     pub day_name: String,
     pub in_progress: bool,
+    pub notes: Option<String>,
     pub exercises: Vec<ExerciseWithLinkId>,
     pub performance: Vec<SessionPerformance>,
+    // every comment on the session, oldest first
+    #[serde(default)]
+    pub comments: Vec<Comment>,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
@@ -252,10 +873,755 @@ pub struct SessionsWithExercisesQuery {
     pub day_id: Uuid,
     pub day_name: String,
     pub in_progress: bool,
+    pub notes: Option<String>,
     pub exercise_id: Option<Uuid>,
     pub exercise_name: String,
     pub exercise_description: String,
+    #[cfg_attr(feature = "backend", sqlx(try_from = "String"))]
+    pub load_type: LoadType,
+    #[cfg_attr(feature = "backend", sqlx(try_from = "Vec<String>"))]
+    pub tracked_metrics: TrackedMetrics,
+    pub link_notes: Option<String>,
     pub link_id: Uuid,
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
+
+// PATCH payloads, only the fields that are present get written
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct PatchRoutine {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub is_active: Option<bool>,
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct PatchTrainingDay {
+    pub day_name: Option<String>,
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct PatchExercise {
+    pub exercise_name: Option<String>,
+    pub exercise_description: Option<String>,
+    pub load_type: Option<LoadType>,
+    pub tracked_metrics: Option<TrackedMetrics>,
+    pub equipment: Option<Equipment>,
+    pub movement_pattern: Option<MovementPattern>,
+    pub unilateral: Option<bool>,
+    // lists replace the stored ones
+    pub primary_muscles: Option<Muscles>,
+    pub secondary_muscles: Option<Muscles>,
+    pub aliases: Option<Vec<String>>,
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct PatchExerciseToTrainingDay {
+    pub exercise_id: Option<uuid::Uuid>,
+    pub day_id: Option<uuid::Uuid>,
+    // an empty string clears the notes
+    pub notes: Option<String>,
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct PatchSetPerformance {
+    pub weight: Option<Decimal>,
+    pub reps: Option<i16>,
+    pub set_number: Option<i16>,
+    pub rir: Option<i16>,
+    #[serde(default)]
+    pub duration_seconds: Option<i32>,
+    #[serde(default)]
+    pub distance_meters: Option<Decimal>,
+    #[serde(default)]
+    pub calories: Option<i32>,
+    #[serde(default)]
+    pub heart_rate: Option<i16>,
+    // an empty string clears the notes
+    pub notes: Option<String>,
+    // the unit `weight` is in, the user's preference when not given
+    #[cfg_attr(feature = "backend", sqlx(skip))]
+    #[serde(default)]
+    pub unit: Option<WeightUnit>,
+}
+
+// Offline sync
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncEntity {
+    Routine,
+    Session,
+    SetPerformance,
+}
+
+impl SyncEntity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncEntity::Routine => "routine",
+            SyncEntity::Session => "session",
+            SyncEntity::SetPerformance => "set_performance",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncOperation {
+    Upsert,
+    Delete,
+}
+
+// One offline edit. `entity_id` is generated on the client for new rows and
+// `fields` only carries the fields that were changed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SyncMutation {
+    pub mutation_id: Uuid,
+    pub entity: SyncEntity,
+    pub entity_id: Uuid,
+    pub operation: SyncOperation,
+    #[serde(default)]
+    pub fields: serde_json::Map<String, serde_json::Value>,
+    pub client_timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SyncPushRequest {
+    pub mutations: Vec<SyncMutation>,
+}
+
+// A field (or `_deleted` for deletes) where a newer write already on the server won
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SyncConflict {
+    pub mutation_id: Uuid,
+    pub entity: SyncEntity,
+    pub entity_id: Uuid,
+    pub field: String,
+    pub client_timestamp: chrono::DateTime<chrono::Utc>,
+    pub server_timestamp: chrono::DateTime<chrono::Utc>,
+    pub server_value: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SyncRejection {
+    pub mutation_id: Uuid,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SyncPushResponse {
+    pub applied: Vec<Uuid>,
+    pub conflicts: Vec<SyncConflict>,
+    pub rejected: Vec<SyncRejection>,
+    pub cursor: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct SyncPullQuery {
+    pub cursor: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SyncChange {
+    pub change_id: i64,
+    pub entity: String,
+    pub entity_id: Uuid,
+    pub operation: String,
+    // the row after the change, null for deletes
+    pub data: Option<serde_json::Value>,
+    pub changed_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SyncPullResponse {
+    pub changes: Vec<SyncChange>,
+    pub cursor: i64,
+    pub has_more: bool,
+}
+
+// Row shapes the sync protocol reads and writes
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct SyncRoutine {
+    pub routine_id: Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub is_active: bool,
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct SyncSession {
+    pub session_id: Uuid,
+    pub day_id: Uuid,
+    // filled in from the training day when left empty
+    #[serde(default)]
+    pub day_name: String,
+    #[serde(default)]
+    pub in_progress: bool,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// Sync carries weights in kilograms, as stored
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct SyncSetPerformance {
+    pub performance_id: Uuid,
+    pub session_id: Uuid,
+    pub exercise_id: Uuid,
+    pub set_number: i16,
+    pub weight: Decimal,
+    pub reps: i16,
+    pub rir: Option<i16>,
+    #[serde(default)]
+    pub duration_seconds: Option<i32>,
+    #[serde(default)]
+    pub distance_meters: Option<Decimal>,
+    #[serde(default)]
+    pub calories: Option<i32>,
+    #[serde(default)]
+    pub heart_rate: Option<i16>,
+}
+
+// Live session updates, pushed to every socket subscribed to the session
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionEvent {
+    SetAdded {
+        session_id: Uuid,
+        exercise_id: Uuid,
+        set: SetPerformance,
+    },
+    SetUpdated {
+        session_id: Uuid,
+        exercise_id: Uuid,
+        set: SetPerformance,
+    },
+    SetRemoved {
+        session_id: Uuid,
+        exercise_id: Uuid,
+        performance_id: Uuid,
+    },
+    SessionEnded {
+        session_id: Uuid,
+    },
+    // Sets of `exercise_id` are logged as `substitute` for the rest of this session, the
+    // routine stays as it is
+    ExerciseSwapped {
+        session_id: Uuid,
+        exercise_id: Uuid,
+        substitute: Exercise,
+    },
+    // only sent to the socket that asked
+    Substitutes {
+        exercise_id: Uuid,
+        substitutes: Vec<Substitute>,
+    },
+    // only sent to the socket whose command failed
+    Error {
+        message: String,
+    },
+}
+
+// Set writes and swaps a client can send over the session socket
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionCommand {
+    AddSet {
+        exercise_id: Uuid,
+        set: SetPerformancePayload,
+    },
+    UpdateSet {
+        exercise_id: Uuid,
+        performance_id: Uuid,
+        patch: PatchSetPerformance,
+    },
+    RemoveSet {
+        exercise_id: Uuid,
+        performance_id: Uuid,
+    },
+    // Comparable exercises for what's at hand, every kind of equipment when empty
+    SuggestSubstitutes {
+        exercise_id: Uuid,
+        #[serde(default)]
+        equipment: Vec<Equipment>,
+        limit: Option<usize>,
+    },
+    // A one-off swap for when the equipment is taken
+    SwapExercise {
+        exercise_id: Uuid,
+        substitute_id: Uuid,
+    },
+}
+
+// Best estimated 1RM for an exercise at the time the set was logged
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct PersonalRecord {
+    pub record_id: Uuid,
+    pub exercise_id: Uuid,
+    pub session_id: Uuid,
+    pub performance_id: Uuid,
+    // the set's effective load, bodyweight included
+    pub weight: Decimal,
+    pub reps: i16,
+    pub estimated_one_rep_max: Decimal,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// A user's training activity, streamed to dashboards over SSE. Weights are in kilograms.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ActivityEvent {
+    SessionStarted {
+        session_id: Uuid,
+        day_id: Uuid,
+        day_name: String,
+    },
+    SessionCompleted {
+        session_id: Uuid,
+    },
+    // closed by the server after being left in progress for too long
+    SessionAutoClosed {
+        session_id: Uuid,
+        day_id: Uuid,
+    },
+    PersonalRecord {
+        record: PersonalRecord,
+    },
+    RoutineActivated {
+        routine_id: Uuid,
+        name: String,
+    },
+    CommentAdded {
+        comment: Comment,
+    },
+    CommentUpdated {
+        comment: Comment,
+    },
+    CommentDeleted {
+        comment_id: Uuid,
+        session_id: Uuid,
+    },
+}
+
+impl ActivityEvent {
+    pub const KINDS: [&'static str; 8] = [
+        "session_started",
+        "session_completed",
+        "session_auto_closed",
+        "personal_record",
+        "routine_activated",
+        "comment_added",
+        "comment_updated",
+        "comment_deleted",
+    ];
+
+    // Same as the serialized `type` tag, used as the SSE event name
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ActivityEvent::SessionStarted { .. } => "session_started",
+            ActivityEvent::SessionCompleted { .. } => "session_completed",
+            ActivityEvent::SessionAutoClosed { .. } => "session_auto_closed",
+            ActivityEvent::PersonalRecord { .. } => "personal_record",
+            ActivityEvent::RoutineActivated { .. } => "routine_activated",
+            ActivityEvent::CommentAdded { .. } => "comment_added",
+            ActivityEvent::CommentUpdated { .. } => "comment_updated",
+            ActivityEvent::CommentDeleted { .. } => "comment_deleted",
+        }
+    }
+}
+
+// Webhooks
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct CreateWebhook {
+    pub url: String,
+    // activity event types to deliver, empty means all of them
+    #[serde(default)]
+    pub events: Vec<String>,
+    // generated when not given
+    pub secret: Option<String>,
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Webhook {
+    pub webhook_id: Uuid,
+    pub url: String,
+    pub events: Vec<String>,
+    pub is_active: bool,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// Only returned when the webhook is created, the secret can't be read back later
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WebhookDelivery {
+    pub delivery_id: Uuid,
+    pub webhook_id: Uuid,
+    pub event_type: String,
+    // the exact body that is POSTed (and signed)
+    pub payload: serde_json::Value,
+    // pending, succeeded or failed
+    pub status: String,
+    pub attempt_count: i32,
+    pub response_status: Option<i16>,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub delivered_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// History export
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct HistoryExportQuery {
+    // inclusive session dates
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    pub routine_id: Option<Uuid>,
+    // whose history, the caller when not given
+    pub user_id: Option<Uuid>,
+    // the caller's preference when not given
+    pub unit: Option<WeightUnit>,
+}
+
+// One logged set, a row of the CSV export
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct HistoryExportRow {
+    pub session_date: chrono::DateTime<chrono::Utc>,
+    pub routine: String,
+    pub training_day: String,
+    pub exercise: String,
+    pub set_number: i16,
+    pub set_type: String,
+    pub weight: Decimal,
+    pub effective_load: Decimal,
+    pub reps: i16,
+    pub rir: Option<i16>,
+    pub duration_seconds: Option<i32>,
+    pub distance_meters: Option<Decimal>,
+    pub calories: Option<i32>,
+    pub heart_rate: Option<i16>,
+    // filled in from effective load and reps, not stored
+    #[cfg_attr(feature = "backend", sqlx(default))]
+    pub estimated_one_rep_max: Decimal,
+}
+
+// History import
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportSource {
+    #[default]
+    Strong,
+    Hevy,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct ImportRequest {
+    pub source: ImportSource,
+    // the exported file, as is
+    pub csv: String,
+    // exercise names from the file mapped onto our exercises, for names that don't match
+    #[serde(default)]
+    pub aliases: std::collections::HashMap<String, Uuid>,
+    #[serde(default)]
+    pub dry_run: bool,
+    // what the file's weights are in when it doesn't say, the caller's preference when not given
+    #[serde(default)]
+    pub unit: Option<WeightUnit>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct UnmatchedExercise {
+    pub name: String,
+    pub rows: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct ImportRowError {
+    // 1-based line in the file, the header is line 1
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct ImportReport {
+    pub source: ImportSource,
+    pub dry_run: bool,
+    // false when nothing was written, either for a dry run or because of the problems below
+    pub imported: bool,
+    pub sessions: usize,
+    pub sets: usize,
+    // rows without reps, e.g. cardio, which can't be stored yet
+    pub skipped_rows: usize,
+    pub unmatched_exercises: Vec<UnmatchedExercise>,
+    pub errors: Vec<ImportRowError>,
+}
+
+// Account export, everything a user owns as one JSON archive
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct AccountExport {
+    pub exported_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub user: UserNoPassword,
+    pub routines: Vec<Routine>,
+    pub training_days: Vec<TrainingDay>,
+    pub exercise_links: Vec<ExerciseToTrainingDay>,
+    // imported sessions have no training day, their day_id is the nil uuid
+    pub sessions: Vec<Session>,
+    // sets grouped per session and exercise
+    pub performance: Vec<SessionPerformance>,
+    pub personal_records: Vec<PersonalRecord>,
+    pub webhooks: Vec<Webhook>,
+    // as coach and as athlete
+    pub coaching: Vec<CoachingLink>,
+    // written by the user or on their sessions
+    pub comments: Vec<Comment>,
+    pub body_measurements: Vec<BodyMeasurement>,
+}
+
+// Coaching
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CoachPermissions {
+    #[serde(default)]
+    pub can_read_history: bool,
+    #[serde(default)]
+    pub can_edit_routines: bool,
+    #[serde(default)]
+    pub can_comment: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct CreateCoachInvitation {
+    pub athlete_username: String,
+    #[serde(flatten)]
+    pub permissions: CoachPermissions,
+}
+
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct CoachingLink {
+    pub link_id: Uuid,
+    pub coach_id: Uuid,
+    pub coach_username: String,
+    pub athlete_id: Uuid,
+    pub athlete_username: String,
+    // pending until the athlete accepts, then accepted
+    pub status: String,
+    pub can_read_history: bool,
+    pub can_edit_routines: bool,
+    pub can_comment: bool,
+    pub accepted_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// One athlete on a coach's dashboard, empty where the coach lacks the permission
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CoachDashboardEntry {
+    pub athlete_id: Uuid,
+    pub athlete_username: String,
+    pub permissions: CoachPermissions,
+    pub active_routine: Option<Routine>,
+    pub last_session_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub recent_personal_records: Vec<PersonalRecord>,
+}
+
+// Comments
+// On a session, narrowed to one exercise in it or one set when those are set.
+// Replies carry the comment they answer in `parent_id`.
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Comment {
+    pub comment_id: Uuid,
+    pub session_id: Uuid,
+    pub exercise_id: Option<Uuid>,
+    pub performance_id: Option<Uuid>,
+    pub parent_id: Option<Uuid>,
+    pub author_id: Uuid,
+    pub author_username: String,
+    pub body: String,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct CreateComment {
+    pub body: String,
+    #[serde(default)]
+    pub exercise_id: Option<Uuid>,
+    // the exercise is taken from the set when not given
+    #[serde(default)]
+    pub performance_id: Option<Uuid>,
+    #[serde(default)]
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct UpdateComment {
+    pub body: String,
+}
+
+// Notes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct SessionNotes {
+    // None or an empty string clears the notes
+    pub notes: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct NoteSearchQuery {
+    // web search syntax: words, "quoted phrases", -excluded, or
+    pub q: String,
+    pub limit: Option<i64>,
+}
+
+// A note matching a search, best match first
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct NoteSearchHit {
+    // session, set or exercise_link
+    pub source: String,
+    // the session_id, performance_id or link_id, depending on `source`
+    pub id: Uuid,
+    pub session_id: Option<Uuid>,
+    pub day_id: Option<Uuid>,
+    pub exercise_id: Option<Uuid>,
+    pub notes: String,
+    pub rank: f32,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// Body measurements, one row per user and day. Weights are stored in kilograms, tape
+// measurements in centimeters.
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct BodyMeasurement {
+    pub measurement_id: Uuid,
+    pub measured_on: chrono::NaiveDate,
+    pub bodyweight: Option<Decimal>,
+    // an estimate, percent of bodyweight
+    pub body_fat_percent: Option<Decimal>,
+    pub waist_cm: Option<Decimal>,
+    pub chest_cm: Option<Decimal>,
+    pub arms_cm: Option<Decimal>,
+    pub thighs_cm: Option<Decimal>,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// Logging a day that already has a measurement fills in the given fields
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct CreateBodyMeasurement {
+    // defaults to today
+    pub measured_on: Option<chrono::NaiveDate>,
+    pub bodyweight: Option<Decimal>,
+    pub body_fat_percent: Option<Decimal>,
+    pub waist_cm: Option<Decimal>,
+    pub chest_cm: Option<Decimal>,
+    pub arms_cm: Option<Decimal>,
+    pub thighs_cm: Option<Decimal>,
+    // the unit bodyweight is given in, the caller's preferred unit if missing
+    pub unit: Option<WeightUnit>,
+}
+
+// Fields left out are kept, measurements can't be cleared one by one
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct PatchBodyMeasurement {
+    pub measured_on: Option<chrono::NaiveDate>,
+    pub bodyweight: Option<Decimal>,
+    pub body_fat_percent: Option<Decimal>,
+    pub waist_cm: Option<Decimal>,
+    pub chest_cm: Option<Decimal>,
+    pub arms_cm: Option<Decimal>,
+    pub thighs_cm: Option<Decimal>,
+    // the unit bodyweight is given in, the caller's preferred unit if missing
+    pub unit: Option<WeightUnit>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct BodyMeasurementsQuery {
+    // inclusive
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    pub unit: Option<WeightUnit>,
+}
+
+// A body measurement charted over time
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BodyMetric {
+    #[default]
+    Bodyweight,
+    BodyFatPercent,
+    WaistCm,
+    ChestCm,
+    ArmsCm,
+    ThighsCm,
+}
+
+impl BodyMetric {
+    // the BodyMeasurements column it is stored in
+    pub fn column(&self) -> &'static str {
+        match self {
+            BodyMetric::Bodyweight => "bodyweight",
+            BodyMetric::BodyFatPercent => "body_fat_percent",
+            BodyMetric::WaistCm => "waist_cm",
+            BodyMetric::ChestCm => "chest_cm",
+            BodyMetric::ArmsCm => "arms_cm",
+            BodyMetric::ThighsCm => "thighs_cm",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct BodyTrendQuery {
+    #[serde(default)]
+    pub metric: BodyMetric,
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    // days the moving average spans, a week if not given
+    pub window: Option<i64>,
+    pub unit: Option<WeightUnit>,
+}
+
+// One day of a body measurement series, oldest first
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct BodyTrendPoint {
+    pub measured_on: chrono::NaiveDate,
+    pub value: Decimal,
+    // mean of the values logged in the window ending on this day
+    #[cfg_attr(feature = "backend", sqlx(default))]
+    pub moving_average: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct BodyweightAsOfQuery {
+    // defaults to today
+    pub date: Option<chrono::NaiveDate>,
+    pub unit: Option<WeightUnit>,
+}
+
+// The latest bodyweight logged on or before `date`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct BodyweightAsOf {
+    pub date: chrono::NaiveDate,
+    pub measured_on: chrono::NaiveDate,
+    pub bodyweight: Decimal,
+}
@@ -0,0 +1,272 @@
+//! Fuzzy exercise search. Postgres ranks with pg_trgm, `search_text_score` in the schema mirrors
+//! `text_score` here, so results can be reproduced without a database.
+
+use std::collections::HashSet;
+
+use uuid::Uuid;
+
+use crate::models::{Exercise, ExerciseSearchHit, SearchQuery};
+
+/// Below this an exercise doesn't match, pg_trgm's default similarity threshold.
+pub const MIN_SCORE: f32 = 0.3;
+/// Added when the name or alias starts with the query.
+pub const PREFIX_BOOST: f32 = 0.25;
+/// Added when the name or alias is the query.
+pub const EXACT_BOOST: f32 = 0.25;
+/// Added for exercises the user logged in the last `RECENT_DAYS`, enough to rank them first.
+pub const RECENT_BOOST: f32 = 2.0;
+pub const RECENT_DAYS: i32 = 30;
+
+// Shorthand gyms use for equipment
+const ABBREVIATIONS: [(&str, &str); 3] =
+    [("db", "dumbbell"), ("bb", "barbell"), ("kb", "kettlebell")];
+
+/// Lowercase words separated by single spaces, punctuation dropped. The schema's
+/// `search_normalize` does the same.
+pub fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The query with equipment shorthand spelled out, "db row" is searched as "dumbbell row" too.
+pub fn expand_abbreviations(query: &str) -> String {
+    normalize(query)
+        .split(' ')
+        .map(|word| {
+            ABBREVIATIONS
+                .iter()
+                .find(|(short, _)| *short == word)
+                .map_or(word, |(_, long)| long)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// pg_trgm's trigrams: every word padded with two spaces in front and one behind
+fn trigrams(text: &str) -> HashSet<[char; 3]> {
+    let mut trigrams = HashSet::new();
+    for word in normalize(text).split(' ').filter(|word| !word.is_empty()) {
+        let padded: Vec<char> = format!("  {} ", word).chars().collect();
+        trigrams.extend(
+            padded
+                .windows(3)
+                .map(|window| [window[0], window[1], window[2]]),
+        );
+    }
+    trigrams
+}
+
+/// pg_trgm's `similarity`: shared trigrams over all trigrams of either text.
+pub fn similarity(a: &str, b: &str) -> f32 {
+    let (a, b) = (trigrams(a), trigrams(b));
+    let shared = a.intersection(&b).count();
+    let all = a.len() + b.len() - shared;
+    if all == 0 {
+        return 0.0;
+    }
+    shared as f32 / all as f32
+}
+
+/// How well a name or alias matches a normalized query. The spaces are also compared
+/// squeezed out, so "benchpres" still finds "Bench Press".
+pub fn text_score(text: &str, query: &str) -> f32 {
+    if query.is_empty() {
+        return 0.0;
+    }
+    let text = normalize(text);
+    let squeezed = similarity(&text.replace(' ', ""), &query.replace(' ', ""));
+    let mut score = similarity(&text, query).max(squeezed);
+    if text.starts_with(query) {
+        score += PREFIX_BOOST;
+    }
+    if text == query {
+        score += EXACT_BOOST;
+    }
+    score
+}
+
+/// The best score of the exercise's name and aliases against the query as typed and with its
+/// abbreviations expanded.
+pub fn match_score(exercise: &Exercise, query: &str) -> f32 {
+    let queries = [normalize(query), expand_abbreviations(query)];
+    std::iter::once(&exercise.exercise_name)
+        .chain(&exercise.aliases)
+        .flat_map(|text| queries.iter().map(move |query| text_score(text, query)))
+        .fold(0.0, f32::max)
+}
+
+impl SearchQuery {
+    /// Whether the exercise passes the query's attribute filters, the name isn't looked at.
+    pub fn filters_match(&self, exercise: &Exercise) -> bool {
+        self.equipment
+            .is_none_or(|equipment| exercise.equipment == equipment)
+            && self
+                .movement_pattern
+                .is_none_or(|pattern| exercise.movement_pattern == pattern)
+            && self.muscle.is_none_or(|muscle| {
+                exercise.primary_muscles.0.contains(&muscle)
+                    || exercise.secondary_muscles.0.contains(&muscle)
+            })
+            && self
+                .unilateral
+                .is_none_or(|unilateral| exercise.unilateral == unilateral)
+    }
+}
+
+/// Ranks exercises the way the database does: filtered, matched above `MIN_SCORE` unless the
+/// name is empty, recently used first, then by score and name.
+pub fn rank_exercises(
+    exercises: &[Exercise],
+    query: &SearchQuery,
+    recently_used: &HashSet<Uuid>,
+    limit: usize,
+) -> Vec<ExerciseSearchHit> {
+    let mut hits: Vec<ExerciseSearchHit> = exercises
+        .iter()
+        .filter(|exercise| query.filters_match(exercise))
+        .filter_map(|exercise| {
+            let score = match_score(exercise, &query.name);
+            if !normalize(&query.name).is_empty() && score < MIN_SCORE {
+                return None;
+            }
+            let recent = recently_used.contains(&exercise.exercise_id);
+            Some(ExerciseSearchHit {
+                exercise: exercise.clone(),
+                score: if recent { score + RECENT_BOOST } else { score },
+                recently_used: recent,
+            })
+        })
+        .collect();
+    hits.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.exercise.exercise_name.cmp(&b.exercise.exercise_name))
+    });
+    hits.truncate(limit);
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exercise(id: u128, name: &str, aliases: &[&str]) -> Exercise {
+        Exercise {
+            exercise_id: Uuid::from_u128(id),
+            exercise_name: name.to_string(),
+            aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn names(hits: &[ExerciseSearchHit]) -> Vec<&str> {
+        hits.iter()
+            .map(|hit| hit.exercise.exercise_name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn normalize_lowercases_and_drops_punctuation() {
+        assert_eq!(normalize("  Bench-Press (Barbell) "), "bench press barbell");
+        assert_eq!(normalize("T-Bar  Row"), "t bar row");
+        assert_eq!(normalize("--"), "");
+    }
+
+    #[test]
+    fn expand_abbreviations_spells_out_equipment() {
+        assert_eq!(expand_abbreviations("DB Row"), "dumbbell row");
+        assert_eq!(
+            expand_abbreviations("bb curl, kb swing"),
+            "barbell curl kettlebell swing"
+        );
+        // only whole words
+        assert_eq!(expand_abbreviations("dbl bbq"), "dbl bbq");
+    }
+
+    #[test]
+    fn similarity_follows_pg_trgm() {
+        assert_eq!(similarity("bench press", "Bench Press"), 1.0);
+        assert_eq!(similarity("bench press", "bench"), 0.5);
+        assert_eq!(similarity("", ""), 0.0);
+        assert_eq!(similarity("squat", "curl"), 0.0);
+    }
+
+    #[test]
+    fn text_score_boosts_prefixes_and_exact_matches() {
+        assert_eq!(text_score("Bench Press", ""), 0.0);
+        assert_eq!(
+            text_score("Bench Press", "bench"),
+            similarity("bench press", "bench") + PREFIX_BOOST
+        );
+        assert_eq!(
+            text_score("Bench Press", "bench press"),
+            1.0 + PREFIX_BOOST + EXACT_BOOST
+        );
+    }
+
+    #[test]
+    fn text_score_finds_names_typed_without_spaces() {
+        // "benchpress" and "benchpres" share 9 of 12 trigrams
+        assert_eq!(text_score("Bench Press", "benchpres"), 0.75);
+        assert!(text_score("Squat", "benchpres") < MIN_SCORE);
+    }
+
+    #[test]
+    fn match_score_expands_abbreviations_and_reads_aliases() {
+        let dumbbell_row = exercise(1, "Dumbbell Row", &[]);
+        let barbell_row = exercise(2, "Barbell Row", &[]);
+        assert_eq!(
+            match_score(&dumbbell_row, "db row"),
+            1.0 + PREFIX_BOOST + EXACT_BOOST
+        );
+        assert!(match_score(&dumbbell_row, "db row") > match_score(&barbell_row, "db row"));
+
+        let romanian_deadlift = exercise(3, "Romanian Deadlift", &["RDL"]);
+        assert_eq!(
+            match_score(&romanian_deadlift, "rdl"),
+            1.0 + PREFIX_BOOST + EXACT_BOOST
+        );
+    }
+
+    #[test]
+    fn rank_exercises_puts_recently_used_first() {
+        let exercises = [
+            exercise(1, "Bench Press", &[]),
+            exercise(2, "Incline Bench Press", &[]),
+            exercise(3, "Squat", &[]),
+        ];
+        let query = SearchQuery {
+            name: "bench press".to_string(),
+            ..Default::default()
+        };
+
+        let hits = rank_exercises(&exercises, &query, &HashSet::new(), 10);
+        assert_eq!(names(&hits), ["Bench Press", "Incline Bench Press"]);
+        assert!(hits.iter().all(|hit| !hit.recently_used));
+
+        let recent = HashSet::from([Uuid::from_u128(2)]);
+        let hits = rank_exercises(&exercises, &query, &recent, 10);
+        assert_eq!(names(&hits), ["Incline Bench Press", "Bench Press"]);
+        assert!(hits[0].recently_used);
+        assert!(hits[0].score > RECENT_BOOST);
+    }
+
+    #[test]
+    fn rank_exercises_without_a_name_lists_everything_recent_first() {
+        let exercises = [
+            exercise(1, "Bench Press", &[]),
+            exercise(2, "Deadlift", &[]),
+            exercise(3, "Squat", &[]),
+        ];
+        let recent = HashSet::from([Uuid::from_u128(3)]);
+
+        let hits = rank_exercises(&exercises, &SearchQuery::default(), &recent, 10);
+        assert_eq!(names(&hits), ["Squat", "Bench Press", "Deadlift"]);
+
+        let hits = rank_exercises(&exercises, &SearchQuery::default(), &recent, 2);
+        assert_eq!(names(&hits), ["Squat", "Bench Press"]);
+    }
+}
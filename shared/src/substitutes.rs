@@ -0,0 +1,253 @@
+//! Substitute suggestions, for when the equipment an exercise needs is taken. Exercises are
+//! compared on their catalogue attributes only, so any list of exercises can be ranked.
+
+use std::collections::HashSet;
+
+use crate::models::{
+    Equipment, Exercise, MovementPattern, Muscle, Muscles, Substitute, SubstituteQuery,
+};
+
+/// Weight of a shared movement pattern, the strongest sign of a comparable exercise.
+pub const PATTERN_WEIGHT: f32 = 0.5;
+/// Weight of the overlap in primary muscles.
+pub const PRIMARY_WEIGHT: f32 = 0.35;
+/// Weight of the overlap in all the muscles worked, primary and secondary.
+pub const ALL_MUSCLES_WEIGHT: f32 = 0.1;
+/// Added when both are done one side at a time, or both are not.
+pub const LATERALITY_WEIGHT: f32 = 0.05;
+
+impl SubstituteQuery {
+    /// The equipment to pick substitutes from, empty for every kind.
+    pub fn available_equipment(&self) -> Result<Vec<Equipment>, String> {
+        self.equipment
+            .iter()
+            .flat_map(|equipment| equipment.split(','))
+            .map(str::trim)
+            .filter(|equipment| !equipment.is_empty())
+            .map(|equipment| Equipment::try_from(equipment.to_string()))
+            .collect()
+    }
+}
+
+// Shared over all, 0 when neither has any
+fn overlap(a: &HashSet<Muscle>, b: &HashSet<Muscle>) -> f32 {
+    let all = a.union(b).count();
+    if all == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f32 / all as f32
+}
+
+fn all_muscles(exercise: &Exercise) -> HashSet<Muscle> {
+    exercise
+        .primary_muscles
+        .0
+        .iter()
+        .chain(&exercise.secondary_muscles.0)
+        .copied()
+        .collect()
+}
+
+/// How well `candidate` stands in for `exercise`, between 0 and 1. None when they share neither
+/// a movement pattern nor a primary muscle, nothing ties them together then. `Other` patterns
+/// don't count as shared.
+pub fn substitute(exercise: &Exercise, candidate: &Exercise) -> Option<Substitute> {
+    let same_pattern = exercise.movement_pattern != MovementPattern::Other
+        && exercise.movement_pattern == candidate.movement_pattern;
+    let primary: HashSet<Muscle> = exercise.primary_muscles.0.iter().copied().collect();
+    let candidate_primary: HashSet<Muscle> = candidate.primary_muscles.0.iter().copied().collect();
+    let mut shared_muscles: Vec<Muscle> =
+        primary.intersection(&candidate_primary).copied().collect();
+    if !same_pattern && shared_muscles.is_empty() {
+        return None;
+    }
+    shared_muscles.sort();
+
+    let mut score = PRIMARY_WEIGHT * overlap(&primary, &candidate_primary)
+        + ALL_MUSCLES_WEIGHT * overlap(&all_muscles(exercise), &all_muscles(candidate));
+    if same_pattern {
+        score += PATTERN_WEIGHT;
+    }
+    if exercise.unilateral == candidate.unilateral {
+        score += LATERALITY_WEIGHT;
+    }
+    Some(Substitute {
+        exercise: candidate.clone(),
+        score,
+        same_pattern,
+        shared_muscles: Muscles(shared_muscles),
+    })
+}
+
+/// Ranks `candidates` as substitutes for `exercise`, best first and then by name. Only the
+/// `available` equipment is considered, every kind when it's empty. The exercise itself is left
+/// out.
+pub fn rank_substitutes(
+    exercise: &Exercise,
+    candidates: &[Exercise],
+    available: &[Equipment],
+    limit: usize,
+) -> Vec<Substitute> {
+    let mut substitutes: Vec<Substitute> = candidates
+        .iter()
+        .filter(|candidate| candidate.exercise_id != exercise.exercise_id)
+        .filter(|candidate| available.is_empty() || available.contains(&candidate.equipment))
+        .filter_map(|candidate| substitute(exercise, candidate))
+        .collect();
+    substitutes.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.exercise.exercise_name.cmp(&b.exercise.exercise_name))
+    });
+    substitutes.truncate(limit);
+    substitutes
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn exercise(
+        id: u128,
+        name: &str,
+        movement_pattern: MovementPattern,
+        equipment: Equipment,
+        primary: &[Muscle],
+    ) -> Exercise {
+        Exercise {
+            exercise_id: Uuid::from_u128(id),
+            exercise_name: name.to_string(),
+            movement_pattern,
+            equipment,
+            primary_muscles: Muscles(primary.to_vec()),
+            ..Default::default()
+        }
+    }
+
+    fn bench_press() -> Exercise {
+        exercise(
+            1,
+            "Bench Press",
+            MovementPattern::HorizontalPush,
+            Equipment::Barbell,
+            &[Muscle::Chest, Muscle::Triceps],
+        )
+    }
+
+    fn names(substitutes: &[Substitute]) -> Vec<&str> {
+        substitutes
+            .iter()
+            .map(|substitute| substitute.exercise.exercise_name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn shared_pattern_outranks_shared_muscles_only() {
+        let same_pattern = exercise(
+            2,
+            "Push Up",
+            MovementPattern::HorizontalPush,
+            Equipment::Bodyweight,
+            &[Muscle::Chest],
+        );
+        let same_muscles = exercise(
+            3,
+            "Dip",
+            MovementPattern::VerticalPush,
+            Equipment::Bodyweight,
+            &[Muscle::Chest, Muscle::Triceps],
+        );
+
+        let ranked = rank_substitutes(&bench_press(), &[same_muscles, same_pattern], &[], 10);
+
+        assert_eq!(names(&ranked), vec!["Push Up", "Dip"]);
+        assert!(ranked[0].same_pattern);
+        assert_eq!(ranked[0].shared_muscles, Muscles(vec![Muscle::Chest]));
+        assert!(!ranked[1].same_pattern);
+        assert_eq!(
+            ranked[1].shared_muscles,
+            Muscles(vec![Muscle::Chest, Muscle::Triceps])
+        );
+    }
+
+    #[test]
+    fn nothing_in_common_is_no_substitute() {
+        let squat = exercise(
+            2,
+            "Squat",
+            MovementPattern::Squat,
+            Equipment::Barbell,
+            &[Muscle::Glutes],
+        );
+        assert!(substitute(&bench_press(), &squat).is_none());
+    }
+
+    #[test]
+    fn other_patterns_are_not_shared() {
+        let exercise_a = exercise(1, "A", MovementPattern::Other, Equipment::Other, &[]);
+        let exercise_b = exercise(2, "B", MovementPattern::Other, Equipment::Other, &[]);
+        assert!(substitute(&exercise_a, &exercise_b).is_none());
+    }
+
+    #[test]
+    fn only_available_equipment_is_ranked() {
+        let candidates = [
+            exercise(
+                2,
+                "Dumbbell Press",
+                MovementPattern::HorizontalPush,
+                Equipment::Dumbbell,
+                &[Muscle::Chest],
+            ),
+            exercise(
+                3,
+                "Machine Press",
+                MovementPattern::HorizontalPush,
+                Equipment::Machine,
+                &[Muscle::Chest],
+            ),
+        ];
+
+        let ranked = rank_substitutes(&bench_press(), &candidates, &[Equipment::Dumbbell], 10);
+        assert_eq!(names(&ranked), vec!["Dumbbell Press"]);
+
+        let ranked = rank_substitutes(&bench_press(), &candidates, &[], 10);
+        assert_eq!(names(&ranked), vec!["Dumbbell Press", "Machine Press"]);
+    }
+
+    #[test]
+    fn the_exercise_itself_is_left_out() {
+        let ranked = rank_substitutes(&bench_press(), &[bench_press()], &[], 10);
+        assert!(ranked.is_empty());
+    }
+
+    #[test]
+    fn equal_scores_are_ordered_by_name_then_limited() {
+        let candidate = |id, name| {
+            exercise(
+                id,
+                name,
+                MovementPattern::HorizontalPush,
+                Equipment::Dumbbell,
+                &[Muscle::Chest, Muscle::Triceps],
+            )
+        };
+        let candidates = [
+            candidate(2, "Incline Press"),
+            candidate(3, "Floor Press"),
+            candidate(4, "Close Grip Press"),
+        ];
+
+        let ranked = rank_substitutes(&bench_press(), &candidates, &[], 10);
+        assert_eq!(
+            names(&ranked),
+            vec!["Close Grip Press", "Floor Press", "Incline Press"]
+        );
+        assert_eq!(ranked[0].score, ranked[2].score);
+
+        let ranked = rank_substitutes(&bench_press(), &candidates, &[], 2);
+        assert_eq!(names(&ranked), vec!["Close Grip Press", "Floor Press"]);
+    }
+}
@@ -1 +1,342 @@
+use rust_decimal::{Decimal, RoundingStrategy};
 
+use crate::models::{
+    BodyMeasurement, BodyTrendPoint, CreateBodyMeasurement, HistoryExportRow, Metric,
+    PatchBodyMeasurement, PatchSetPerformance, PersonalRecord, SessionEvent, SessionPerformance,
+    SessionWithExercisePerformance, SetPerformance, SetPerformancePayload, TrackedMetrics,
+    WeightUnit,
+};
+
+/// Decimal places a weight is stored with, the scale of the NUMERIC weight columns.
+pub const WEIGHT_SCALE: u32 = 3;
+
+/// Rounds half away from zero to the stored scale, the way Postgres' `ROUND` does.
+pub fn round_weight(weight: Decimal) -> Decimal {
+    weight.round_dp_with_strategy(WEIGHT_SCALE, RoundingStrategy::MidpointAwayFromZero)
+}
+
+/// Estimated one-rep max using the Epley formula. A single rep is its own max.
+pub fn estimated_one_rep_max(weight: Decimal, reps: i16) -> Decimal {
+    if reps <= 1 {
+        return weight;
+    }
+    // weight * (1 + reps / 30), ordered so the only inexact step is the final division
+    round_weight(weight * Decimal::from(30 + reps) / Decimal::from(30))
+}
+
+/// One pound in kilograms, exactly, by definition.
+pub const KG_PER_LB: Decimal = Decimal::from_parts(45_359_237, 0, 0, false, 8);
+
+impl WeightUnit {
+    /// The finest step fractional plates allow on a bar, a pair of 0.125 kg or 0.25 lb plates.
+    pub fn increment(&self) -> Decimal {
+        match self {
+            WeightUnit::Kg => Decimal::new(25, 2),
+            WeightUnit::Lb => Decimal::new(5, 1),
+        }
+    }
+}
+
+/// Converts a weight between units, without rounding.
+pub fn convert_weight(weight: Decimal, from: WeightUnit, to: WeightUnit) -> Decimal {
+    match (from, to) {
+        (WeightUnit::Kg, WeightUnit::Lb) => weight / KG_PER_LB,
+        (WeightUnit::Lb, WeightUnit::Kg) => weight * KG_PER_LB,
+        _ => weight,
+    }
+}
+
+/// Rounds to the nearest load that can be put on a bar in `unit`.
+pub fn round_to_increment(weight: Decimal, unit: WeightUnit) -> Decimal {
+    let increment = unit.increment();
+    ((weight / increment).round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero)
+        * increment)
+        .normalize()
+}
+
+/// A weight as stored, in kilograms. Input is only rounded to the stored scale, reading it back
+/// goes through `display_weight` and so lands on the unit's plate increment, e.g. 61.3 kg
+/// reads back as 61.25 kg.
+pub fn to_storage_weight(weight: Decimal, unit: WeightUnit) -> Decimal {
+    round_weight(convert_weight(weight, unit, WeightUnit::Kg))
+}
+
+/// A stored weight as a lifter using `unit` loads it, rounded to `unit.increment()` even when
+/// it was logged in that unit.
+pub fn display_weight(weight_kg: Decimal, unit: WeightUnit) -> Decimal {
+    round_to_increment(convert_weight(weight_kg, WeightUnit::Kg, unit), unit)
+}
+
+/// A stored weight that isn't put on a bar, like bodyweight or an estimate, in `unit`.
+pub fn display_measured_weight(weight_kg: Decimal, unit: WeightUnit) -> Decimal {
+    round_weight(convert_weight(weight_kg, WeightUnit::Kg, unit))
+}
+
+impl SetPerformancePayload {
+    /// The set with its weight in kilograms, read in its own unit or else `preferred`.
+    pub fn into_storage_unit(mut self, preferred: WeightUnit) -> Self {
+        self.weight = to_storage_weight(self.weight, self.unit.unwrap_or(preferred));
+        self.unit = Some(WeightUnit::Kg);
+        self
+    }
+}
+
+impl PatchSetPerformance {
+    /// The patch with its weight in kilograms, read in its own unit or else `preferred`.
+    pub fn into_storage_unit(mut self, preferred: WeightUnit) -> Self {
+        let unit = self.unit.unwrap_or(preferred);
+        self.weight = self.weight.map(|weight| to_storage_weight(weight, unit));
+        self.unit = Some(WeightUnit::Kg);
+        self
+    }
+}
+
+impl CreateBodyMeasurement {
+    /// The measurement with its bodyweight in kilograms, read in its own unit or else `preferred`.
+    pub fn into_storage_unit(mut self, preferred: WeightUnit) -> Self {
+        let unit = self.unit.unwrap_or(preferred);
+        self.bodyweight = self
+            .bodyweight
+            .map(|weight| to_storage_weight(weight, unit));
+        self.unit = Some(WeightUnit::Kg);
+        self
+    }
+}
+
+impl PatchBodyMeasurement {
+    /// The patch with its bodyweight in kilograms, read in its own unit or else `preferred`.
+    pub fn into_storage_unit(mut self, preferred: WeightUnit) -> Self {
+        let unit = self.unit.unwrap_or(preferred);
+        self.bodyweight = self
+            .bodyweight
+            .map(|weight| to_storage_weight(weight, unit));
+        self.unit = Some(WeightUnit::Kg);
+        self
+    }
+}
+
+/// Fills in each point's moving average, the mean of the points logged in the `window_days`
+/// days ending on it. Days without a measurement don't count, so gaps don't drag it down.
+/// Points have to be oldest first.
+pub fn with_moving_average(
+    mut points: Vec<BodyTrendPoint>,
+    window_days: i64,
+) -> Vec<BodyTrendPoint> {
+    let mut start = 0;
+    let mut sum = Decimal::ZERO;
+    for end in 0..points.len() {
+        sum += points[end].value;
+        let first_day = points[end].measured_on - chrono::Duration::days(window_days - 1);
+        while points[start].measured_on < first_day {
+            sum -= points[start].value;
+            start += 1;
+        }
+        let count = Decimal::from(end - start + 1);
+        points[end].moving_average = round_weight(sum / count);
+    }
+    points
+}
+
+/// Average heart rates outside this range are a misread strap, not a set.
+pub const HEART_RATE_RANGE: std::ops::RangeInclusive<i16> = 20..=250;
+
+impl TrackedMetrics {
+    /// Checks a logged set against the metrics, every tracked one filled in and no others.
+    /// Reps and weight default to zero, so for them only a value that isn't tracked is refused.
+    pub fn check_set(&self, set: &SetPerformancePayload) -> Result<(), String> {
+        self.check_zero(Metric::Reps, set.reps == 0)?;
+        self.check_zero(Metric::Weight, set.weight.is_zero())?;
+        self.check_given(Metric::Duration, set.duration_seconds.is_some())?;
+        self.check_given(Metric::Distance, set.distance_meters.is_some())?;
+        self.check_given(Metric::Calories, set.calories.is_some())?;
+        self.check_given(Metric::HeartRate, set.heart_rate.is_some())?;
+        check_ranges(
+            set.reps,
+            set.weight,
+            set.duration_seconds,
+            set.distance_meters,
+            set.calories,
+            set.heart_rate,
+        )
+    }
+
+    /// Checks a patch against the metrics, it may only change the tracked ones.
+    pub fn check_patch(&self, patch: &PatchSetPerformance) -> Result<(), String> {
+        let changes = [
+            (Metric::Reps, patch.reps.is_some_and(|reps| reps != 0)),
+            (
+                Metric::Weight,
+                patch.weight.is_some_and(|weight| !weight.is_zero()),
+            ),
+            (Metric::Duration, patch.duration_seconds.is_some()),
+            (Metric::Distance, patch.distance_meters.is_some()),
+            (Metric::Calories, patch.calories.is_some()),
+            (Metric::HeartRate, patch.heart_rate.is_some()),
+        ];
+        for (metric, changed) in changes {
+            self.check_zero(metric, !changed)?;
+        }
+        check_ranges(
+            patch.reps.unwrap_or_default(),
+            patch.weight.unwrap_or_default(),
+            patch.duration_seconds,
+            patch.distance_meters,
+            patch.calories,
+            patch.heart_rate,
+        )
+    }
+
+    fn check_zero(&self, metric: Metric, is_zero: bool) -> Result<(), String> {
+        if !is_zero && !self.tracks(metric) {
+            return Err(format!("The exercise doesn't track {}", metric.as_str()));
+        }
+        Ok(())
+    }
+
+    fn check_given(&self, metric: Metric, given: bool) -> Result<(), String> {
+        match (self.tracks(metric), given) {
+            (true, false) => Err(format!(
+                "The exercise tracks {}, it is required",
+                metric.as_str()
+            )),
+            (false, true) => Err(format!("The exercise doesn't track {}", metric.as_str())),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn check_ranges(
+    reps: i16,
+    weight: Decimal,
+    duration_seconds: Option<i32>,
+    distance_meters: Option<Decimal>,
+    calories: Option<i32>,
+    heart_rate: Option<i16>,
+) -> Result<(), String> {
+    if reps < 0 {
+        return Err("Reps can't be negative".to_string());
+    }
+    if weight < Decimal::ZERO {
+        return Err("Weight can't be negative".to_string());
+    }
+    if duration_seconds.is_some_and(|seconds| seconds <= 0) {
+        return Err("Duration must be positive".to_string());
+    }
+    if distance_meters.is_some_and(|meters| meters <= Decimal::ZERO) {
+        return Err("Distance must be positive".to_string());
+    }
+    if calories.is_some_and(|calories| calories < 0) {
+        return Err("Calories can't be negative".to_string());
+    }
+    if heart_rate.is_some_and(|bpm| !HEART_RATE_RANGE.contains(&bpm)) {
+        return Err(format!(
+            "Heart rate must be between {} and {}",
+            HEART_RATE_RANGE.start(),
+            HEART_RATE_RANGE.end()
+        ));
+    }
+    Ok(())
+}
+
+/// Responses are built in kilograms and converted for the reader last.
+pub trait InWeightUnit {
+    fn in_unit(self, unit: WeightUnit) -> Self;
+}
+
+// The effective load can include bodyweight, it is converted but not rounded to plates
+impl InWeightUnit for SetPerformance {
+    fn in_unit(mut self, unit: WeightUnit) -> Self {
+        self.weight = display_weight(self.weight, unit);
+        self.effective_load = display_measured_weight(self.effective_load, unit);
+        self
+    }
+}
+
+impl InWeightUnit for SessionPerformance {
+    fn in_unit(mut self, unit: WeightUnit) -> Self {
+        self.sets = self.sets.in_unit(unit);
+        self
+    }
+}
+
+impl InWeightUnit for SessionWithExercisePerformance {
+    fn in_unit(mut self, unit: WeightUnit) -> Self {
+        self.performance = self.performance.in_unit(unit);
+        self
+    }
+}
+
+// A record's weight is the effective load and its estimate isn't a load, neither is rounded
+// to plates
+impl InWeightUnit for PersonalRecord {
+    fn in_unit(mut self, unit: WeightUnit) -> Self {
+        self.weight = display_measured_weight(self.weight, unit);
+        self.estimated_one_rep_max = display_measured_weight(self.estimated_one_rep_max, unit);
+        self
+    }
+}
+
+impl InWeightUnit for HistoryExportRow {
+    fn in_unit(mut self, unit: WeightUnit) -> Self {
+        self.weight = display_weight(self.weight, unit);
+        self.effective_load = display_measured_weight(self.effective_load, unit);
+        self.estimated_one_rep_max = display_measured_weight(self.estimated_one_rep_max, unit);
+        self
+    }
+}
+
+// Only a bodyweight series is converted, see `BodyMetric`
+impl InWeightUnit for BodyTrendPoint {
+    fn in_unit(mut self, unit: WeightUnit) -> Self {
+        self.value = display_measured_weight(self.value, unit);
+        self.moving_average = display_measured_weight(self.moving_average, unit);
+        self
+    }
+}
+
+impl InWeightUnit for BodyMeasurement {
+    fn in_unit(mut self, unit: WeightUnit) -> Self {
+        self.bodyweight = self
+            .bodyweight
+            .map(|bodyweight| display_measured_weight(bodyweight, unit));
+        self
+    }
+}
+
+impl InWeightUnit for SessionEvent {
+    fn in_unit(self, unit: WeightUnit) -> Self {
+        match self {
+            SessionEvent::SetAdded {
+                session_id,
+                exercise_id,
+                set,
+            } => SessionEvent::SetAdded {
+                session_id,
+                exercise_id,
+                set: set.in_unit(unit),
+            },
+            SessionEvent::SetUpdated {
+                session_id,
+                exercise_id,
+                set,
+            } => SessionEvent::SetUpdated {
+                session_id,
+                exercise_id,
+                set: set.in_unit(unit),
+            },
+            event => event,
+        }
+    }
+}
+
+impl<T: InWeightUnit> InWeightUnit for Option<T> {
+    fn in_unit(self, unit: WeightUnit) -> Self {
+        self.map(|value| value.in_unit(unit))
+    }
+}
+
+impl<T: InWeightUnit> InWeightUnit for Vec<T> {
+    fn in_unit(self, unit: WeightUnit) -> Self {
+        self.into_iter().map(|value| value.in_unit(unit)).collect()
+    }
+}